@@ -0,0 +1,173 @@
+// Human-readable output for the show-* commands
+use cfx_stake_client::cfx_stake_core::{
+    Checkpoint, CheckpointRing, LockClock, MultisigConfig, MultisigProposal, ProposalStatus, ProposalType, StakePool,
+    TierTable, UserStake,
+};
+use cfx_stake_client::ProposalPayload;
+use solana_sdk::clock::Clock;
+use solana_sdk::pubkey::Pubkey;
+
+// Base units rendered as whole tokens
+fn tokens(amount: u64, decimals: u8) -> String {
+    if decimals == 0 {
+        return amount.to_string();
+    }
+    let scale = 10u64.pow(decimals as u32);
+    format!("{}.{:0width$}", amount / scale, amount % scale, width = decimals as usize)
+}
+
+fn yes_no(flag: bool) -> &'static str {
+    if flag { "yes" } else { "no" }
+}
+
+fn clock_name(lock_clock: LockClock) -> &'static str {
+    match lock_clock {
+        LockClock::Slot => "slot",
+        LockClock::UnixTimestamp => "unix timestamp",
+    }
+}
+
+fn proposal_type_name(proposal_type: ProposalType) -> &'static str {
+    match proposal_type {
+        ProposalType::TogglePause => "TogglePause",
+        ProposalType::UpdateAuthority => "UpdateAuthority",
+        ProposalType::UpdateTeamWallet => "UpdateTeamWallet",
+        ProposalType::AdminWithdraw => "AdminWithdraw",
+        ProposalType::SetTransferConsent => "SetTransferConsent",
+        ProposalType::SetOutflowCap => "SetOutflowCap",
+        ProposalType::SetCircuitBreaker => "SetCircuitBreaker",
+        ProposalType::ResetCircuitBreaker => "ResetCircuitBreaker",
+        ProposalType::RescueTokens => "RescueTokens",
+        ProposalType::SetTierTable => "SetTierTable",
+    }
+}
+
+fn status_name(status: ProposalStatus) -> &'static str {
+    match status {
+        ProposalStatus::Pending => "pending",
+        ProposalStatus::Approved => "approved",
+        ProposalStatus::Executed => "executed",
+        ProposalStatus::Rejected => "rejected",
+    }
+}
+
+pub fn print_pool(address: &Pubkey, pool: &StakePool) {
+    let decimals = pool.decimals;
+    println!("Stake pool:           {address}");
+    println!("Pool id:              {}", pool.pool_id);
+    println!("Version:              {}", pool.version);
+    println!("Authority:            {}", pool.authority);
+    println!("Token mint:           {} ({decimals} decimals)", pool.token_mint);
+    println!("Token vault:          {}", pool.token_vault);
+    println!("Token program:        {}", pool.token_program);
+    println!("Total staked:         {}", tokens(pool.total_staked, decimals));
+    println!("Vault surplus:        {}", tokens(pool.surplus, decimals));
+    println!("Lock clock:           {}", clock_name(pool.lock_clock()));
+    println!("Lock duration:        {} slots / {} seconds", pool.lock_duration_slots, pool.lock_duration_seconds);
+    println!("Emergency mode:       {}", yes_no(pool.emergency_mode != 0));
+    println!("Transfer consent:     {}", yes_no(pool.require_transfer_consent != 0));
+    if pool.outflow_cap == 0 {
+        println!("Outflow cap:          unlimited");
+    } else {
+        println!("Outflow cap:          {} per epoch", tokens(pool.outflow_cap, decimals));
+    }
+    if pool.breaker_threshold_bps == 0 {
+        println!("Circuit breaker:      disabled");
+    } else {
+        println!(
+            "Circuit breaker:      {} bps over {} slots{}",
+            pool.breaker_threshold_bps,
+            pool.breaker_window_slots,
+            if pool.breaker_tripped != 0 { " (TRIPPED)" } else { "" }
+        );
+    }
+    let limits = &pool.limits;
+    println!("Stake limits:         {} - {} tokens", limits.min_stake, limits.max_individual_stake);
+    println!("Unstake limits:       {} - {} tokens", limits.min_unstake, limits.max_individual_unstake);
+    println!("Max pool size:        {} tokens", limits.max_pool_size);
+}
+
+pub fn print_tier_table(tier_table: &TierTable) {
+    if tier_table.tiers.is_empty() {
+        println!("Tiers:                none");
+        return;
+    }
+    println!("Tiers:");
+    for (index, tier) in tier_table.tiers.iter().enumerate() {
+        println!("  {}: {} tokens, lock >= {}", index + 1, tier.min_stake, tier.min_lock);
+    }
+}
+
+pub fn print_checkpoints(ring: &CheckpointRing, entries: &[Checkpoint]) {
+    let Some(first) = ring.ordered(entries).next() else {
+        println!("Checkpoints:          none");
+        return;
+    };
+    let partial = if ring.complete == 0 { ", earlier history unknown" } else { "" };
+    println!("Checkpoints:          {} since slot {}{partial}", ring.len, first.slot);
+}
+
+pub fn print_position(
+    address: &Pubkey,
+    user_stake: &UserStake,
+    pool: &StakePool,
+    tier_table: Option<&TierTable>,
+    clock: &Clock,
+) {
+    let decimals = pool.decimals;
+    println!("Position:             {address}");
+    println!("Owner:                {}", user_stake.owner);
+    println!("Stake pool:           {}", user_stake.stake_pool);
+    println!("Staked:               {}", tokens(user_stake.staked_amount, decimals));
+    println!("Withdrawable:         {}", tokens(user_stake.withdrawable_amount(pool, clock), decimals));
+    println!("Voting power:         {}", tokens(user_stake.voting_power(pool, clock), decimals));
+    println!("Withdrawal requested: {}", yes_no(user_stake.withdrawal_requested));
+    println!("Unlocked:             {}", yes_no(pool.is_unlocked(user_stake, clock)));
+    println!("Unlock slot:          {}", user_stake.unlock_slot);
+    println!("Unlock timestamp:     {}", user_stake.unlock_timestamp);
+    if user_stake.lock_end != 0 {
+        println!("Lock end:             {}", user_stake.lock_end);
+    }
+    if user_stake.tier_verified_slot != 0 {
+        let tier = tier_table.map_or(0, |tier_table| user_stake.cached_tier(tier_table, pool, clock));
+        if tier == user_stake.tier {
+            println!("Tier:                 {} (verified at slot {})", tier, user_stake.tier_verified_slot);
+        } else {
+            println!(
+                "Tier:                 {} (tier {} verified at slot {} has lapsed)",
+                tier, user_stake.tier, user_stake.tier_verified_slot
+            );
+        }
+    }
+    if user_stake.has_vesting() {
+        let vesting = &user_stake.vesting;
+        println!(
+            "Vesting:              {} from {} (cliff {}) to {}",
+            tokens(vesting.total, decimals),
+            vesting.start,
+            vesting.cliff,
+            vesting.end
+        );
+    }
+}
+
+pub fn print_proposal(address: &Pubkey, proposal: &MultisigProposal, multisig: &MultisigConfig, current_slot: u64) {
+    println!("Proposal:             {address}");
+    println!("Id:                   {}", proposal.id);
+    println!("Type:                 {}", proposal_type_name(proposal.proposal_type));
+    match ProposalPayload::decode(proposal.proposal_type, &proposal.data) {
+        Ok(payload) => println!("Payload:              {payload:?}"),
+        Err(_) => println!("Payload:              invalid ({} bytes)", proposal.data.len()),
+    }
+    let expired = if proposal.is_expired(current_slot) { " (expired)" } else { "" };
+    println!("Status:               {}{expired}", status_name(proposal.status));
+    println!("Proposer:             {}", proposal.proposer);
+    println!("Created at slot:      {}", proposal.created_at);
+    if let Some(executed_at) = proposal.executed_at {
+        println!("Executed at slot:     {executed_at}");
+    }
+    println!("Signatures:           {}/{}", proposal.signature_count, multisig.threshold);
+    for (signer, signed) in multisig.signers.iter().zip(proposal.signatures) {
+        println!("  {signer} {}", if signed { "signed" } else { "-" });
+    }
+}
@@ -0,0 +1,516 @@
+// Admin CLI for stake pools and their multisig
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Context, Result};
+use base64::Engine;
+use cfx_stake_client::cfx_stake_core::token_interface::associated_token_address;
+use cfx_stake_client::cfx_stake_core::{LockClock, StakeTier};
+use cfx_stake_client::instructions::{self, InitializeArgs, RescueAccounts};
+use cfx_stake_client::{accounts, pda, PoolAccounts, ProposalPayload};
+use clap::{ArgAction, Parser, Subcommand, ValueEnum};
+use solana_rpc_client::rpc_client::RpcClient;
+use solana_sdk::clock::Clock;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::hash::Hash;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::message::Message;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{read_keypair_file, Keypair, Signer};
+use solana_sdk::sysvar;
+use solana_sdk::transaction::Transaction;
+
+mod display;
+
+#[derive(Parser)]
+#[command(name = "cfx-stake", version, about = "Admin CLI for Chain-Fox DAO stake pools")]
+struct Cli {
+    /// RPC endpoint
+    #[arg(long, short = 'u', global = true, default_value = "http://127.0.0.1:8899")]
+    url: String,
+
+    /// Keypair that signs and pays for transactions [default: ~/.config/solana/id.json]
+    #[arg(long, short = 'k', global = true)]
+    keypair: Option<PathBuf>,
+
+    /// Print unsigned transactions for an offline signer instead of sending them
+    #[arg(long, global = true, requires = "signer")]
+    offline: bool,
+
+    /// Public key of the offline signer, which also pays the fees
+    #[arg(long, global = true, requires = "offline")]
+    signer: Option<Pubkey>,
+
+    /// Blockhash for offline transactions (fetched from the RPC if omitted)
+    #[arg(long, global = true, requires = "offline")]
+    blockhash: Option<Hash>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Create a stake pool, and the mint's pool registry if it does not exist yet
+    InitPool {
+        #[arg(long)]
+        mint: Pubkey,
+        #[arg(long, default_value_t = 0)]
+        pool_id: u64,
+        /// Token program of the mint [default: owner of the mint account]
+        #[arg(long)]
+        token_program: Option<Pubkey>,
+        /// Also create the mint's pool registry [default: when it does not exist yet]
+        #[arg(long, action = ArgAction::Set)]
+        init_registry: Option<bool>,
+        #[arg(long, value_enum)]
+        lock_clock: Option<ClockArg>,
+        #[arg(long)]
+        lock_duration_slots: Option<u64>,
+        /// Only used by pools on the unix timestamp clock
+        #[arg(long)]
+        lock_duration_seconds: Option<u64>,
+    },
+    /// Set up the multisig that governs a pool
+    InitMultisig {
+        #[arg(long)]
+        pool: Pubkey,
+        /// The three signers, comma separated
+        #[arg(long, value_delimiter = ',', required = true)]
+        signers: Vec<Pubkey>,
+        #[arg(long)]
+        threshold: u8,
+    },
+    /// Create a multisig proposal
+    Propose {
+        #[arg(long)]
+        pool: Pubkey,
+        /// Id of the new proposal [default: the multisig's proposal count]
+        #[arg(long)]
+        proposal_id: Option<u64>,
+        #[command(subcommand)]
+        proposal: ProposalCommand,
+    },
+    /// Sign a pending proposal
+    Sign {
+        #[arg(long)]
+        pool: Pubkey,
+        #[arg(long)]
+        proposal_id: u64,
+    },
+    /// Execute an approved proposal
+    Execute {
+        #[arg(long)]
+        pool: Pubkey,
+        #[arg(long)]
+        proposal_id: u64,
+        /// Token account receiving AdminWithdraw or RescueTokens funds
+        /// [default: the recipient's associated token account]
+        #[arg(long)]
+        recipient_token_account: Option<Pubkey>,
+        /// Pool mint for AdminWithdraw, or mint of the rescued tokens for RescueTokens
+        /// [default: read from the pool or the source account]
+        #[arg(long)]
+        mint: Option<Pubkey>,
+        /// Token program of `--mint` [default: read from the pool or the source account]
+        #[arg(long)]
+        token_program: Option<Pubkey>,
+        /// Pool token vault for AdminWithdraw [default: read from the pool]
+        #[arg(long)]
+        token_vault: Option<Pubkey>,
+        /// Create the pool's tier table first, for SetTierTable [default: when it does not exist yet]
+        #[arg(long, action = ArgAction::Set)]
+        init_tier_table: Option<bool>,
+        /// The proposal as it was proposed [default: read from the proposal account]
+        #[command(subcommand)]
+        proposal: Option<ProposalCommand>,
+    },
+    /// Start balance checkpoints for a pool, or one of its positions, created before checkpoints existed
+    InitCheckpoints {
+        #[arg(long)]
+        pool: Pubkey,
+        /// Owner of the position [default: the pool-wide checkpoints]
+        #[arg(long)]
+        owner: Option<Pubkey>,
+    },
+    /// Show the state of a stake pool
+    ShowPool {
+        #[arg(long)]
+        pool: Pubkey,
+    },
+    /// Show a stake position
+    ShowPosition {
+        #[arg(long)]
+        pool: Pubkey,
+        #[arg(long)]
+        owner: Pubkey,
+    },
+    /// Show a multisig proposal
+    ShowProposal {
+        #[arg(long)]
+        pool: Pubkey,
+        #[arg(long)]
+        proposal_id: u64,
+    },
+}
+
+#[derive(Subcommand)]
+enum ProposalCommand {
+    /// Toggle emergency mode
+    TogglePause,
+    /// Hand the pool authority to a new key
+    UpdateAuthority {
+        #[arg(long)]
+        new_authority: Pubkey,
+    },
+    /// Withdraw vault surplus to a recipient wallet
+    AdminWithdraw {
+        /// Amount in base units
+        #[arg(long)]
+        amount: u64,
+        #[arg(long)]
+        recipient: Pubkey,
+    },
+    /// Require recipient consent for position transfers
+    SetTransferConsent {
+        #[arg(long, action = ArgAction::Set)]
+        required: bool,
+    },
+    /// Set the per-epoch withdrawal outflow cap
+    SetOutflowCap {
+        /// Base units per epoch, 0 for unlimited
+        #[arg(long)]
+        cap: u64,
+    },
+    /// Configure the vault outflow circuit breaker
+    SetCircuitBreaker {
+        /// Share of the vault, in basis points, 0 to disable
+        #[arg(long)]
+        threshold_bps: u16,
+        #[arg(long)]
+        window_slots: u64,
+    },
+    /// Resume the pool after the circuit breaker tripped
+    ResetCircuitBreaker,
+    /// Recover foreign tokens held by the pool
+    RescueTokens {
+        /// Amount in base units
+        #[arg(long)]
+        amount: u64,
+        #[arg(long)]
+        recipient: Pubkey,
+        #[arg(long)]
+        source_token_account: Pubkey,
+    },
+    /// Replace the pool's service tier thresholds
+    SetTierTable {
+        /// `MIN_STAKE:MIN_LOCK` per tier, lowest first; whole tokens and pool lock clock units
+        #[arg(long = "tier", value_parser = parse_tier)]
+        tiers: Vec<StakeTier>,
+    },
+}
+
+fn parse_tier(value: &str) -> std::result::Result<StakeTier, String> {
+    let (min_stake, min_lock) = value.split_once(':').ok_or("expected MIN_STAKE:MIN_LOCK")?;
+    Ok(StakeTier {
+        min_stake: min_stake.parse().map_err(|err| format!("invalid minimum stake: {err}"))?,
+        min_lock: min_lock.parse().map_err(|err| format!("invalid minimum lock: {err}"))?,
+    })
+}
+
+impl From<ProposalCommand> for ProposalPayload {
+    fn from(command: ProposalCommand) -> Self {
+        match command {
+            ProposalCommand::TogglePause => Self::TogglePause,
+            ProposalCommand::UpdateAuthority { new_authority } => Self::UpdateAuthority { new_authority },
+            ProposalCommand::AdminWithdraw { amount, recipient } => Self::AdminWithdraw { amount, recipient },
+            ProposalCommand::SetTransferConsent { required } => Self::SetTransferConsent { required },
+            ProposalCommand::SetOutflowCap { cap } => Self::SetOutflowCap { cap },
+            ProposalCommand::SetCircuitBreaker { threshold_bps, window_slots } => {
+                Self::SetCircuitBreaker { threshold_bps, window_slots }
+            }
+            ProposalCommand::ResetCircuitBreaker => Self::ResetCircuitBreaker,
+            ProposalCommand::RescueTokens { amount, recipient, source_token_account } => {
+                Self::RescueTokens { amount, recipient, source_token_account }
+            }
+            ProposalCommand::SetTierTable { tiers } => Self::SetTierTable { tiers },
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ClockArg {
+    Slot,
+    UnixTimestamp,
+}
+
+impl From<ClockArg> for LockClock {
+    fn from(clock: ClockArg) -> Self {
+        match clock {
+            ClockArg::Slot => LockClock::Slot,
+            ClockArg::UnixTimestamp => LockClock::UnixTimestamp,
+        }
+    }
+}
+
+// Who signs: a local keypair, or an offline signer known only by its public key
+enum Authority {
+    Keypair(Keypair),
+    Offline(Pubkey),
+}
+
+struct Config {
+    rpc: RpcClient,
+    authority: Authority,
+    blockhash: Option<Hash>,
+}
+
+impl Config {
+    fn pubkey(&self) -> Pubkey {
+        match &self.authority {
+            Authority::Keypair(keypair) => keypair.pubkey(),
+            Authority::Offline(pubkey) => *pubkey,
+        }
+    }
+
+    fn account_exists(&self, address: &Pubkey) -> Result<bool> {
+        Ok(self.rpc.get_account_with_commitment(address, self.rpc.commitment())?.value.is_some())
+    }
+
+    fn clock(&self) -> Result<Clock> {
+        let account = self.rpc.get_account(&sysvar::clock::ID)?;
+        Ok(bincode::deserialize(&account.data)?)
+    }
+
+    // Sign and send the transaction, or print it unsigned in offline mode.
+    // `extra_signers` are local keypairs (e.g. a fresh vault) that always sign here.
+    fn submit(&self, instructions: &[Instruction], extra_signers: &[&Keypair]) -> Result<()> {
+        let blockhash = match self.blockhash {
+            Some(blockhash) => blockhash,
+            None => self.rpc.get_latest_blockhash()?,
+        };
+        let mut transaction = Transaction::new_unsigned(Message::new(instructions, Some(&self.pubkey())));
+        let mut signers: Vec<&dyn Signer> = extra_signers.iter().map(|keypair| *keypair as &dyn Signer).collect();
+
+        match &self.authority {
+            Authority::Keypair(keypair) => {
+                signers.push(keypair);
+                transaction.try_sign(&signers, blockhash)?;
+                let signature = self.rpc.send_and_confirm_transaction(&transaction)?;
+                println!("Signature: {signature}");
+            }
+            Authority::Offline(_) => {
+                transaction.try_partial_sign(&signers, blockhash)?;
+                let required = transaction.message.header.num_required_signatures as usize;
+                println!("Blockhash: {blockhash}");
+                println!("Signers:");
+                for (key, signature) in transaction.message.account_keys[..required].iter().zip(&transaction.signatures) {
+                    let state = if *signature == Default::default() { "missing" } else { "signed" };
+                    println!("  {key} ({state})");
+                }
+                let bytes = bincode::serialize(&transaction)?;
+                println!("Transaction: {}", base64::engine::general_purpose::STANDARD.encode(bytes));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn default_keypair_path() -> Result<PathBuf> {
+    let home = std::env::var_os("HOME").ok_or_else(|| anyhow!("HOME is not set, pass --keypair"))?;
+    Ok(PathBuf::from(home).join(".config/solana/id.json"))
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let rpc = RpcClient::new_with_commitment(cli.url, CommitmentConfig::confirmed());
+    let authority = match cli.signer {
+        Some(pubkey) if cli.offline => Authority::Offline(pubkey),
+        _ => {
+            let path = match cli.keypair {
+                Some(path) => path,
+                None => default_keypair_path()?,
+            };
+            let keypair = read_keypair_file(&path)
+                .map_err(|err| anyhow!("failed to read keypair {}: {err}", path.display()))?;
+            Authority::Keypair(keypair)
+        }
+    };
+    let config = Config { rpc, authority, blockhash: cli.blockhash };
+
+    match cli.command {
+        Command::InitPool {
+            mint,
+            pool_id,
+            token_program,
+            init_registry,
+            lock_clock,
+            lock_duration_slots,
+            lock_duration_seconds,
+        } => {
+            let authority = config.pubkey();
+            let token_program = match token_program {
+                Some(token_program) => token_program,
+                None => config.rpc.get_account(&mint).context("failed to fetch mint")?.owner,
+            };
+            let token_vault = Keypair::new();
+
+            let mut ixs = Vec::new();
+            let init_registry = match init_registry {
+                Some(init_registry) => init_registry,
+                None => !config.account_exists(&pda::find_pool_registry(&mint).0)?,
+            };
+            if init_registry {
+                ixs.push(instructions::initialize_pool_registry(&authority, &mint));
+            }
+            let args = InitializeArgs {
+                lock_duration_slots,
+                lock_clock: lock_clock.map(LockClock::from),
+                lock_duration_seconds,
+                limits: None,
+            };
+            ixs.push(instructions::initialize(&authority, &mint, &token_vault.pubkey(), &token_program, pool_id, args));
+
+            println!("Stake pool: {}", pda::find_stake_pool(&mint, pool_id).0);
+            println!("Token vault: {}", token_vault.pubkey());
+            config.submit(&ixs, &[&token_vault])
+        }
+        Command::InitMultisig { pool, signers, threshold } => {
+            let signers: [Pubkey; 3] = signers.try_into().map_err(|_| anyhow!("exactly three signers are required"))?;
+            println!("Multisig config: {}", pda::find_multisig_config(&pool).0);
+            config.submit(&[instructions::initialize_multisig(&pool, &config.pubkey(), signers, threshold)], &[])
+        }
+        Command::Propose { pool, proposal_id, proposal } => {
+            let proposal_id = match proposal_id {
+                Some(proposal_id) => proposal_id,
+                None => accounts::fetch_multisig_config(&config.rpc, &pool)?.proposal_count,
+            };
+            let payload = ProposalPayload::from(proposal);
+            println!("Proposal id: {proposal_id}");
+            config.submit(&[instructions::create_proposal(&pool, proposal_id, &config.pubkey(), &payload)?], &[])
+        }
+        Command::Sign { pool, proposal_id } => {
+            config.submit(&[instructions::sign_proposal(&pool, proposal_id, &config.pubkey())], &[])
+        }
+        Command::Execute {
+            pool,
+            proposal_id,
+            recipient_token_account,
+            mint,
+            token_program,
+            token_vault,
+            init_tier_table,
+            proposal,
+        } => {
+            let executor = config.pubkey();
+            let payload = match proposal {
+                Some(proposal) => ProposalPayload::from(proposal),
+                None => {
+                    let proposal = accounts::fetch_proposal(&config.rpc, &pool, proposal_id)?;
+                    ProposalPayload::decode(proposal.proposal_type, &proposal.data)?
+                }
+            };
+
+            // AdminWithdraw, RescueTokens and SetTierTable take extra accounts and have their own instructions
+            let mut ixs = Vec::new();
+            let ix = match payload {
+                ProposalPayload::AdminWithdraw { recipient, .. } => {
+                    let pool_accounts = match (mint, token_vault, token_program) {
+                        (Some(token_mint), Some(token_vault), Some(token_program)) => {
+                            PoolAccounts { stake_pool: pool, token_mint, token_vault, token_program }
+                        }
+                        _ => accounts::fetch_pool_accounts(&config.rpc, &pool)?,
+                    };
+                    let recipient_token_account = recipient_token_account.unwrap_or_else(|| {
+                        associated_token_address(&recipient, &pool_accounts.token_mint, &pool_accounts.token_program)
+                    });
+                    instructions::execute_admin_withdraw(&pool_accounts, proposal_id, &recipient_token_account, &executor)
+                }
+                ProposalPayload::RescueTokens { recipient, source_token_account, .. } => {
+                    let (mint, token_program) = match (mint, token_program) {
+                        (Some(mint), Some(token_program)) => (mint, token_program),
+                        _ => {
+                            let source = config.rpc.get_account(&source_token_account)?;
+                            // The mint is the first field of every token account
+                            let mint = source.data.get(..32)
+                                .and_then(|bytes| Pubkey::try_from(bytes).ok())
+                                .ok_or_else(|| anyhow!("{source_token_account} is not a token account"))?;
+                            (mint, source.owner)
+                        }
+                    };
+                    let rescue = RescueAccounts {
+                        source_token_account,
+                        mint,
+                        recipient_token_account: recipient_token_account
+                            .unwrap_or_else(|| associated_token_address(&recipient, &mint, &token_program)),
+                        token_program,
+                    };
+                    instructions::execute_rescue_tokens(&pool, proposal_id, &rescue, &executor)
+                }
+                ProposalPayload::SetTierTable { .. } => {
+                    let init_tier_table = match init_tier_table {
+                        Some(init_tier_table) => init_tier_table,
+                        None => !config.account_exists(&pda::find_tier_table(&pool).0)?,
+                    };
+                    if init_tier_table {
+                        ixs.push(instructions::initialize_tier_table(&pool, &executor));
+                    }
+                    instructions::execute_set_tier_table(&pool, proposal_id, &executor)
+                }
+                _ => instructions::execute_proposal(&pool, proposal_id, &executor),
+            };
+            ixs.push(ix);
+            config.submit(&ixs, &[])
+        }
+        Command::InitCheckpoints { pool, owner } => {
+            let payer = config.pubkey();
+            let ix = match owner {
+                Some(owner) => {
+                    let user_stake = pda::find_user_stake(&pool, &owner).0;
+                    println!("Checkpoints: {}", pda::find_stake_checkpoints(&user_stake).0);
+                    instructions::initialize_stake_checkpoints(&pool, &owner, &payer)
+                }
+                None => {
+                    println!("Checkpoints: {}", pda::find_pool_checkpoints(&pool).0);
+                    instructions::initialize_pool_checkpoints(&pool, &payer)
+                }
+            };
+            config.submit(&[ix], &[])
+        }
+        Command::ShowPool { pool } => {
+            let stake_pool = accounts::fetch_stake_pool(&config.rpc, &pool)?;
+            display::print_pool(&pool, &stake_pool);
+            match accounts::fetch_pool_checkpoints(&config.rpc, &pool) {
+                Ok(checkpoints) => display::print_checkpoints(&checkpoints.ring, &checkpoints.entries),
+                Err(_) => println!("Checkpoints:          not initialized"),
+            }
+            if let Ok(tier_table) = accounts::fetch_tier_table(&config.rpc, &pool) {
+                display::print_tier_table(&tier_table);
+            }
+            Ok(())
+        }
+        Command::ShowPosition { pool, owner } => {
+            let stake_pool = accounts::fetch_stake_pool(&config.rpc, &pool)?;
+            let user_stake = accounts::fetch_user_stake(&config.rpc, &pool, &owner)?;
+            let tier_table = accounts::fetch_tier_table(&config.rpc, &pool).ok();
+            display::print_position(
+                &pda::find_user_stake(&pool, &owner).0,
+                &user_stake,
+                &stake_pool,
+                tier_table.as_ref(),
+                &config.clock()?,
+            );
+            match accounts::fetch_stake_checkpoints(&config.rpc, &pool, &owner) {
+                Ok(checkpoints) => display::print_checkpoints(&checkpoints.ring, &checkpoints.entries),
+                Err(_) => println!("Checkpoints:          not initialized"),
+            }
+            Ok(())
+        }
+        Command::ShowProposal { pool, proposal_id } => {
+            let multisig_config = accounts::fetch_multisig_config(&config.rpc, &pool)?;
+            let proposal = accounts::fetch_proposal(&config.rpc, &pool, proposal_id)?;
+            let address = pda::find_proposal(&pda::find_multisig_config(&pool).0, proposal_id).0;
+            display::print_proposal(&address, &proposal, &multisig_config, config.clock()?.slot);
+            Ok(())
+        }
+    }
+}
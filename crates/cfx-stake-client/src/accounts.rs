@@ -0,0 +1,139 @@
+// Account fetch and decode helpers
+use anchor_lang::error::ErrorCode;
+use anchor_lang::{AccountDeserialize, Discriminator};
+use cfx_stake_core::{
+    MultisigConfig, MultisigProposal, PoolCheckpoints, PoolRegistry, ProposalRecord, StakeCheckpoints, StakePool,
+    TierTable, UserStake, VestingSchedule, ID,
+};
+use solana_account_decoder::UiAccountEncoding;
+use solana_program::pubkey::Pubkey;
+use solana_rpc_client::rpc_client::RpcClient;
+use solana_rpc_client_api::config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
+use solana_rpc_client_api::filter::{Memcmp, RpcFilterType};
+
+use crate::error::{ClientError, Result};
+use crate::instructions::PoolAccounts;
+use crate::pda;
+
+/// Decode a Borsh program account (everything except the zero-copy ones)
+pub fn decode_account<T: AccountDeserialize>(data: &[u8]) -> Result<T> {
+    let mut data = data;
+    Ok(T::try_deserialize(&mut data)?)
+}
+
+/// Decode a zero-copy account of `len` bytes. RPC buffers carry no alignment
+/// guarantee, so the account is read unaligned instead of cast in place.
+fn decode_zero_copy<T: bytemuck::Pod + Discriminator>(data: &[u8], len: usize, name: &'static str) -> Result<T> {
+    if data.len() < 8 {
+        return Err(anchor_lang::error::Error::from(ErrorCode::AccountDiscriminatorNotFound).into());
+    }
+    if data[..8] != T::DISCRIMINATOR {
+        return Err(anchor_lang::error::Error::from(ErrorCode::AccountDiscriminatorMismatch).into());
+    }
+    let body = data.get(8..8 + len).ok_or(ClientError::AccountTooSmall(name))?;
+    Ok(bytemuck::pod_read_unaligned(body))
+}
+
+pub fn decode_stake_pool(data: &[u8]) -> Result<StakePool> {
+    decode_zero_copy(data, StakePool::LEN, "StakePool")
+}
+
+pub fn decode_stake_checkpoints(data: &[u8]) -> Result<StakeCheckpoints> {
+    decode_zero_copy(data, StakeCheckpoints::LEN, "StakeCheckpoints")
+}
+
+pub fn decode_pool_checkpoints(data: &[u8]) -> Result<PoolCheckpoints> {
+    decode_zero_copy(data, PoolCheckpoints::LEN, "PoolCheckpoints")
+}
+
+fn fetch_data(rpc: &RpcClient, address: &Pubkey) -> Result<Vec<u8>> {
+    let account = rpc.get_account(address)?;
+    if account.owner != ID {
+        return Err(ClientError::InvalidOwner(*address));
+    }
+    Ok(account.data)
+}
+
+fn fetch_account<T: AccountDeserialize>(rpc: &RpcClient, address: &Pubkey) -> Result<T> {
+    decode_account(&fetch_data(rpc, address)?)
+}
+
+pub fn fetch_stake_pool(rpc: &RpcClient, address: &Pubkey) -> Result<StakePool> {
+    decode_stake_pool(&fetch_data(rpc, address)?)
+}
+
+/// Fetch a stake pool together with the accounts its instructions need
+pub fn fetch_pool_accounts(rpc: &RpcClient, address: &Pubkey) -> Result<PoolAccounts> {
+    Ok(PoolAccounts::new(*address, &fetch_stake_pool(rpc, address)?))
+}
+
+pub fn fetch_pool_registry(rpc: &RpcClient, token_mint: &Pubkey) -> Result<PoolRegistry> {
+    fetch_account(rpc, &pda::find_pool_registry(token_mint).0)
+}
+
+pub fn fetch_tier_table(rpc: &RpcClient, stake_pool: &Pubkey) -> Result<TierTable> {
+    fetch_account(rpc, &pda::find_tier_table(stake_pool).0)
+}
+
+pub fn fetch_user_stake(rpc: &RpcClient, stake_pool: &Pubkey, owner: &Pubkey) -> Result<UserStake> {
+    fetch_account(rpc, &pda::find_user_stake(stake_pool, owner).0)
+}
+
+pub fn fetch_stake_checkpoints(rpc: &RpcClient, stake_pool: &Pubkey, owner: &Pubkey) -> Result<StakeCheckpoints> {
+    let user_stake = pda::find_user_stake(stake_pool, owner).0;
+    decode_stake_checkpoints(&fetch_data(rpc, &pda::find_stake_checkpoints(&user_stake).0)?)
+}
+
+pub fn fetch_pool_checkpoints(rpc: &RpcClient, stake_pool: &Pubkey) -> Result<PoolCheckpoints> {
+    decode_pool_checkpoints(&fetch_data(rpc, &pda::find_pool_checkpoints(stake_pool).0)?)
+}
+
+// Offsets of `UserStake` fields in the account data, for getProgramAccounts filters
+const USER_STAKE_POOL_OFFSET: usize = 8 + 32;
+const USER_STAKE_AUTO_WITHDRAW_OFFSET: usize =
+    8 + 32 + 32 + 8 + 8 + 8 + 1 + 1 + 8 + 8 + 8 + VestingSchedule::LEN + 1;
+
+/// Every position that opted in to keeper-completed withdrawals, optionally
+/// limited to one pool
+pub fn fetch_auto_withdraw_positions(
+    rpc: &RpcClient,
+    stake_pool: Option<&Pubkey>,
+) -> Result<Vec<(Pubkey, UserStake)>> {
+    let mut filters = vec![
+        RpcFilterType::DataSize((8 + UserStake::LEN) as u64),
+        RpcFilterType::Memcmp(Memcmp::new_raw_bytes(0, UserStake::DISCRIMINATOR.to_vec())),
+        RpcFilterType::Memcmp(Memcmp::new_raw_bytes(USER_STAKE_AUTO_WITHDRAW_OFFSET, vec![1])),
+    ];
+    if let Some(stake_pool) = stake_pool {
+        filters.push(RpcFilterType::Memcmp(Memcmp::new_raw_bytes(
+            USER_STAKE_POOL_OFFSET,
+            stake_pool.to_bytes().to_vec(),
+        )));
+    }
+    let config = RpcProgramAccountsConfig {
+        filters: Some(filters),
+        account_config: RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            ..RpcAccountInfoConfig::default()
+        },
+        ..RpcProgramAccountsConfig::default()
+    };
+    rpc.get_program_accounts_with_config(&ID, config)?
+        .into_iter()
+        .map(|(address, account)| Ok((address, decode_account(&account.data)?)))
+        .collect()
+}
+
+pub fn fetch_multisig_config(rpc: &RpcClient, stake_pool: &Pubkey) -> Result<MultisigConfig> {
+    fetch_account(rpc, &pda::find_multisig_config(stake_pool).0)
+}
+
+pub fn fetch_proposal(rpc: &RpcClient, stake_pool: &Pubkey, proposal_id: u64) -> Result<MultisigProposal> {
+    let multisig_config = pda::find_multisig_config(stake_pool).0;
+    fetch_account(rpc, &pda::find_proposal(&multisig_config, proposal_id).0)
+}
+
+pub fn fetch_proposal_record(rpc: &RpcClient, stake_pool: &Pubkey, proposal_id: u64) -> Result<ProposalRecord> {
+    let multisig_config = pda::find_multisig_config(stake_pool).0;
+    fetch_account(rpc, &pda::find_proposal_record(&multisig_config, proposal_id).0)
+}
@@ -0,0 +1,20 @@
+use solana_program::pubkey::Pubkey;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ClientError {
+    #[error("RPC error: {0}")]
+    Rpc(#[from] solana_rpc_client_api::client_error::Error),
+    #[error("Failed to decode account: {0}")]
+    Decode(#[from] anchor_lang::error::Error),
+    #[error("Account {0} is not owned by the staking program")]
+    InvalidOwner(Pubkey),
+    #[error("Account data too small for {0}")]
+    AccountTooSmall(&'static str),
+    #[error("Invalid proposal payload")]
+    InvalidPayload,
+    #[error("Tier table has {0} tiers, at most {max} are allowed", max = cfx_stake_core::MAX_TIERS)]
+    TooManyTiers(usize),
+}
+
+pub type Result<T> = std::result::Result<T, ClientError>;
@@ -0,0 +1,670 @@
+// Typed instruction builders. Account lists come from the program's generated
+// `accounts` structs, so they stay in sync with the on-chain constraints.
+use anchor_lang::{InstructionData, ToAccountMetas};
+use cfx_stake_core::{accounts, instruction, LockClock, PoolLimits, StakePool, VestingSchedule, ID};
+use solana_program::instruction::Instruction;
+use solana_program::pubkey::Pubkey;
+use solana_program::{system_program, sysvar};
+
+use crate::error::Result;
+use crate::pda;
+use crate::proposals::ProposalPayload;
+
+/// Addresses shared by every instruction that moves tokens in or out of a pool
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PoolAccounts {
+    pub stake_pool: Pubkey,
+    pub token_mint: Pubkey,
+    pub token_vault: Pubkey,
+    pub token_program: Pubkey,
+}
+
+impl PoolAccounts {
+    pub fn new(stake_pool: Pubkey, pool: &StakePool) -> Self {
+        Self {
+            stake_pool,
+            token_mint: pool.token_mint,
+            token_vault: pool.token_vault,
+            token_program: pool.token_program,
+        }
+    }
+}
+
+/// Optional pool parameters for `initialize`; `None` selects the program defaults
+#[derive(Default)]
+pub struct InitializeArgs {
+    pub lock_duration_slots: Option<u64>,
+    pub lock_clock: Option<LockClock>,
+    pub lock_duration_seconds: Option<u64>,
+    pub limits: Option<PoolLimits>,
+}
+
+fn build(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+// Admin instructions
+
+/// `token_vault` is a fresh keypair that must also sign the transaction
+pub fn initialize(
+    authority: &Pubkey,
+    token_mint: &Pubkey,
+    token_vault: &Pubkey,
+    token_program: &Pubkey,
+    pool_id: u64,
+    args: InitializeArgs,
+) -> Instruction {
+    let (stake_pool, bump) = pda::find_stake_pool(token_mint, pool_id);
+    build(
+        accounts::Initialize {
+            stake_pool,
+            pool_checkpoints: pda::find_pool_checkpoints(&stake_pool).0,
+            pool_registry: pda::find_pool_registry(token_mint).0,
+            token_mint: *token_mint,
+            token_vault: *token_vault,
+            authority: *authority,
+            system_program: system_program::ID,
+            token_program: *token_program,
+            rent: sysvar::rent::ID,
+        },
+        instruction::Initialize {
+            _stake_pool_bump: bump,
+            lock_duration_slots: args.lock_duration_slots,
+            lock_clock: args.lock_clock,
+            lock_duration_seconds: args.lock_duration_seconds,
+            pool_id,
+            limits: args.limits,
+        },
+    )
+}
+
+pub fn initialize_pool_registry(payer: &Pubkey, token_mint: &Pubkey) -> Instruction {
+    build(
+        accounts::InitializePoolRegistry {
+            pool_registry: pda::find_pool_registry(token_mint).0,
+            token_mint: *token_mint,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::InitializePoolRegistry {},
+    )
+}
+
+pub fn register_pool(stake_pool: &Pubkey, token_mint: &Pubkey, authority: &Pubkey) -> Instruction {
+    build(
+        accounts::RegisterPool {
+            stake_pool: *stake_pool,
+            pool_registry: pda::find_pool_registry(token_mint).0,
+            authority: *authority,
+        },
+        instruction::RegisterPool {},
+    )
+}
+
+pub fn initialize_tier_table(stake_pool: &Pubkey, payer: &Pubkey) -> Instruction {
+    build(
+        accounts::InitializeTierTable {
+            tier_table: pda::find_tier_table(stake_pool).0,
+            stake_pool: *stake_pool,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::InitializeTierTable {},
+    )
+}
+
+/// Start the pool-wide balance history of a pool created before checkpoints existed
+pub fn initialize_pool_checkpoints(stake_pool: &Pubkey, payer: &Pubkey) -> Instruction {
+    build(
+        accounts::InitializePoolCheckpoints {
+            pool_checkpoints: pda::find_pool_checkpoints(stake_pool).0,
+            stake_pool: *stake_pool,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::InitializePoolCheckpoints {},
+    )
+}
+
+pub fn toggle_pause(stake_pool: &Pubkey, authority: &Pubkey, pause: bool) -> Instruction {
+    build(
+        accounts::TogglePause {
+            stake_pool: *stake_pool,
+            authority: *authority,
+        },
+        instruction::TogglePause { pause },
+    )
+}
+
+pub fn sync_vault(pool: &PoolAccounts) -> Instruction {
+    build(
+        accounts::SyncVault {
+            stake_pool: pool.stake_pool,
+            token_vault: pool.token_vault,
+        },
+        instruction::SyncVault {},
+    )
+}
+
+pub fn migrate_stake_pool(stake_pool: &Pubkey, token_mint: &Pubkey, payer: &Pubkey) -> Instruction {
+    build(
+        accounts::MigrateStakePool {
+            stake_pool: *stake_pool,
+            token_mint: *token_mint,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::MigrateStakePool {},
+    )
+}
+
+pub fn migrate_user_stake(stake_pool: &Pubkey, owner: &Pubkey, payer: &Pubkey) -> Instruction {
+    build(
+        accounts::MigrateUserStake {
+            user_stake: pda::find_user_stake(stake_pool, owner).0,
+            stake_pool: *stake_pool,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::MigrateUserStake {},
+    )
+}
+
+pub fn migrate_multisig_config(stake_pool: &Pubkey, payer: &Pubkey) -> Instruction {
+    build(
+        accounts::MigrateMultisigConfig {
+            multisig_config: pda::find_multisig_config(stake_pool).0,
+            stake_pool: *stake_pool,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::MigrateMultisigConfig {},
+    )
+}
+
+pub fn migrate_proposal(stake_pool: &Pubkey, proposal_id: u64, payer: &Pubkey) -> Instruction {
+    let multisig_config = pda::find_multisig_config(stake_pool).0;
+    build(
+        accounts::MigrateProposal {
+            proposal: pda::find_proposal(&multisig_config, proposal_id).0,
+            multisig_config,
+            stake_pool: *stake_pool,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::MigrateProposal {},
+    )
+}
+
+// Multisig instructions. The multisig config and proposal addresses are derived
+// from the stake pool and the proposal id.
+
+pub fn initialize_multisig(
+    stake_pool: &Pubkey,
+    authority: &Pubkey,
+    signers: [Pubkey; 3],
+    threshold: u8,
+) -> Instruction {
+    let (multisig_config, bump) = pda::find_multisig_config(stake_pool);
+    build(
+        accounts::InitializeMultisig {
+            multisig_config,
+            stake_pool: *stake_pool,
+            authority: *authority,
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+        },
+        instruction::InitializeMultisig {
+            signers,
+            threshold,
+            _multisig_bump: bump,
+        },
+    )
+}
+
+/// `proposal_id` must equal the multisig config's current `proposal_count`
+pub fn create_proposal(
+    stake_pool: &Pubkey,
+    proposal_id: u64,
+    proposer: &Pubkey,
+    payload: &ProposalPayload,
+) -> Result<Instruction> {
+    let multisig_config = pda::find_multisig_config(stake_pool).0;
+    let (proposal, bump) = pda::find_proposal(&multisig_config, proposal_id);
+    Ok(build(
+        accounts::CreateProposal {
+            proposal,
+            multisig_config,
+            stake_pool: *stake_pool,
+            proposer: *proposer,
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+        },
+        instruction::CreateProposal {
+            proposal_type: payload.proposal_type(),
+            data: payload.encode()?,
+            _proposal_bump: bump,
+        },
+    ))
+}
+
+pub fn sign_proposal(stake_pool: &Pubkey, proposal_id: u64, signer: &Pubkey) -> Instruction {
+    let multisig_config = pda::find_multisig_config(stake_pool).0;
+    build(
+        accounts::SignProposal {
+            proposal: pda::find_proposal(&multisig_config, proposal_id).0,
+            multisig_config,
+            stake_pool: *stake_pool,
+            signer: *signer,
+        },
+        instruction::SignProposal {},
+    )
+}
+
+pub fn execute_proposal(stake_pool: &Pubkey, proposal_id: u64, executor: &Pubkey) -> Instruction {
+    let multisig_config = pda::find_multisig_config(stake_pool).0;
+    build(
+        accounts::ExecuteProposal {
+            proposal: pda::find_proposal(&multisig_config, proposal_id).0,
+            multisig_config,
+            stake_pool: *stake_pool,
+            executor: *executor,
+        },
+        instruction::ExecuteProposal {},
+    )
+}
+
+pub fn execute_admin_withdraw(
+    pool: &PoolAccounts,
+    proposal_id: u64,
+    recipient_token_account: &Pubkey,
+    executor: &Pubkey,
+) -> Instruction {
+    let multisig_config = pda::find_multisig_config(&pool.stake_pool).0;
+    build(
+        accounts::ExecuteAdminWithdraw {
+            proposal: pda::find_proposal(&multisig_config, proposal_id).0,
+            multisig_config,
+            stake_pool: pool.stake_pool,
+            stake_pool_authority: pool.stake_pool,
+            token_vault: pool.token_vault,
+            token_mint: pool.token_mint,
+            recipient_token_account: *recipient_token_account,
+            executor: *executor,
+            token_program: pool.token_program,
+        },
+        instruction::ExecuteAdminWithdraw {},
+    )
+}
+
+/// Accounts of a foreign token held by the pool, as named in a RescueTokens proposal
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RescueAccounts {
+    pub source_token_account: Pubkey,
+    pub mint: Pubkey,
+    pub recipient_token_account: Pubkey,
+    pub token_program: Pubkey,
+}
+
+pub fn execute_rescue_tokens(
+    stake_pool: &Pubkey,
+    proposal_id: u64,
+    rescue: &RescueAccounts,
+    executor: &Pubkey,
+) -> Instruction {
+    let multisig_config = pda::find_multisig_config(stake_pool).0;
+    build(
+        accounts::ExecuteRescueTokens {
+            proposal: pda::find_proposal(&multisig_config, proposal_id).0,
+            multisig_config,
+            stake_pool: *stake_pool,
+            stake_pool_authority: *stake_pool,
+            source_token_account: rescue.source_token_account,
+            mint: rescue.mint,
+            recipient_token_account: rescue.recipient_token_account,
+            executor: *executor,
+            token_program: rescue.token_program,
+        },
+        instruction::ExecuteRescueTokens {},
+    )
+}
+
+pub fn execute_set_tier_table(stake_pool: &Pubkey, proposal_id: u64, executor: &Pubkey) -> Instruction {
+    let multisig_config = pda::find_multisig_config(stake_pool).0;
+    build(
+        accounts::ExecuteSetTierTable {
+            proposal: pda::find_proposal(&multisig_config, proposal_id).0,
+            multisig_config,
+            stake_pool: *stake_pool,
+            tier_table: pda::find_tier_table(stake_pool).0,
+            executor: *executor,
+        },
+        instruction::ExecuteSetTierTable {},
+    )
+}
+
+/// `proposer` receives the rent of the closed proposal
+pub fn close_proposal(
+    stake_pool: &Pubkey,
+    proposal_id: u64,
+    proposer: &Pubkey,
+    closer: &Pubkey,
+) -> Instruction {
+    let multisig_config = pda::find_multisig_config(stake_pool).0;
+    build(
+        accounts::CloseProposal {
+            proposal: pda::find_proposal(&multisig_config, proposal_id).0,
+            proposal_record: pda::find_proposal_record(&multisig_config, proposal_id).0,
+            multisig_config,
+            proposer: *proposer,
+            closer: *closer,
+            system_program: system_program::ID,
+        },
+        instruction::CloseProposal {},
+    )
+}
+
+// User instructions
+
+pub fn create_user_stake(stake_pool: &Pubkey, owner: &Pubkey) -> Instruction {
+    let (user_stake, bump) = pda::find_user_stake(stake_pool, owner);
+    build(
+        accounts::CreateUserStake {
+            user_stake,
+            checkpoints: pda::find_stake_checkpoints(&user_stake).0,
+            stake_pool: *stake_pool,
+            owner: *owner,
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+        },
+        instruction::CreateUserStake { _user_stake_bump: bump },
+    )
+}
+
+pub fn stake(pool: &PoolAccounts, owner: &Pubkey, user_token_account: &Pubkey, amount: u64) -> Instruction {
+    let user_stake = pda::find_user_stake(&pool.stake_pool, owner).0;
+    build(
+        accounts::Stake {
+            user_stake,
+            checkpoints: pda::find_stake_checkpoints(&user_stake).0,
+            stake_pool: pool.stake_pool,
+            pool_checkpoints: pda::find_pool_checkpoints(&pool.stake_pool).0,
+            stake_pool_authority: pool.stake_pool,
+            token_vault: pool.token_vault,
+            token_mint: pool.token_mint,
+            user_token_account: *user_token_account,
+            owner: *owner,
+            token_program: pool.token_program,
+        },
+        instruction::Stake { amount },
+    )
+}
+
+pub fn create_user_stake_for(stake_pool: &Pubkey, beneficiary: &Pubkey, funder: &Pubkey) -> Instruction {
+    let user_stake = pda::find_user_stake(stake_pool, beneficiary).0;
+    build(
+        accounts::CreateUserStakeFor {
+            user_stake,
+            checkpoints: pda::find_stake_checkpoints(&user_stake).0,
+            stake_pool: *stake_pool,
+            beneficiary: *beneficiary,
+            funder: *funder,
+            system_program: system_program::ID,
+        },
+        instruction::CreateUserStakeFor {},
+    )
+}
+
+fn stake_for_accounts(
+    pool: &PoolAccounts,
+    beneficiary: &Pubkey,
+    funder: &Pubkey,
+    funder_token_account: &Pubkey,
+) -> accounts::StakeFor {
+    let user_stake = pda::find_user_stake(&pool.stake_pool, beneficiary).0;
+    accounts::StakeFor {
+        user_stake,
+        checkpoints: pda::find_stake_checkpoints(&user_stake).0,
+        stake_pool: pool.stake_pool,
+        pool_checkpoints: pda::find_pool_checkpoints(&pool.stake_pool).0,
+        token_vault: pool.token_vault,
+        token_mint: pool.token_mint,
+        funder_token_account: *funder_token_account,
+        beneficiary: *beneficiary,
+        funder: *funder,
+        token_program: pool.token_program,
+    }
+}
+
+pub fn stake_for(
+    pool: &PoolAccounts,
+    beneficiary: &Pubkey,
+    funder: &Pubkey,
+    funder_token_account: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    build(
+        stake_for_accounts(pool, beneficiary, funder, funder_token_account),
+        instruction::StakeFor { amount },
+    )
+}
+
+/// Stakes `vesting.total` under the given schedule, in the pool's lock clock units.
+/// The beneficiary must sign alongside the funder.
+pub fn stake_vested(
+    pool: &PoolAccounts,
+    beneficiary: &Pubkey,
+    funder: &Pubkey,
+    funder_token_account: &Pubkey,
+    vesting: &VestingSchedule,
+) -> Instruction {
+    let mut instruction = build(
+        stake_for_accounts(pool, beneficiary, funder, funder_token_account),
+        instruction::StakeVested {
+            amount: vesting.total,
+            start: vesting.start,
+            cliff: vesting.cliff,
+            end: vesting.end,
+        },
+    );
+    for meta in instruction.accounts.iter_mut().filter(|meta| meta.pubkey == *beneficiary) {
+        meta.is_signer = true;
+    }
+    instruction
+}
+
+pub fn transfer_position(stake_pool: &Pubkey, owner: &Pubkey, new_owner: &Pubkey) -> Instruction {
+    let from_user_stake = pda::find_user_stake(stake_pool, owner).0;
+    let to_user_stake = pda::find_user_stake(stake_pool, new_owner).0;
+    build(
+        accounts::TransferPosition {
+            from_user_stake,
+            from_checkpoints: pda::find_stake_checkpoints(&from_user_stake).0,
+            to_user_stake,
+            to_checkpoints: pda::find_stake_checkpoints(&to_user_stake).0,
+            stake_pool: *stake_pool,
+            owner: *owner,
+            new_owner: *new_owner,
+        },
+        instruction::TransferPosition {},
+    )
+}
+
+pub fn request_withdrawal(stake_pool: &Pubkey, owner: &Pubkey) -> Instruction {
+    build(
+        accounts::RequestWithdrawal {
+            user_stake: pda::find_user_stake(stake_pool, owner).0,
+            stake_pool: *stake_pool,
+            owner: *owner,
+        },
+        instruction::RequestWithdrawal {},
+    )
+}
+
+pub fn extend_lock(stake_pool: &Pubkey, owner: &Pubkey, new_lock_end: u64) -> Instruction {
+    build(
+        accounts::ExtendLock {
+            user_stake: pda::find_user_stake(stake_pool, owner).0,
+            stake_pool: *stake_pool,
+            owner: *owner,
+        },
+        instruction::ExtendLock { new_lock_end },
+    )
+}
+
+pub fn withdraw(pool: &PoolAccounts, owner: &Pubkey, user_token_account: &Pubkey) -> Instruction {
+    let user_stake = pda::find_user_stake(&pool.stake_pool, owner).0;
+    build(
+        accounts::Withdraw {
+            user_stake,
+            checkpoints: pda::find_stake_checkpoints(&user_stake).0,
+            stake_pool: pool.stake_pool,
+            pool_checkpoints: pda::find_pool_checkpoints(&pool.stake_pool).0,
+            stake_pool_authority: pool.stake_pool,
+            token_vault: pool.token_vault,
+            token_mint: pool.token_mint,
+            user_token_account: *user_token_account,
+            owner: *owner,
+            token_program: pool.token_program,
+        },
+        instruction::Withdraw {},
+    )
+}
+
+/// Opt `owner`'s position in or out of keeper-completed withdrawals; cranked
+/// payouts go to `payout_token_account`, which is only checked when enabling
+pub fn set_auto_withdraw(
+    stake_pool: &Pubkey,
+    owner: &Pubkey,
+    payout_token_account: &Pubkey,
+    enabled: bool,
+    keeper_tip_bps: u16,
+) -> Instruction {
+    build(
+        accounts::SetAutoWithdraw {
+            user_stake: pda::find_user_stake(stake_pool, owner).0,
+            stake_pool: *stake_pool,
+            payout_token_account: *payout_token_account,
+            owner: *owner,
+        },
+        instruction::SetAutoWithdraw { enabled, keeper_tip_bps },
+    )
+}
+
+/// Complete `owner`'s matured withdrawal, paying their registered
+/// `payout_token_account` and the keeper's tip to `keeper_token_account`
+pub fn crank_withdraw(
+    pool: &PoolAccounts,
+    owner: &Pubkey,
+    payout_token_account: &Pubkey,
+    keeper: &Pubkey,
+    keeper_token_account: &Pubkey,
+) -> Instruction {
+    let user_stake = pda::find_user_stake(&pool.stake_pool, owner).0;
+    build(
+        accounts::CrankWithdraw {
+            user_stake,
+            checkpoints: pda::find_stake_checkpoints(&user_stake).0,
+            stake_pool: pool.stake_pool,
+            pool_checkpoints: pda::find_pool_checkpoints(&pool.stake_pool).0,
+            stake_pool_authority: pool.stake_pool,
+            token_vault: pool.token_vault,
+            token_mint: pool.token_mint,
+            owner_token_account: *payout_token_account,
+            keeper_token_account: *keeper_token_account,
+            keeper: *keeper,
+            token_program: pool.token_program,
+        },
+        instruction::CrankWithdraw {},
+    )
+}
+
+pub fn close_user_stake(stake_pool: &Pubkey, owner: &Pubkey) -> Instruction {
+    let user_stake = pda::find_user_stake(stake_pool, owner).0;
+    build(
+        accounts::CloseUserStake {
+            user_stake,
+            stake_pool: *stake_pool,
+            owner: *owner,
+        },
+        instruction::CloseUserStake {},
+    )
+}
+
+/// Start the balance history of `owner`'s position if it predates checkpoints
+pub fn initialize_stake_checkpoints(stake_pool: &Pubkey, owner: &Pubkey, payer: &Pubkey) -> Instruction {
+    let user_stake = pda::find_user_stake(stake_pool, owner).0;
+    build(
+        accounts::InitializeStakeCheckpoints {
+            checkpoints: pda::find_stake_checkpoints(&user_stake).0,
+            user_stake,
+            stake_pool: *stake_pool,
+            payer: *payer,
+            system_program: system_program::ID,
+        },
+        instruction::InitializeStakeCheckpoints {},
+    )
+}
+
+/// Refresh the cached tier of `owner`'s position; the tier is also in the return data
+pub fn verify_tier(stake_pool: &Pubkey, owner: &Pubkey) -> Instruction {
+    build(
+        accounts::VerifyTier {
+            user_stake: pda::find_user_stake(stake_pool, owner).0,
+            stake_pool: *stake_pool,
+            tier_table: pda::find_tier_table(stake_pool).0,
+        },
+        instruction::VerifyTier {},
+    )
+}
+
+// View instructions, meant for simulation. The result is in the return data.
+
+pub fn get_position_status(stake_pool: &Pubkey, owner: &Pubkey) -> Instruction {
+    build(
+        accounts::GetPositionStatus {
+            user_stake: pda::find_user_stake(stake_pool, owner).0,
+            stake_pool: *stake_pool,
+        },
+        instruction::GetPositionStatus {},
+    )
+}
+
+/// Return data is a `cfx_stake_interface::PositionSnapshot`
+pub fn get_position_snapshot(stake_pool: &Pubkey, owner: &Pubkey) -> Instruction {
+    build(
+        accounts::GetPositionSnapshot {
+            user_stake: pda::find_user_stake(stake_pool, owner).0,
+            stake_pool: *stake_pool,
+            tier_table: pda::find_tier_table(stake_pool).0,
+        },
+        instruction::GetPositionSnapshot {},
+    )
+}
+
+/// `owner`'s staked amount and the pool total at the end of `slot`
+pub fn stake_at(stake_pool: &Pubkey, owner: &Pubkey, slot: u64) -> Instruction {
+    let user_stake = pda::find_user_stake(stake_pool, owner).0;
+    build(
+        accounts::StakeAt {
+            user_stake,
+            checkpoints: pda::find_stake_checkpoints(&user_stake).0,
+            stake_pool: *stake_pool,
+            pool_checkpoints: pda::find_pool_checkpoints(stake_pool).0,
+        },
+        instruction::StakeAt { slot },
+    )
+}
+
+pub fn get_pool_stats(stake_pool: &Pubkey) -> Instruction {
+    build(
+        accounts::GetPoolStats {
+            stake_pool: *stake_pool,
+        },
+        instruction::GetPoolStats {},
+    )
+}
@@ -0,0 +1,22 @@
+//! Client SDK for the Chain-Fox DAO staking program.
+//!
+//! Instruction builders, PDA derivation, account decoding and proposal payload
+//! encoding, built on the program's own types through its `cpi` feature.
+
+#![allow(clippy::result_large_err)]
+
+pub mod accounts;
+pub mod error;
+pub mod instructions;
+pub mod pda;
+pub mod proposals;
+
+#[cfg(test)]
+mod tests;
+
+pub use cfx_stake_core;
+pub use cfx_stake_interface;
+pub use cfx_stake_core::ID as PROGRAM_ID;
+pub use error::ClientError;
+pub use instructions::PoolAccounts;
+pub use proposals::ProposalPayload;
@@ -0,0 +1,57 @@
+// PDA derivation, mirroring the seeds declared in the program's account constraints
+use cfx_stake_core::{StakePool, ID};
+use solana_program::pubkey::Pubkey;
+
+/// Stake pool for `token_mint` and `pool_id` (pool 0 keeps the legacy seeds)
+pub fn find_stake_pool(token_mint: &Pubkey, pool_id: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"stake_pool", token_mint.as_ref(), StakePool::pool_id_seed(pool_id).as_slice()],
+        &ID,
+    )
+}
+
+/// Registry listing every pool of `token_mint`
+pub fn find_pool_registry(token_mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"pool_registry", token_mint.as_ref()], &ID)
+}
+
+/// Service tier table of `stake_pool`
+pub fn find_tier_table(stake_pool: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"tier_table", stake_pool.as_ref()], &ID)
+}
+
+/// Stake position of `owner` in `stake_pool`
+pub fn find_user_stake(stake_pool: &Pubkey, owner: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"user_stake", stake_pool.as_ref(), owner.as_ref()], &ID)
+}
+
+/// Balance checkpoints of the position `user_stake`
+pub fn find_stake_checkpoints(user_stake: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"stake_checkpoints", user_stake.as_ref()], &ID)
+}
+
+/// Total staked checkpoints of `stake_pool`
+pub fn find_pool_checkpoints(stake_pool: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"pool_checkpoints", stake_pool.as_ref()], &ID)
+}
+
+/// Multisig configuration governing `stake_pool`
+pub fn find_multisig_config(stake_pool: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"multisig_config", stake_pool.as_ref()], &ID)
+}
+
+/// Proposal `proposal_id` of `multisig_config`
+pub fn find_proposal(multisig_config: &Pubkey, proposal_id: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"proposal", multisig_config.as_ref(), &proposal_id.to_le_bytes()],
+        &ID,
+    )
+}
+
+/// Record left behind when proposal `proposal_id` is closed
+pub fn find_proposal_record(multisig_config: &Pubkey, proposal_id: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"proposal_record", multisig_config.as_ref(), &proposal_id.to_le_bytes()],
+        &ID,
+    )
+}
@@ -0,0 +1,137 @@
+// Typed multisig proposal payloads, encoded in the layouts `execute_proposal`,
+// `execute_admin_withdraw`, `execute_rescue_tokens` and `execute_set_tier_table` expect
+use cfx_stake_core::{ProposalType, StakeTier, MAX_TIERS};
+use solana_program::pubkey::Pubkey;
+
+use crate::error::{ClientError, Result};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProposalPayload {
+    TogglePause,
+    UpdateAuthority { new_authority: Pubkey },
+    AdminWithdraw { amount: u64, recipient: Pubkey },
+    SetTransferConsent { required: bool },
+    SetOutflowCap { cap: u64 },
+    SetCircuitBreaker { threshold_bps: u16, window_slots: u64 },
+    ResetCircuitBreaker,
+    RescueTokens { amount: u64, recipient: Pubkey, source_token_account: Pubkey },
+    SetTierTable { tiers: Vec<StakeTier> },
+}
+
+impl ProposalPayload {
+    pub fn proposal_type(&self) -> ProposalType {
+        match self {
+            Self::TogglePause => ProposalType::TogglePause,
+            Self::UpdateAuthority { .. } => ProposalType::UpdateAuthority,
+            Self::AdminWithdraw { .. } => ProposalType::AdminWithdraw,
+            Self::SetTransferConsent { .. } => ProposalType::SetTransferConsent,
+            Self::SetOutflowCap { .. } => ProposalType::SetOutflowCap,
+            Self::SetCircuitBreaker { .. } => ProposalType::SetCircuitBreaker,
+            Self::ResetCircuitBreaker => ProposalType::ResetCircuitBreaker,
+            Self::RescueTokens { .. } => ProposalType::RescueTokens,
+            Self::SetTierTable { .. } => ProposalType::SetTierTable,
+        }
+    }
+
+    /// Proposal data bytes stored on the proposal account
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+        match self {
+            Self::TogglePause | Self::ResetCircuitBreaker => {}
+            Self::UpdateAuthority { new_authority } => {
+                // [new_authority: 32 bytes]
+                data.extend_from_slice(new_authority.as_ref());
+            }
+            Self::AdminWithdraw { amount, recipient } => {
+                // [amount: 8 bytes][recipient: 32 bytes]
+                data.extend_from_slice(&amount.to_le_bytes());
+                data.extend_from_slice(recipient.as_ref());
+            }
+            Self::SetTransferConsent { required } => {
+                // [required: 1 byte]
+                data.push(u8::from(*required));
+            }
+            Self::SetOutflowCap { cap } => {
+                // [cap: 8 bytes]
+                data.extend_from_slice(&cap.to_le_bytes());
+            }
+            Self::SetCircuitBreaker { threshold_bps, window_slots } => {
+                // [threshold_bps: 2 bytes][window_slots: 8 bytes]
+                data.extend_from_slice(&threshold_bps.to_le_bytes());
+                data.extend_from_slice(&window_slots.to_le_bytes());
+            }
+            Self::RescueTokens { amount, recipient, source_token_account } => {
+                // [amount: 8 bytes][recipient: 32 bytes][source_token_account: 32 bytes]
+                data.extend_from_slice(&amount.to_le_bytes());
+                data.extend_from_slice(recipient.as_ref());
+                data.extend_from_slice(source_token_account.as_ref());
+            }
+            Self::SetTierTable { tiers } => {
+                // [count: 1 byte][min_stake: 8 bytes, min_lock: 8 bytes] * count
+                if tiers.len() > MAX_TIERS {
+                    return Err(ClientError::TooManyTiers(tiers.len()));
+                }
+                data.push(tiers.len() as u8);
+                for tier in tiers {
+                    data.extend_from_slice(&tier.min_stake.to_le_bytes());
+                    data.extend_from_slice(&tier.min_lock.to_le_bytes());
+                }
+            }
+        }
+        Ok(data)
+    }
+
+    /// Decode the data of an existing proposal
+    pub fn decode(proposal_type: ProposalType, data: &[u8]) -> Result<Self> {
+        let u64_at = |offset: usize| -> Result<u64> {
+            data.get(offset..offset + 8)
+                .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+                .ok_or(ClientError::InvalidPayload)
+        };
+        let pubkey_at = |offset: usize| -> Result<Pubkey> {
+            data.get(offset..offset + 32)
+                .map(|bytes| Pubkey::try_from(bytes).unwrap())
+                .ok_or(ClientError::InvalidPayload)
+        };
+
+        let payload = match proposal_type {
+            ProposalType::TogglePause => Self::TogglePause,
+            ProposalType::UpdateAuthority => Self::UpdateAuthority { new_authority: pubkey_at(0)? },
+            ProposalType::AdminWithdraw => Self::AdminWithdraw {
+                amount: u64_at(0)?,
+                recipient: pubkey_at(8)?,
+            },
+            ProposalType::SetTransferConsent => Self::SetTransferConsent {
+                required: *data.first().ok_or(ClientError::InvalidPayload)? != 0,
+            },
+            ProposalType::SetOutflowCap => Self::SetOutflowCap { cap: u64_at(0)? },
+            ProposalType::SetCircuitBreaker => Self::SetCircuitBreaker {
+                threshold_bps: data.get(0..2)
+                    .map(|bytes| u16::from_le_bytes(bytes.try_into().unwrap()))
+                    .ok_or(ClientError::InvalidPayload)?,
+                window_slots: u64_at(2)?,
+            },
+            ProposalType::ResetCircuitBreaker => Self::ResetCircuitBreaker,
+            ProposalType::RescueTokens => Self::RescueTokens {
+                amount: u64_at(0)?,
+                recipient: pubkey_at(8)?,
+                source_token_account: pubkey_at(40)?,
+            },
+            ProposalType::SetTierTable => {
+                let count = *data.first().ok_or(ClientError::InvalidPayload)? as usize;
+                let tiers = (0..count)
+                    .map(|index| {
+                        Ok(StakeTier {
+                            min_stake: u64_at(1 + index * 16)?,
+                            min_lock: u64_at(9 + index * 16)?,
+                        })
+                    })
+                    .collect::<Result<_>>()?;
+                Self::SetTierTable { tiers }
+            }
+            // No longer supported by the program
+            ProposalType::UpdateTeamWallet => return Err(ClientError::InvalidPayload),
+        };
+        Ok(payload)
+    }
+}
@@ -0,0 +1,128 @@
+use anchor_lang::__private::bytemuck::Zeroable;
+use cfx_stake_core::{ProposalType, StakePool, StakeTier, TierTable, ID, MAX_TIERS};
+use solana_program::pubkey::Pubkey;
+
+use crate::{pda, ClientError, ProposalPayload};
+
+fn tiers(count: u64) -> Vec<StakeTier> {
+    (1..=count)
+        .map(|index| StakeTier { min_stake: index * 1_000, min_lock: index * 100 })
+        .collect()
+}
+
+fn payloads() -> Vec<ProposalPayload> {
+    let recipient = Pubkey::new_unique();
+    vec![
+        ProposalPayload::TogglePause,
+        ProposalPayload::UpdateAuthority { new_authority: Pubkey::new_unique() },
+        ProposalPayload::AdminWithdraw { amount: 42, recipient },
+        ProposalPayload::SetTransferConsent { required: true },
+        ProposalPayload::SetOutflowCap { cap: 7_000_000 },
+        ProposalPayload::SetCircuitBreaker { threshold_bps: 1_500, window_slots: 9_000 },
+        ProposalPayload::ResetCircuitBreaker,
+        ProposalPayload::RescueTokens { amount: 5, recipient, source_token_account: Pubkey::new_unique() },
+        ProposalPayload::SetTierTable { tiers: tiers(MAX_TIERS as u64) },
+    ]
+}
+
+#[test]
+fn payloads_round_trip() {
+    for payload in payloads() {
+        let data = payload.encode().unwrap();
+        assert_eq!(ProposalPayload::decode(payload.proposal_type(), &data).unwrap(), payload);
+    }
+}
+
+// Byte offsets read by `execute_proposal`, `execute_admin_withdraw`,
+// `execute_rescue_tokens` and `execute_set_tier_table`
+#[test]
+fn payloads_match_the_program_layout() {
+    for payload in payloads() {
+        let data = payload.encode().unwrap();
+        match payload {
+            ProposalPayload::TogglePause | ProposalPayload::ResetCircuitBreaker => assert!(data.is_empty()),
+            ProposalPayload::UpdateAuthority { new_authority } => {
+                assert_eq!(Pubkey::try_from(&data[0..32]).unwrap(), new_authority);
+            }
+            ProposalPayload::AdminWithdraw { amount, recipient } => {
+                assert_eq!(data.len(), 40);
+                assert_eq!(u64::from_le_bytes(data[0..8].try_into().unwrap()), amount);
+                assert_eq!(Pubkey::try_from(&data[8..40]).unwrap(), recipient);
+            }
+            ProposalPayload::SetTransferConsent { required } => assert_eq!(data, [u8::from(required)]),
+            ProposalPayload::SetOutflowCap { cap } => {
+                assert_eq!(u64::from_le_bytes(data[0..8].try_into().unwrap()), cap);
+            }
+            ProposalPayload::SetCircuitBreaker { threshold_bps, window_slots } => {
+                assert_eq!(data.len(), 10);
+                assert_eq!(u16::from_le_bytes(data[0..2].try_into().unwrap()), threshold_bps);
+                assert_eq!(u64::from_le_bytes(data[2..10].try_into().unwrap()), window_slots);
+            }
+            ProposalPayload::RescueTokens { amount, recipient, source_token_account } => {
+                assert_eq!(data.len(), 72);
+                assert_eq!(u64::from_le_bytes(data[0..8].try_into().unwrap()), amount);
+                assert_eq!(Pubkey::try_from(&data[8..40]).unwrap(), recipient);
+                assert_eq!(Pubkey::try_from(&data[40..72]).unwrap(), source_token_account);
+            }
+            ProposalPayload::SetTierTable { tiers } => {
+                assert_eq!(TierTable::parse_tiers(&data, &StakePool::zeroed()).unwrap(), tiers);
+            }
+        }
+    }
+}
+
+#[test]
+fn tier_tables_over_the_limit_are_refused() {
+    let payload = ProposalPayload::SetTierTable { tiers: tiers(MAX_TIERS as u64 + 1) };
+    assert!(matches!(payload.encode(), Err(ClientError::TooManyTiers(count)) if count == MAX_TIERS + 1));
+}
+
+#[test]
+fn truncated_payloads_are_rejected() {
+    for payload in payloads() {
+        let data = payload.encode().unwrap();
+        if data.is_empty() {
+            continue;
+        }
+        assert!(ProposalPayload::decode(payload.proposal_type(), &data[..data.len() - 1]).is_err());
+    }
+    assert!(ProposalPayload::decode(ProposalType::UpdateTeamWallet, &[]).is_err());
+}
+
+// Seeds as declared in the program's account constraints
+#[test]
+fn pdas_match_the_program_seeds() {
+    let mint = Pubkey::new_unique();
+    let owner = Pubkey::new_unique();
+    let derive = |seeds: &[&[u8]]| Pubkey::find_program_address(seeds, &ID);
+
+    // Pool 0 keeps the seeds from before multiple pools per mint
+    let legacy_pool = derive(&[b"stake_pool", mint.as_ref()]);
+    assert_eq!(pda::find_stake_pool(&mint, 0), legacy_pool);
+    assert_eq!(
+        pda::find_stake_pool(&mint, 3),
+        derive(&[b"stake_pool", mint.as_ref(), StakePool::pool_id_seed(3).as_slice()])
+    );
+    assert_ne!(pda::find_stake_pool(&mint, 3), legacy_pool);
+
+    let pool = legacy_pool.0;
+    let user_stake = derive(&[b"user_stake", pool.as_ref(), owner.as_ref()]);
+    let multisig_config = derive(&[b"multisig_config", pool.as_ref()]);
+    assert_eq!(pda::find_pool_registry(&mint), derive(&[b"pool_registry", mint.as_ref()]));
+    assert_eq!(pda::find_tier_table(&pool), derive(&[b"tier_table", pool.as_ref()]));
+    assert_eq!(pda::find_user_stake(&pool, &owner), user_stake);
+    assert_eq!(
+        pda::find_stake_checkpoints(&user_stake.0),
+        derive(&[b"stake_checkpoints", user_stake.0.as_ref()])
+    );
+    assert_eq!(pda::find_pool_checkpoints(&pool), derive(&[b"pool_checkpoints", pool.as_ref()]));
+    assert_eq!(pda::find_multisig_config(&pool), multisig_config);
+    assert_eq!(
+        pda::find_proposal(&multisig_config.0, 5),
+        derive(&[b"proposal", multisig_config.0.as_ref(), &5u64.to_le_bytes()])
+    );
+    assert_eq!(
+        pda::find_proposal_record(&multisig_config.0, 5),
+        derive(&[b"proposal_record", multisig_config.0.as_ref(), &5u64.to_le_bytes()])
+    );
+}
@@ -0,0 +1,260 @@
+// Anchor event parsing from transaction logs
+use anchor_lang::{AnchorDeserialize, Discriminator};
+use base64::Engine;
+use cfx_stake_client::cfx_stake_core::{
+    AccountMigratedEvent, AdminWithdrawEvent, AuthorityUpdatedEvent, AutoWithdrawUpdatedEvent, CheckpointsInitializedEvent,
+    CircuitBreakerResetEvent, CircuitBreakerTrippedEvent, CircuitBreakerUpdatedEvent, CrankWithdrawEvent,
+    LockExtendedEvent,
+    MultisigInitializedEvent, OutflowCapUpdatedEvent, PauseEvent, PoolInitializedEvent, PositionTransferredEvent,
+    ProposalClosedEvent, ProposalCreatedEvent, ProposalExecutedEvent, ProposalSignedEvent, StakeEvent,
+    StakeForEvent, TierTableInitializedEvent, TierTableUpdatedEvent, TierVerifiedEvent, TokensRescuedEvent,
+    TransferConsentUpdatedEvent, UserStakeClosedEvent, UserStakeCreatedEvent, VaultSyncedEvent, VestedStakeEvent,
+    WithdrawEvent, WithdrawalRequestEvent,
+};
+use solana_sdk::pubkey::Pubkey;
+
+/// Program events, each tagged with the pool it concerns
+pub enum ProgramEvent {
+    Stake(StakeEvent),
+    StakeFor(StakeForEvent),
+    VestedStake(VestedStakeEvent),
+    PositionTransferred(PositionTransferredEvent),
+    Withdraw(WithdrawEvent),
+    WithdrawalRequest(WithdrawalRequestEvent),
+    LockExtended(LockExtendedEvent),
+    UserStakeCreated(UserStakeCreatedEvent),
+    UserStakeClosed(UserStakeClosedEvent),
+    Pause(PauseEvent),
+    AdminWithdraw(AdminWithdrawEvent),
+    VaultSynced(VaultSyncedEvent),
+    CircuitBreakerTripped(CircuitBreakerTrippedEvent),
+    TokensRescued(TokensRescuedEvent),
+    PoolInitialized(PoolInitializedEvent),
+    MultisigInitialized(MultisigInitializedEvent),
+    ProposalCreated(ProposalCreatedEvent),
+    ProposalSigned(ProposalSignedEvent),
+    ProposalExecuted(ProposalExecutedEvent),
+    ProposalClosed(ProposalClosedEvent),
+    AuthorityUpdated(AuthorityUpdatedEvent),
+    TransferConsentUpdated(TransferConsentUpdatedEvent),
+    OutflowCapUpdated(OutflowCapUpdatedEvent),
+    CircuitBreakerUpdated(CircuitBreakerUpdatedEvent),
+    CircuitBreakerReset(CircuitBreakerResetEvent),
+    AutoWithdrawUpdated(AutoWithdrawUpdatedEvent),
+    CrankWithdraw(CrankWithdrawEvent),
+    TierTableInitialized(TierTableInitializedEvent),
+    TierTableUpdated(TierTableUpdatedEvent),
+    TierVerified(TierVerifiedEvent),
+    CheckpointsInitialized(CheckpointsInitializedEvent),
+    AccountMigrated(AccountMigratedEvent),
+}
+
+// Evaluate `$body` with `$e` bound to the inner event of any variant
+macro_rules! with_event {
+    ($event:expr, $e:ident => $body:expr) => {
+        match $event {
+            ProgramEvent::Stake($e) => $body,
+            ProgramEvent::StakeFor($e) => $body,
+            ProgramEvent::VestedStake($e) => $body,
+            ProgramEvent::PositionTransferred($e) => $body,
+            ProgramEvent::Withdraw($e) => $body,
+            ProgramEvent::WithdrawalRequest($e) => $body,
+            ProgramEvent::LockExtended($e) => $body,
+            ProgramEvent::UserStakeCreated($e) => $body,
+            ProgramEvent::UserStakeClosed($e) => $body,
+            ProgramEvent::Pause($e) => $body,
+            ProgramEvent::AdminWithdraw($e) => $body,
+            ProgramEvent::VaultSynced($e) => $body,
+            ProgramEvent::CircuitBreakerTripped($e) => $body,
+            ProgramEvent::TokensRescued($e) => $body,
+            ProgramEvent::PoolInitialized($e) => $body,
+            ProgramEvent::MultisigInitialized($e) => $body,
+            ProgramEvent::ProposalCreated($e) => $body,
+            ProgramEvent::ProposalSigned($e) => $body,
+            ProgramEvent::ProposalExecuted($e) => $body,
+            ProgramEvent::ProposalClosed($e) => $body,
+            ProgramEvent::AuthorityUpdated($e) => $body,
+            ProgramEvent::TransferConsentUpdated($e) => $body,
+            ProgramEvent::OutflowCapUpdated($e) => $body,
+            ProgramEvent::CircuitBreakerUpdated($e) => $body,
+            ProgramEvent::CircuitBreakerReset($e) => $body,
+            ProgramEvent::AutoWithdrawUpdated($e) => $body,
+            ProgramEvent::CrankWithdraw($e) => $body,
+            ProgramEvent::TierTableInitialized($e) => $body,
+            ProgramEvent::TierTableUpdated($e) => $body,
+            ProgramEvent::TierVerified($e) => $body,
+            ProgramEvent::CheckpointsInitialized($e) => $body,
+            ProgramEvent::AccountMigrated($e) => $body,
+        }
+    };
+}
+
+fn decode<T: AnchorDeserialize + Discriminator>(data: &[u8]) -> Option<T> {
+    if data.len() < 8 || data[..8] != T::DISCRIMINATOR {
+        return None;
+    }
+    T::deserialize(&mut &data[8..]).ok()
+}
+
+impl ProgramEvent {
+    /// Decode an event from the bytes of a `Program data:` log line. Returns
+    /// `None` for anything that is not one of the program's pool events.
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        None
+            .or_else(|| decode(data).map(Self::Stake))
+            .or_else(|| decode(data).map(Self::StakeFor))
+            .or_else(|| decode(data).map(Self::VestedStake))
+            .or_else(|| decode(data).map(Self::PositionTransferred))
+            .or_else(|| decode(data).map(Self::Withdraw))
+            .or_else(|| decode(data).map(Self::WithdrawalRequest))
+            .or_else(|| decode(data).map(Self::LockExtended))
+            .or_else(|| decode(data).map(Self::UserStakeCreated))
+            .or_else(|| decode(data).map(Self::UserStakeClosed))
+            .or_else(|| decode(data).map(Self::Pause))
+            .or_else(|| decode(data).map(Self::AdminWithdraw))
+            .or_else(|| decode(data).map(Self::VaultSynced))
+            .or_else(|| decode(data).map(Self::CircuitBreakerTripped))
+            .or_else(|| decode(data).map(Self::TokensRescued))
+            .or_else(|| decode(data).map(Self::PoolInitialized))
+            .or_else(|| decode(data).map(Self::MultisigInitialized))
+            .or_else(|| decode(data).map(Self::ProposalCreated))
+            .or_else(|| decode(data).map(Self::ProposalSigned))
+            .or_else(|| decode(data).map(Self::ProposalExecuted))
+            .or_else(|| decode(data).map(Self::ProposalClosed))
+            .or_else(|| decode(data).map(Self::AuthorityUpdated))
+            .or_else(|| decode(data).map(Self::TransferConsentUpdated))
+            .or_else(|| decode(data).map(Self::OutflowCapUpdated))
+            .or_else(|| decode(data).map(Self::CircuitBreakerUpdated))
+            .or_else(|| decode(data).map(Self::CircuitBreakerReset))
+            .or_else(|| decode(data).map(Self::AutoWithdrawUpdated))
+            .or_else(|| decode(data).map(Self::CrankWithdraw))
+            .or_else(|| decode(data).map(Self::TierTableInitialized))
+            .or_else(|| decode(data).map(Self::TierTableUpdated))
+            .or_else(|| decode(data).map(Self::TierVerified))
+            .or_else(|| decode(data).map(Self::CheckpointsInitialized))
+            .or_else(|| decode(data).map(Self::AccountMigrated))
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Stake(_) => "stake",
+            Self::StakeFor(_) => "stake_for",
+            Self::VestedStake(_) => "vested_stake",
+            Self::PositionTransferred(_) => "position_transferred",
+            Self::Withdraw(_) => "withdraw",
+            Self::WithdrawalRequest(_) => "withdrawal_request",
+            Self::LockExtended(_) => "lock_extended",
+            Self::UserStakeCreated(_) => "user_stake_created",
+            Self::UserStakeClosed(_) => "user_stake_closed",
+            Self::Pause(_) => "pause",
+            Self::AdminWithdraw(_) => "admin_withdraw",
+            Self::VaultSynced(_) => "vault_synced",
+            Self::CircuitBreakerTripped(_) => "circuit_breaker_tripped",
+            Self::TokensRescued(_) => "tokens_rescued",
+            Self::PoolInitialized(_) => "pool_initialized",
+            Self::MultisigInitialized(_) => "multisig_initialized",
+            Self::ProposalCreated(_) => "proposal_created",
+            Self::ProposalSigned(_) => "proposal_signed",
+            Self::ProposalExecuted(_) => "proposal_executed",
+            Self::ProposalClosed(_) => "proposal_closed",
+            Self::AuthorityUpdated(_) => "authority_updated",
+            Self::TransferConsentUpdated(_) => "transfer_consent_updated",
+            Self::OutflowCapUpdated(_) => "outflow_cap_updated",
+            Self::CircuitBreakerUpdated(_) => "circuit_breaker_updated",
+            Self::CircuitBreakerReset(_) => "circuit_breaker_reset",
+            Self::AutoWithdrawUpdated(_) => "auto_withdraw_updated",
+            Self::CrankWithdraw(_) => "crank_withdraw",
+            Self::TierTableInitialized(_) => "tier_table_initialized",
+            Self::TierTableUpdated(_) => "tier_table_updated",
+            Self::TierVerified(_) => "tier_verified",
+            Self::CheckpointsInitialized(_) => "checkpoints_initialized",
+            Self::AccountMigrated(_) => "account_migrated",
+        }
+    }
+
+    /// Pool the event concerns
+    pub fn stake_pool(&self) -> Pubkey {
+        with_event!(self, e => e.stake_pool)
+    }
+
+    /// Position of the event in its pool's event sequence. Only instructions
+    /// that write the pool advance the sequence; proposal events are ordered by
+    /// their proposal id and position events by their place in the chain.
+    pub fn seq(&self) -> Option<u64> {
+        match self {
+            Self::Stake(e) => Some(e.seq),
+            Self::StakeFor(e) => Some(e.seq),
+            Self::VestedStake(e) => Some(e.seq),
+            Self::Withdraw(e) => Some(e.seq),
+            Self::WithdrawalRequest(e) => Some(e.seq),
+            Self::Pause(e) => Some(e.seq),
+            Self::AdminWithdraw(e) => Some(e.seq),
+            Self::VaultSynced(e) => Some(e.seq),
+            Self::CircuitBreakerTripped(e) => Some(e.seq),
+            Self::PoolInitialized(e) => Some(e.seq),
+            Self::MultisigInitialized(e) => Some(e.seq),
+            Self::AuthorityUpdated(e) => Some(e.seq),
+            Self::TransferConsentUpdated(e) => Some(e.seq),
+            Self::OutflowCapUpdated(e) => Some(e.seq),
+            Self::CircuitBreakerUpdated(e) => Some(e.seq),
+            Self::CircuitBreakerReset(e) => Some(e.seq),
+            Self::CrankWithdraw(e) => Some(e.seq),
+            Self::AccountMigrated(e) => Some(e.seq),
+            Self::PositionTransferred(e) => Some(e.seq),
+            Self::UserStakeCreated(e) => Some(e.seq),
+            Self::TokensRescued(e) => Some(e.seq),
+            Self::ProposalCreated(e) => Some(e.seq),
+            Self::ProposalSigned(e) => Some(e.seq),
+            Self::ProposalExecuted(e) => Some(e.seq),
+            Self::TierTableUpdated(e) => Some(e.seq),
+            Self::LockExtended(_)
+            | Self::UserStakeClosed(_)
+            | Self::ProposalClosed(_)
+            | Self::AutoWithdrawUpdated(_)
+            | Self::TierTableInitialized(_)
+            | Self::TierVerified(_)
+            | Self::CheckpointsInitialized(_) => None,
+        }
+    }
+
+    /// Slot and unix timestamp recorded by the program when the event was emitted
+    pub fn slot_and_timestamp(&self) -> (u64, i64) {
+        with_event!(self, e => (e.slot, e.timestamp))
+    }
+}
+
+/// Extract the program's events from a transaction's log messages, in emission order.
+///
+/// `Program data:` lines are attributed through the invoke stack, so events
+/// logged by other programs in the same transaction are ignored.
+pub fn parse_logs(program_id: &Pubkey, logs: &[String]) -> Vec<ProgramEvent> {
+    let program = program_id.to_string();
+    let mut stack: Vec<&str> = Vec::new();
+    let mut events = Vec::new();
+
+    for line in logs {
+        let Some(rest) = line.strip_prefix("Program ") else { continue };
+        if let Some(data) = rest.strip_prefix("data: ") {
+            if stack.last() != Some(&program.as_str()) {
+                continue;
+            }
+            let Ok(bytes) = base64::engine::general_purpose::STANDARD.decode(data.trim()) else { continue };
+            if let Some(event) = ProgramEvent::decode(&bytes) {
+                events.push(event);
+            }
+            continue;
+        }
+
+        // "Program <id> invoke [n]", "Program <id> success", "Program <id> failed: ..."
+        let mut words = rest.split_whitespace();
+        let (Some(id), Some(action)) = (words.next(), words.next()) else { continue };
+        match action {
+            "invoke" => stack.push(id),
+            "success" | "failed:" => {
+                stack.pop();
+            }
+            _ => {}
+        }
+    }
+    events
+}
@@ -0,0 +1,11 @@
+//! Event indexer for the Chain-Fox DAO staking program.
+//!
+//! Parses the program's Anchor events out of transaction logs and materializes
+//! per-pool and per-user history in SQLite.
+
+pub mod events;
+pub mod source;
+pub mod store;
+
+pub use events::{parse_logs, ProgramEvent};
+pub use store::{SequenceGap, Store, TimelineEntry, TransactionLogs, TvlPoint};
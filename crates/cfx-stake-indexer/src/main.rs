@@ -0,0 +1,127 @@
+// Indexer CLI: sync from an RPC node or ingest log dumps, then query the history
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use cfx_stake_client::PROGRAM_ID;
+use cfx_stake_indexer::{source, Store, TransactionLogs};
+use clap::{Parser, Subcommand};
+use solana_rpc_client::rpc_client::RpcClient;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+
+#[derive(Parser)]
+#[command(name = "cfx-stake-indexer", version, about = "Index Chain-Fox DAO staking events into SQLite")]
+struct Cli {
+    /// SQLite database file
+    #[arg(long, global = true, default_value = "cfx-stake-index.db")]
+    db: PathBuf,
+
+    /// Staking program id
+    #[arg(long, global = true, default_value_t = PROGRAM_ID)]
+    program_id: Pubkey,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Index new program transactions from an RPC node (a cluster or a local validator)
+    Sync {
+        #[arg(long, short = 'u', default_value = "http://127.0.0.1:8899")]
+        url: String,
+        /// Keep polling for new transactions
+        #[arg(long)]
+        follow: bool,
+        /// Polling interval in seconds
+        #[arg(long, default_value_t = 10)]
+        interval: u64,
+    },
+    /// Index transactions from a JSON-lines file of {"signature", "slot", "blockTime", "logs"} objects, in chain order
+    Ingest { file: PathBuf },
+    /// Pool TVL over time
+    Tvl {
+        #[arg(long)]
+        pool: Pubkey,
+        /// Bucket size in seconds
+        #[arg(long, default_value_t = 86_400)]
+        bucket: i64,
+    },
+    /// A user's stake timeline in a pool
+    Timeline {
+        #[arg(long)]
+        pool: Pubkey,
+        #[arg(long)]
+        user: Pubkey,
+    },
+    /// Breaks found in a pool's event sequence
+    Gaps {
+        #[arg(long)]
+        pool: Pubkey,
+    },
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let mut store = Store::open(&cli.db).with_context(|| format!("failed to open {}", cli.db.display()))?;
+
+    match cli.command {
+        Command::Sync { url, follow, interval } => {
+            let rpc = RpcClient::new_with_commitment(url, CommitmentConfig::confirmed());
+            loop {
+                let indexed = source::sync(&rpc, &mut store, &cli.program_id)?;
+                println!("Indexed {indexed} events");
+                if !follow {
+                    break;
+                }
+                thread::sleep(Duration::from_secs(interval));
+            }
+        }
+        Command::Ingest { file } => {
+            let reader = BufReader::new(File::open(&file).with_context(|| format!("failed to open {}", file.display()))?);
+            let mut indexed = 0;
+            for (number, line) in reader.lines().enumerate() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let tx: TransactionLogs =
+                    serde_json::from_str(&line).with_context(|| format!("invalid transaction on line {}", number + 1))?;
+                indexed += store.apply(&cli.program_id, &tx)?;
+            }
+            println!("Indexed {indexed} events");
+        }
+        Command::Tvl { pool, bucket } => {
+            println!("{:<12} {:<12} {:>20}", "slot", "timestamp", "total_staked");
+            for point in store.tvl_history(&pool, bucket)? {
+                println!("{:<12} {:<12} {:>20}", point.slot, point.timestamp, point.total_staked);
+            }
+        }
+        Command::Timeline { pool, user } => {
+            println!("{:<12} {:<12} {:<22} {:>20} {:>20}  signature", "slot", "timestamp", "event", "change", "staked");
+            for entry in store.user_timeline(&pool, &user)? {
+                println!(
+                    "{:<12} {:<12} {:<22} {:>20} {:>20}  {}",
+                    entry.slot, entry.timestamp, entry.kind, entry.amount, entry.staked, entry.signature
+                );
+            }
+        }
+        Command::Gaps { pool } => {
+            let gaps = store.sequence_gaps(&pool)?;
+            if gaps.is_empty() {
+                println!("No gaps in the event sequence");
+            }
+            for gap in gaps {
+                println!(
+                    "expected seq {} but found {} at slot {} ({})",
+                    gap.expected, gap.found, gap.slot, gap.signature
+                );
+            }
+        }
+    }
+    Ok(())
+}
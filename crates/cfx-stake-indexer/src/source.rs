@@ -0,0 +1,62 @@
+// Fetching program transactions from an RPC node (a cluster or a local validator)
+use std::str::FromStr;
+
+use anyhow::Result;
+use solana_rpc_client::rpc_client::{GetConfirmedSignaturesForAddress2Config, RpcClient};
+use solana_rpc_client_api::config::RpcTransactionConfig;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_transaction_status::UiTransactionEncoding;
+
+use crate::store::{Store, TransactionLogs};
+
+/// Index every program transaction newer than the store's cursor.
+/// Returns the number of events indexed.
+pub fn sync(rpc: &RpcClient, store: &mut Store, program_id: &Pubkey) -> Result<usize> {
+    let commitment = CommitmentConfig::confirmed();
+    let until = store.cursor()?.map(|signature| Signature::from_str(&signature)).transpose()?;
+
+    // Signatures come newest first; page back to the cursor, then replay oldest first
+    let mut pending = Vec::new();
+    let mut before = None;
+    loop {
+        let page = rpc.get_signatures_for_address_with_config(
+            program_id,
+            GetConfirmedSignaturesForAddress2Config {
+                before,
+                until,
+                limit: None,
+                commitment: Some(commitment),
+            },
+        )?;
+        let Some(last) = page.last() else { break };
+        before = Some(Signature::from_str(&last.signature)?);
+        pending.extend(page);
+    }
+
+    let config = RpcTransactionConfig {
+        encoding: Some(UiTransactionEncoding::Json),
+        commitment: Some(commitment),
+        max_supported_transaction_version: Some(0),
+    };
+    let mut indexed = 0;
+    for status in pending.into_iter().rev() {
+        // Failed transactions emit no events
+        if status.err.is_none() {
+            let tx = rpc.get_transaction_with_config(&Signature::from_str(&status.signature)?, config)?;
+            let logs: Option<Vec<String>> = tx.transaction.meta.and_then(|meta| meta.log_messages.into());
+            indexed += store.apply(
+                program_id,
+                &TransactionLogs {
+                    signature: status.signature.clone(),
+                    slot: tx.slot,
+                    block_time: tx.block_time,
+                    logs: logs.unwrap_or_default(),
+                },
+            )?;
+        }
+        store.set_cursor(&status.signature)?;
+    }
+    Ok(indexed)
+}
@@ -0,0 +1,387 @@
+// SQLite storage for indexed events, plus the history queries
+use std::path::Path;
+
+use anyhow::Result;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Deserialize;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::events::{parse_logs, ProgramEvent};
+
+// Amounts are stored as SQLite INTEGERs (i64); base-unit amounts of the staked
+// mint stay far below i64::MAX.
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS transactions (
+    signature   TEXT PRIMARY KEY,
+    slot        INTEGER NOT NULL,
+    block_time  INTEGER
+);
+
+-- One row per event, with the pool's total stake after the event. seq is NULL
+-- for events outside the pool's event sequence.
+CREATE TABLE IF NOT EXISTS pool_events (
+    id            INTEGER PRIMARY KEY AUTOINCREMENT,
+    signature     TEXT NOT NULL,
+    event_index   INTEGER NOT NULL,
+    stake_pool    TEXT NOT NULL,
+    seq           INTEGER,
+    kind          TEXT NOT NULL,
+    actor         TEXT,
+    amount        INTEGER NOT NULL,
+    total_staked  INTEGER NOT NULL,
+    slot          INTEGER NOT NULL,
+    timestamp     INTEGER NOT NULL,
+    UNIQUE (signature, event_index)
+);
+CREATE INDEX IF NOT EXISTS pool_events_by_pool ON pool_events (stake_pool, id);
+
+-- One row per affected position, with the signed change and resulting stake
+CREATE TABLE IF NOT EXISTS user_events (
+    id            INTEGER PRIMARY KEY AUTOINCREMENT,
+    signature     TEXT NOT NULL,
+    event_index   INTEGER NOT NULL,
+    stake_pool    TEXT NOT NULL,
+    user          TEXT NOT NULL,
+    kind          TEXT NOT NULL,
+    amount        INTEGER NOT NULL,
+    staked        INTEGER NOT NULL,
+    slot          INTEGER NOT NULL,
+    timestamp     INTEGER NOT NULL,
+    UNIQUE (signature, event_index, user)
+);
+CREATE INDEX IF NOT EXISTS user_events_by_user ON user_events (stake_pool, user, id);
+
+-- Running state derived from the events
+CREATE TABLE IF NOT EXISTS pools (
+    stake_pool    TEXT PRIMARY KEY,
+    total_staked  INTEGER NOT NULL,
+    last_seq      INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS positions (
+    stake_pool  TEXT NOT NULL,
+    user        TEXT NOT NULL,
+    staked      INTEGER NOT NULL,
+    PRIMARY KEY (stake_pool, user)
+);
+
+-- Breaks in a pool's event sequence, found when an event's seq is not last_seq + 1
+CREATE TABLE IF NOT EXISTS sequence_gaps (
+    id          INTEGER PRIMARY KEY AUTOINCREMENT,
+    stake_pool  TEXT NOT NULL,
+    expected    INTEGER NOT NULL,
+    found       INTEGER NOT NULL,
+    signature   TEXT NOT NULL,
+    slot        INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS sequence_gaps_by_pool ON sequence_gaps (stake_pool, id);
+
+CREATE TABLE IF NOT EXISTS sync_state (
+    key    TEXT PRIMARY KEY,
+    value  TEXT NOT NULL
+);
+";
+
+const CURSOR_KEY: &str = "last_signature";
+
+/// Logs of one confirmed transaction. Also the JSON-lines format accepted by
+/// `cfx-stake-indexer ingest`.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionLogs {
+    pub signature: String,
+    pub slot: u64,
+    pub block_time: Option<i64>,
+    pub logs: Vec<String>,
+}
+
+pub struct TvlPoint {
+    pub slot: u64,
+    pub timestamp: i64,
+    pub total_staked: u64,
+}
+
+pub struct TimelineEntry {
+    pub signature: String,
+    pub kind: String,
+    pub amount: i64,
+    pub staked: u64,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+/// A break in a pool's event sequence: events from `expected` up to (but not
+/// including) `found` were never indexed
+pub struct SequenceGap {
+    pub expected: u64,
+    pub found: u64,
+    pub signature: String,
+    pub slot: u64,
+}
+
+pub struct Store {
+    conn: Connection,
+}
+
+impl Store {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::with_connection(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> Result<Self> {
+        Self::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(conn: Connection) -> Result<Self> {
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self { conn })
+    }
+
+    /// Signature of the newest transaction fetched from the RPC
+    pub fn cursor(&self) -> Result<Option<String>> {
+        Ok(self
+            .conn
+            .query_row("SELECT value FROM sync_state WHERE key = ?1", [CURSOR_KEY], |row| row.get(0))
+            .optional()?)
+    }
+
+    pub fn set_cursor(&self, signature: &str) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO sync_state (key, value) VALUES (?1, ?2)
+             ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+            params![CURSOR_KEY, signature],
+        )?;
+        Ok(())
+    }
+
+    /// Index the program's events in a transaction. Transactions must be applied
+    /// in chain order; already indexed transactions are skipped. Returns the
+    /// number of events indexed.
+    pub fn apply(&mut self, program_id: &Pubkey, tx: &TransactionLogs) -> Result<usize> {
+        let db = self.conn.transaction()?;
+        let inserted = db.execute(
+            "INSERT OR IGNORE INTO transactions (signature, slot, block_time) VALUES (?1, ?2, ?3)",
+            params![tx.signature, tx.slot as i64, tx.block_time],
+        )?;
+        if inserted == 0 {
+            return Ok(0);
+        }
+
+        let events = parse_logs(program_id, &tx.logs);
+        for (index, event) in events.iter().enumerate() {
+            apply_event(&db, &tx.signature, index, event)?;
+        }
+        db.commit()?;
+        Ok(events.len())
+    }
+
+    /// Pool TVL over time: the last total stake of each `bucket_seconds` interval
+    pub fn tvl_history(&self, stake_pool: &Pubkey, bucket_seconds: i64) -> Result<Vec<TvlPoint>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT slot, timestamp, total_staked FROM pool_events
+             WHERE id IN (
+                 SELECT MAX(id) FROM pool_events WHERE stake_pool = ?1 GROUP BY timestamp / ?2
+             )
+             ORDER BY id",
+        )?;
+        let rows = stmt.query_map(params![stake_pool.to_string(), bucket_seconds.max(1)], |row| {
+            Ok(TvlPoint {
+                slot: row.get::<_, i64>(0)? as u64,
+                timestamp: row.get(1)?,
+                total_staked: row.get::<_, i64>(2)? as u64,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Every event that touched a user's position in a pool, oldest first
+    pub fn user_timeline(&self, stake_pool: &Pubkey, user: &Pubkey) -> Result<Vec<TimelineEntry>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT signature, kind, amount, staked, slot, timestamp FROM user_events
+             WHERE stake_pool = ?1 AND user = ?2
+             ORDER BY id",
+        )?;
+        let rows = stmt.query_map(params![stake_pool.to_string(), user.to_string()], |row| {
+            Ok(TimelineEntry {
+                signature: row.get(0)?,
+                kind: row.get(1)?,
+                amount: row.get(2)?,
+                staked: row.get::<_, i64>(3)? as u64,
+                slot: row.get::<_, i64>(4)? as u64,
+                timestamp: row.get(5)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Breaks found in a pool's event sequence, oldest first
+    pub fn sequence_gaps(&self, stake_pool: &Pubkey) -> Result<Vec<SequenceGap>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT expected, found, signature, slot FROM sequence_gaps
+             WHERE stake_pool = ?1
+             ORDER BY id",
+        )?;
+        let rows = stmt.query_map([stake_pool.to_string()], |row| {
+            Ok(SequenceGap {
+                expected: row.get::<_, i64>(0)? as u64,
+                found: row.get::<_, i64>(1)? as u64,
+                signature: row.get(2)?,
+                slot: row.get::<_, i64>(3)? as u64,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+}
+
+// Total stake and last event sequence number of a pool
+fn pool_state(db: &Connection, stake_pool: &Pubkey) -> Result<(i64, u64)> {
+    Ok(db
+        .query_row(
+            "SELECT total_staked, last_seq FROM pools WHERE stake_pool = ?1",
+            [stake_pool.to_string()],
+            |row| Ok((row.get(0)?, row.get::<_, i64>(1)? as u64)),
+        )
+        .optional()?
+        .unwrap_or((0, 0)))
+}
+
+fn position_stake(db: &Connection, stake_pool: &Pubkey, user: &Pubkey) -> Result<i64> {
+    Ok(db
+        .query_row(
+            "SELECT staked FROM positions WHERE stake_pool = ?1 AND user = ?2",
+            [stake_pool.to_string(), user.to_string()],
+            |row| row.get(0),
+        )
+        .optional()?
+        .unwrap_or(0))
+}
+
+fn apply_event(db: &Connection, signature: &str, index: usize, event: &ProgramEvent) -> Result<()> {
+    let kind = event.name();
+    let stake_pool = event.stake_pool();
+    let seq = event.seq();
+    let (slot, timestamp) = event.slot_and_timestamp();
+    let (total, last_seq) = pool_state(db, &stake_pool)?;
+
+    // Sequence numbers start at 1 and increase by one per event
+    if let Some(seq) = seq.filter(|&seq| seq != last_seq + 1) {
+        db.execute(
+            "INSERT INTO sequence_gaps (stake_pool, expected, found, signature, slot) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![stake_pool.to_string(), (last_seq + 1) as i64, seq as i64, signature, slot as i64],
+        )?;
+    }
+
+    // Actor, amount, and the pool's new total stake when the event changes it
+    let (actor, amount, new_total) = match event {
+        ProgramEvent::Stake(e) => (Some(e.user), e.amount_staked, Some(total + e.amount_staked as i64)),
+        ProgramEvent::StakeFor(e) => (Some(e.funder), e.amount_staked, Some(total + e.amount_staked as i64)),
+        ProgramEvent::VestedStake(e) => (Some(e.funder), e.amount_staked, Some(total + e.amount_staked as i64)),
+        ProgramEvent::Withdraw(e) => {
+            (Some(e.user), e.amount_withdrawn, Some((total - e.amount_withdrawn as i64).max(0)))
+        }
+        ProgramEvent::VaultSynced(e) => (None, e.surplus, Some(e.total_staked as i64)),
+        ProgramEvent::PositionTransferred(e) => (Some(e.from), e.amount, None),
+        ProgramEvent::WithdrawalRequest(e) => (Some(e.user), 0, None),
+        ProgramEvent::LockExtended(e) => (Some(e.user), 0, None),
+        ProgramEvent::UserStakeCreated(e) => (Some(e.user), 0, None),
+        ProgramEvent::UserStakeClosed(e) => (Some(e.user), 0, None),
+        ProgramEvent::Pause(e) => (Some(e.authority), u64::from(e.paused), None),
+        ProgramEvent::AdminWithdraw(e) => (Some(e.recipient), e.amount_withdrawn, None),
+        ProgramEvent::CircuitBreakerTripped(e) => (None, e.window_outflow, None),
+        ProgramEvent::TokensRescued(e) => (Some(e.recipient), e.amount, None),
+        ProgramEvent::PoolInitialized(e) => (Some(e.authority), 0, Some(0)),
+        ProgramEvent::MultisigInitialized(e) => (Some(e.authority), 0, None),
+        ProgramEvent::ProposalCreated(e) => (Some(e.proposer), 0, None),
+        ProgramEvent::ProposalSigned(e) => (Some(e.signer), 0, None),
+        ProgramEvent::ProposalExecuted(e) => (Some(e.executor), 0, None),
+        ProgramEvent::ProposalClosed(e) => (Some(e.closer), 0, None),
+        ProgramEvent::AuthorityUpdated(e) => (Some(e.new_authority), 0, None),
+        ProgramEvent::TransferConsentUpdated(e) => (None, u64::from(e.new_required), None),
+        ProgramEvent::OutflowCapUpdated(e) => (None, e.new_cap, None),
+        ProgramEvent::CircuitBreakerUpdated(e) => (None, u64::from(e.new_threshold_bps), None),
+        ProgramEvent::CircuitBreakerReset(e) => (None, e.window_outflow, None),
+        ProgramEvent::AutoWithdrawUpdated(e) => (Some(e.user), u64::from(e.new_keeper_tip_bps), None),
+        ProgramEvent::CrankWithdraw(e) => (Some(e.keeper), e.keeper_tip, None),
+        ProgramEvent::TierTableInitialized(e) => (Some(e.payer), 0, None),
+        ProgramEvent::TierTableUpdated(e) => (None, e.new_tiers.len() as u64, None),
+        ProgramEvent::TierVerified(e) => (Some(e.user), u64::from(e.tier), None),
+        ProgramEvent::CheckpointsInitialized(e) => match e.user {
+            Some(user) => (Some(user), e.amount, None),
+            None => (None, e.amount, Some(e.amount as i64)),
+        },
+        ProgramEvent::AccountMigrated(e) => (Some(e.account), u64::from(e.to_version), None),
+    };
+    let total = new_total.unwrap_or(total);
+
+    db.execute(
+        "INSERT INTO pools (stake_pool, total_staked, last_seq) VALUES (?1, ?2, ?3)
+         ON CONFLICT (stake_pool) DO UPDATE SET total_staked = excluded.total_staked,
+             last_seq = MAX(last_seq, excluded.last_seq)",
+        params![stake_pool.to_string(), total, seq.unwrap_or(last_seq) as i64],
+    )?;
+    db.execute(
+        "INSERT OR IGNORE INTO pool_events
+             (signature, event_index, stake_pool, seq, kind, actor, amount, total_staked, slot, timestamp)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+        params![
+            signature,
+            index as i64,
+            stake_pool.to_string(),
+            seq.map(|seq| seq as i64),
+            kind,
+            actor.map(|actor| actor.to_string()),
+            amount as i64,
+            total,
+            slot as i64,
+            timestamp,
+        ],
+    )?;
+
+    // Position changes: (user, signed change, staked amount after the event when the event reports it)
+    let changes: Vec<(Pubkey, i64, Option<i64>)> = match event {
+        ProgramEvent::Stake(e) => vec![(e.user, e.amount_staked as i64, Some(e.total_staked as i64))],
+        ProgramEvent::StakeFor(e) => vec![(e.beneficiary, e.amount_staked as i64, Some(e.total_staked as i64))],
+        ProgramEvent::VestedStake(e) => vec![(e.beneficiary, e.amount_staked as i64, None)],
+        ProgramEvent::PositionTransferred(e) => {
+            vec![(e.from, -(e.amount as i64), Some(0)), (e.to, e.amount as i64, Some(e.amount as i64))]
+        }
+        ProgramEvent::Withdraw(e) => vec![(e.user, -(e.amount_withdrawn as i64), None)],
+        ProgramEvent::WithdrawalRequest(e) => vec![(e.user, 0, None)],
+        ProgramEvent::LockExtended(e) => vec![(e.user, 0, None)],
+        ProgramEvent::UserStakeCreated(e) => vec![(e.user, 0, Some(0))],
+        ProgramEvent::AutoWithdrawUpdated(e) => vec![(e.user, 0, None)],
+        ProgramEvent::TierVerified(e) => vec![(e.user, 0, Some(e.staked_amount as i64))],
+        ProgramEvent::CheckpointsInitialized(e) => {
+            e.user.map(|user| (user, 0, Some(e.amount as i64))).into_iter().collect()
+        }
+        ProgramEvent::UserStakeClosed(e) => vec![(e.user, 0, Some(0))],
+        _ => Vec::new(),
+    };
+    for (user, change, reported) in changes {
+        let staked = match reported {
+            Some(staked) => staked,
+            None => (position_stake(db, &stake_pool, &user)? + change).max(0),
+        };
+        db.execute(
+            "INSERT INTO positions (stake_pool, user, staked) VALUES (?1, ?2, ?3)
+             ON CONFLICT (stake_pool, user) DO UPDATE SET staked = excluded.staked",
+            params![stake_pool.to_string(), user.to_string(), staked],
+        )?;
+        db.execute(
+            "INSERT OR IGNORE INTO user_events
+                 (signature, event_index, stake_pool, user, kind, amount, staked, slot, timestamp)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                signature,
+                index as i64,
+                stake_pool.to_string(),
+                user.to_string(),
+                kind,
+                change,
+                staked,
+                slot as i64,
+                timestamp,
+            ],
+        )?;
+    }
+    Ok(())
+}
@@ -0,0 +1,157 @@
+// Log parsing and indexing against transaction logs in the RPC `logMessages` format
+use anchor_lang::Event;
+use base64::Engine;
+use cfx_stake_client::cfx_stake_core::{LockExtendedEvent, StakeEvent};
+use cfx_stake_client::PROGRAM_ID;
+use cfx_stake_indexer::{parse_logs, ProgramEvent, Store, TransactionLogs};
+use solana_sdk::pubkey::Pubkey;
+
+const POOL_HISTORY: &str = include_str!("fixtures/pool_history.jsonl");
+const MISSING_EVENT: &str = include_str!("fixtures/missing_event.jsonl");
+
+fn transactions(fixture: &str) -> Vec<TransactionLogs> {
+    fixture.lines().map(|line| serde_json::from_str(line).unwrap()).collect()
+}
+
+fn pool() -> Pubkey {
+    Pubkey::new_from_array([1; 32])
+}
+
+fn user(byte: u8) -> Pubkey {
+    Pubkey::new_from_array([byte; 32])
+}
+
+fn indexed(fixtures: &[&str]) -> Store {
+    let mut store = Store::open_in_memory().unwrap();
+    for fixture in fixtures {
+        for tx in transactions(fixture) {
+            store.apply(&PROGRAM_ID, &tx).unwrap();
+        }
+    }
+    store
+}
+
+#[test]
+fn events_are_attributed_through_the_invoke_stack() {
+    let txs = transactions(POOL_HISTORY);
+
+    // Called directly, with a token CPI before the event
+    let events = parse_logs(&PROGRAM_ID, &txs[0].logs);
+    assert_eq!(events.len(), 1);
+    assert!(matches!(&events[0], ProgramEvent::Stake(e) if e.user == user(2) && e.amount_staked == 500));
+
+    // Called by a router: the router's own event-shaped line after our return is ignored
+    let events = parse_logs(&PROGRAM_ID, &txs[1].logs);
+    assert_eq!(events.len(), 1);
+    assert!(matches!(&events[0], ProgramEvent::Stake(e) if e.user == user(3) && e.seq == 2));
+
+    // Nothing is ours when the program id does not match
+    assert!(parse_logs(&Pubkey::new_unique(), &txs[1].logs).is_empty());
+}
+
+#[test]
+fn unknown_and_malformed_data_lines_are_skipped() {
+    let program = PROGRAM_ID.to_string();
+    let logs = [
+        format!("Program {program} invoke [1]"),
+        "Program data: not base64!".to_string(),
+        "Program data: AAAAAAAAAAAAAAAA".to_string(),
+        format!("Program {program} success"),
+    ];
+    assert!(parse_logs(&PROGRAM_ID, &logs).is_empty());
+}
+
+#[test]
+fn events_update_pool_and_position_state() {
+    let store = indexed(&[POOL_HISTORY]);
+
+    let timeline = store.user_timeline(&pool(), &user(2)).unwrap();
+    let changes: Vec<_> = timeline.iter().map(|entry| (entry.kind.as_str(), entry.amount, entry.staked)).collect();
+    assert_eq!(changes, [("stake", 500, 500), ("withdraw", -200, 300)]);
+
+    let timeline = store.user_timeline(&pool(), &user(3)).unwrap();
+    assert_eq!(timeline.len(), 1);
+    assert_eq!(timeline[0].signature, "sig2");
+    assert_eq!(timeline[0].staked, 300);
+
+    assert!(store.sequence_gaps(&pool()).unwrap().is_empty());
+}
+
+#[test]
+fn transactions_are_indexed_once() {
+    let mut store = indexed(&[POOL_HISTORY]);
+    let tx = &transactions(POOL_HISTORY)[0];
+    assert_eq!(store.apply(&PROGRAM_ID, tx).unwrap(), 0);
+    assert_eq!(store.user_timeline(&pool(), &user(2)).unwrap().len(), 2);
+}
+
+#[test]
+fn tvl_keeps_the_last_total_of_each_bucket() {
+    let store = indexed(&[POOL_HISTORY, MISSING_EVENT]);
+
+    let daily: Vec<_> = store
+        .tvl_history(&pool(), 86_400)
+        .unwrap()
+        .iter()
+        .map(|point| (point.slot, point.timestamp, point.total_staked))
+        .collect();
+    assert_eq!(daily, [(150, 1_050, 800), (400, 90_100, 700)]);
+
+    let every_event: Vec<_> =
+        store.tvl_history(&pool(), 1).unwrap().iter().map(|point| point.total_staked).collect();
+    assert_eq!(every_event, [500, 800, 600, 700]);
+}
+
+#[test]
+fn skipped_sequence_numbers_are_recorded() {
+    let store = indexed(&[POOL_HISTORY, MISSING_EVENT]);
+
+    let gaps = store.sequence_gaps(&pool()).unwrap();
+    assert_eq!(gaps.len(), 1);
+    assert_eq!((gaps[0].expected, gaps[0].found), (4, 5));
+    assert_eq!((gaps[0].signature.as_str(), gaps[0].slot), ("sig5", 400));
+}
+
+fn program_logs(events: &[Vec<u8>]) -> Vec<String> {
+    let program = PROGRAM_ID.to_string();
+    let mut logs = vec![format!("Program {program} invoke [1]")];
+    logs.extend(
+        events
+            .iter()
+            .map(|data| format!("Program data: {}", base64::engine::general_purpose::STANDARD.encode(data))),
+    );
+    logs.push(format!("Program {program} success"));
+    logs
+}
+
+#[test]
+fn events_outside_the_pool_sequence_leave_no_gaps() {
+    let mut store = indexed(&[POOL_HISTORY]);
+    let extended = LockExtendedEvent {
+        user: user(2),
+        stake_pool: pool(),
+        old_lock_end: 1_000,
+        new_lock_end: 2_000,
+        voting_power: 300,
+        slot: 500,
+        timestamp: 91_000,
+    };
+    let stake = StakeEvent {
+        user: user(2),
+        stake_pool: pool(),
+        amount_staked: 100,
+        total_staked: 400,
+        seq: 4,
+        slot: 500,
+        timestamp: 91_000,
+    };
+    let tx = TransactionLogs {
+        signature: "sig4".to_string(),
+        slot: 500,
+        block_time: Some(91_000),
+        logs: program_logs(&[extended.data(), stake.data()]),
+    };
+
+    assert_eq!(store.apply(&PROGRAM_ID, &tx).unwrap(), 2);
+    assert!(store.sequence_gaps(&pool()).unwrap().is_empty());
+}
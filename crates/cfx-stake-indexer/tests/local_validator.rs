@@ -0,0 +1,20 @@
+// End-to-end sync against a running validator with the program deployed and
+// some pool activity, e.g. after `anchor test --detach`:
+//
+//     CFX_STAKE_RPC_URL=http://127.0.0.1:8899 cargo test -p cfx-stake-indexer -- --ignored
+use cfx_stake_client::PROGRAM_ID;
+use cfx_stake_indexer::{source, Store};
+use solana_rpc_client::rpc_client::RpcClient;
+use solana_sdk::commitment_config::CommitmentConfig;
+
+#[test]
+#[ignore = "needs a local validator with the staking program deployed"]
+fn sync_indexes_the_program_history() {
+    let url = std::env::var("CFX_STAKE_RPC_URL").unwrap_or_else(|_| "http://127.0.0.1:8899".to_string());
+    let rpc = RpcClient::new_with_commitment(url, CommitmentConfig::confirmed());
+    let mut store = Store::open_in_memory().unwrap();
+
+    let indexed = source::sync(&rpc, &mut store, &PROGRAM_ID).unwrap();
+    assert!(indexed > 0, "no program events found; run the program tests against this validator first");
+    assert!(store.cursor().unwrap().is_some());
+}
@@ -0,0 +1,157 @@
+//! Stable CPI interface to the Chain-Fox DAO staking program.
+//!
+//! Other programs gate features on CFX stake by invoking `get_position_snapshot`
+//! and decoding the returned [`PositionSnapshot`], without depending on the
+//! staking program itself or on the layout of its accounts.
+//!
+//! Compatibility guarantees:
+//! - The instruction's discriminator and account list never change.
+//! - Snapshot fields keep their offsets and meaning in every later version.
+//!   New fields are only appended, together with a `version` bump.
+//! - Decoding accepts any version at or above the one it was built for and
+//!   ignores trailing bytes, so callers built against an older version keep
+//!   working after a program upgrade.
+
+use solana_program::account_info::AccountInfo;
+use solana_program::instruction::{AccountMeta, Instruction};
+use solana_program::program::{get_return_data, invoke};
+use solana_program::program_error::ProgramError;
+use solana_program::pubkey::Pubkey;
+
+#[cfg(test)]
+mod tests;
+
+// Must match `declare_id!` in cfx-stake-core
+solana_program::declare_id!("HupexUsRkmBGiFxSM14JwUJs7ADJNFfQ6UygRuKrHyp8");
+
+/// Anchor discriminator of `get_position_snapshot` (`sha256("global:get_position_snapshot")[..8]`)
+pub const GET_POSITION_SNAPSHOT_DISCRIMINATOR: [u8; 8] = [1, 252, 67, 35, 33, 80, 162, 180];
+
+/// Lock clock of the pool, which gives `lock_end` its unit
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LockClock {
+    Slot,
+    UnixTimestamp,
+}
+
+/// A stake position as seen by the staking program at the time of the call
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PositionSnapshot {
+    pub version: u8,
+    pub owner: Pubkey,
+    pub stake_pool: Pubkey,
+    pub staked_amount: u64,             // Base units
+    pub lock_end: u64,                  // Explicit lock end in `lock_clock` units, 0 if none
+    pub lock_clock: LockClock,
+    pub tier: u8,                       // Service tier right now (0 = none or no tier table)
+    pub voting_power: u64,              // Staked amount boosted by the remaining lock
+    pub withdrawal_requested: bool,
+}
+
+impl PositionSnapshot {
+    pub const VERSION: u8 = 1;
+
+    // version(1) + owner(32) + stake_pool(32) + staked_amount(8) + lock_end(8) +
+    // lock_clock(1) + tier(1) + voting_power(8) + withdrawal_requested(1)
+    pub const LEN: usize = 1 + 32 + 32 + 8 + 8 + 1 + 1 + 8 + 1;
+
+    /// Encode in the current layout (little-endian, no padding)
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(Self::LEN);
+        data.push(self.version);
+        data.extend_from_slice(self.owner.as_ref());
+        data.extend_from_slice(self.stake_pool.as_ref());
+        data.extend_from_slice(&self.staked_amount.to_le_bytes());
+        data.extend_from_slice(&self.lock_end.to_le_bytes());
+        data.push(match self.lock_clock {
+            LockClock::Slot => 0,
+            LockClock::UnixTimestamp => 1,
+        });
+        data.push(self.tier);
+        data.extend_from_slice(&self.voting_power.to_le_bytes());
+        data.push(u8::from(self.withdrawal_requested));
+        data
+    }
+
+    /// Decode a snapshot of this version or any later one
+    pub fn try_from_bytes(data: &[u8]) -> Result<Self, ProgramError> {
+        if data.len() < Self::LEN || data[0] < Self::VERSION {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let pubkey_at = |offset: usize| Pubkey::try_from(&data[offset..offset + 32]).unwrap();
+        let u64_at = |offset: usize| u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap());
+        let lock_clock = match data[81] {
+            0 => LockClock::Slot,
+            1 => LockClock::UnixTimestamp,
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
+
+        Ok(Self {
+            version: data[0],
+            owner: pubkey_at(1),
+            stake_pool: pubkey_at(33),
+            staked_amount: u64_at(65),
+            lock_end: u64_at(73),
+            lock_clock,
+            tier: data[82],
+            voting_power: u64_at(83),
+            withdrawal_requested: data[91] != 0,
+        })
+    }
+}
+
+/// Stake position of `owner` in `stake_pool`
+pub fn find_user_stake(stake_pool: &Pubkey, owner: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"user_stake", stake_pool.as_ref(), owner.as_ref()], &ID)
+}
+
+/// Service tier table of `stake_pool`; pass it even if the pool never created one
+pub fn find_tier_table(stake_pool: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"tier_table", stake_pool.as_ref()], &ID)
+}
+
+/// `get_position_snapshot` instruction for `owner`'s position in `stake_pool`
+pub fn get_position_snapshot(stake_pool: &Pubkey, owner: &Pubkey) -> Instruction {
+    Instruction {
+        program_id: ID,
+        accounts: vec![
+            AccountMeta::new_readonly(find_user_stake(stake_pool, owner).0, false),
+            AccountMeta::new_readonly(*stake_pool, false),
+            AccountMeta::new_readonly(find_tier_table(stake_pool).0, false),
+        ],
+        data: GET_POSITION_SNAPSHOT_DISCRIMINATOR.to_vec(),
+    }
+}
+
+/// Invoke `get_position_snapshot` from another program and decode the result.
+///
+/// `user_stake` and `tier_table` must be the PDAs from [`find_user_stake`] and
+/// [`find_tier_table`]; the staking program rejects anything else.
+pub fn get_position_snapshot_cpi<'a>(
+    program: &AccountInfo<'a>,
+    user_stake: &AccountInfo<'a>,
+    stake_pool: &AccountInfo<'a>,
+    tier_table: &AccountInfo<'a>,
+) -> Result<PositionSnapshot, ProgramError> {
+    if !check_id(program.key) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let instruction = Instruction {
+        program_id: ID,
+        accounts: vec![
+            AccountMeta::new_readonly(*user_stake.key, false),
+            AccountMeta::new_readonly(*stake_pool.key, false),
+            AccountMeta::new_readonly(*tier_table.key, false),
+        ],
+        data: GET_POSITION_SNAPSHOT_DISCRIMINATOR.to_vec(),
+    };
+    invoke(
+        &instruction,
+        &[user_stake.clone(), stake_pool.clone(), tier_table.clone(), program.clone()],
+    )?;
+
+    match get_return_data() {
+        Some((program_id, data)) if program_id == ID => PositionSnapshot::try_from_bytes(&data),
+        _ => Err(ProgramError::InvalidAccountData),
+    }
+}
@@ -0,0 +1,69 @@
+use solana_program::hash::hash;
+use solana_program::program_error::ProgramError;
+use solana_program::pubkey::Pubkey;
+
+use crate::{LockClock, PositionSnapshot, GET_POSITION_SNAPSHOT_DISCRIMINATOR};
+
+fn snapshot() -> PositionSnapshot {
+    PositionSnapshot {
+        version: PositionSnapshot::VERSION,
+        owner: Pubkey::new_from_array([0x11; 32]),
+        stake_pool: Pubkey::new_from_array([0x22; 32]),
+        staked_amount: 0x0102_0304_0506_0708,
+        lock_end: 0x1000,
+        lock_clock: LockClock::UnixTimestamp,
+        tier: 3,
+        voting_power: 0xffff,
+        withdrawal_requested: true,
+    }
+}
+
+#[test]
+fn discriminator_is_the_anchor_sighash() {
+    let sighash = hash(b"global:get_position_snapshot");
+    assert_eq!(GET_POSITION_SNAPSHOT_DISCRIMINATOR, sighash.to_bytes()[..8]);
+}
+
+#[test]
+fn snapshot_layout_is_pinned() {
+    let mut expected = vec![1];
+    expected.extend_from_slice(&[0x11; 32]);
+    expected.extend_from_slice(&[0x22; 32]);
+    expected.extend_from_slice(&[0x08, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02, 0x01]);
+    expected.extend_from_slice(&[0x00, 0x10, 0, 0, 0, 0, 0, 0]);
+    expected.extend_from_slice(&[1, 3]);
+    expected.extend_from_slice(&[0xff, 0xff, 0, 0, 0, 0, 0, 0]);
+    expected.push(1);
+
+    let data = snapshot().to_bytes();
+    assert_eq!(data, expected);
+    assert_eq!(data.len(), PositionSnapshot::LEN);
+    assert_eq!(PositionSnapshot::try_from_bytes(&data).unwrap(), snapshot());
+}
+
+#[test]
+fn short_or_older_snapshots_are_rejected() {
+    let data = snapshot().to_bytes();
+    for len in 0..PositionSnapshot::LEN {
+        assert_eq!(PositionSnapshot::try_from_bytes(&data[..len]), Err(ProgramError::InvalidAccountData));
+    }
+
+    let mut older = data.clone();
+    older[0] = PositionSnapshot::VERSION - 1;
+    assert_eq!(PositionSnapshot::try_from_bytes(&older), Err(ProgramError::InvalidAccountData));
+
+    let mut bad_clock = data;
+    bad_clock[81] = 2;
+    assert_eq!(PositionSnapshot::try_from_bytes(&bad_clock), Err(ProgramError::InvalidAccountData));
+}
+
+#[test]
+fn longer_and_newer_snapshots_decode() {
+    // A later version appends fields and bumps `version`
+    let mut newer = snapshot().to_bytes();
+    newer[0] = PositionSnapshot::VERSION + 1;
+    newer.extend_from_slice(&[0xaa; 24]);
+
+    let decoded = PositionSnapshot::try_from_bytes(&newer).unwrap();
+    assert_eq!(decoded, PositionSnapshot { version: PositionSnapshot::VERSION + 1, ..snapshot() });
+}
@@ -0,0 +1,206 @@
+// Keeper: finds opted-in positions whose withdrawal has matured and cranks them
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use cfx_stake_client::cfx_stake_core::token_interface::associated_token_address;
+use cfx_stake_client::cfx_stake_core::{StakePool, UserStake};
+use cfx_stake_client::instructions::{self, PoolAccounts};
+use cfx_stake_client::{accounts, pda};
+use clap::Parser;
+use solana_rpc_client::rpc_client::RpcClient;
+use solana_sdk::clock::Clock;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::instruction::{AccountMeta, Instruction};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{read_keypair_file, Keypair, Signer};
+use solana_sdk::transaction::Transaction;
+use solana_sdk::{system_program, sysvar};
+
+#[derive(Parser)]
+#[command(name = "cfx-stake-keeper", version, about = "Complete matured withdrawals for positions that opted in")]
+struct Cli {
+    /// RPC URL
+    #[arg(long, short = 'u', default_value = "http://127.0.0.1:8899")]
+    url: String,
+
+    /// Keeper keypair; pays the fees and receives the tips [default: ~/.config/solana/id.json]
+    #[arg(long, short = 'k')]
+    keypair: Option<PathBuf>,
+
+    /// Only crank positions of this pool
+    #[arg(long)]
+    pool: Option<Pubkey>,
+
+    /// Skip positions whose keeper tip is below this (basis points)
+    #[arg(long, default_value_t = 0)]
+    min_tip_bps: u16,
+
+    /// List matured positions without sending transactions
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Keep scanning
+    #[arg(long)]
+    follow: bool,
+
+    /// Scan interval in seconds
+    #[arg(long, default_value_t = 30)]
+    interval: u64,
+}
+
+fn default_keypair_path() -> Result<PathBuf> {
+    let home = std::env::var_os("HOME").ok_or_else(|| anyhow!("HOME is not set, pass --keypair"))?;
+    Ok(PathBuf::from(home).join(".config/solana/id.json"))
+}
+
+// Associated token program `CreateIdempotent`, so a keeper without a token
+// account for the pool mint can still collect its tip
+fn create_associated_token_account_idempotent(
+    payer: &Pubkey,
+    owner: &Pubkey,
+    mint: &Pubkey,
+    token_program: &Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id: anchor_spl::associated_token::ID,
+        accounts: vec![
+            AccountMeta::new(*payer, true),
+            AccountMeta::new(associated_token_address(owner, mint, token_program), false),
+            AccountMeta::new_readonly(*owner, false),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new_readonly(system_program::ID, false),
+            AccountMeta::new_readonly(*token_program, false),
+        ],
+        data: vec![1],
+    }
+}
+
+// Amount a crank would release now (capped at what the request reserved in the
+// outflow queue), or `None` if the withdrawal has not matured
+fn matured_amount(pool: &StakePool, user_stake: &UserStake, clock: &Clock) -> Option<u64> {
+    if !user_stake.withdrawal_requested || user_stake.staked_amount == 0 || pool.breaker_tripped != 0 {
+        return None;
+    }
+    if !pool.is_unlocked(user_stake, clock) {
+        return None;
+    }
+    Some(user_stake.payout_amount(pool, clock)).filter(|amount| *amount > 0)
+}
+
+struct Keeper {
+    rpc: RpcClient,
+    keypair: Keypair,
+    cli: Cli,
+}
+
+impl Keeper {
+    /// Scan once and crank every matured position. Returns the number cranked.
+    /// Only a failed scan is an error; a pool or position that cannot be
+    /// loaded or cranked is reported and skipped.
+    fn run_once(&self) -> Result<usize> {
+        let positions = accounts::fetch_auto_withdraw_positions(&self.rpc, self.cli.pool.as_ref())?;
+        let clock: Clock = bincode::deserialize(&self.rpc.get_account(&sysvar::clock::ID)?.data)?;
+        let mut pools: HashMap<Pubkey, Option<StakePool>> = HashMap::new();
+        let mut cranked = 0;
+
+        for (address, user_stake) in positions {
+            if user_stake.keeper_tip_bps < self.cli.min_tip_bps {
+                continue;
+            }
+            let pool = match pools.entry(user_stake.stake_pool) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    let pool = accounts::fetch_stake_pool(&self.rpc, &user_stake.stake_pool);
+                    if let Err(err) = &pool {
+                        eprintln!("pool {}: skipped, failed to load: {err}", user_stake.stake_pool);
+                    }
+                    entry.insert(pool.ok())
+                }
+            };
+            let Some(pool) = pool else { continue };
+            let Some(amount) = matured_amount(pool, &user_stake, &clock) else { continue };
+
+            println!("{address}: {amount} matured for {} (tip {} bps)", user_stake.owner, user_stake.keeper_tip_bps);
+            if self.cli.dry_run {
+                continue;
+            }
+            match self.crank(&PoolAccounts::new(user_stake.stake_pool, pool), &user_stake) {
+                Ok(signature) => {
+                    println!("  cranked: {signature}");
+                    cranked += 1;
+                }
+                Err(err) => eprintln!("{address}: failed: {err}"),
+            }
+        }
+        Ok(cranked)
+    }
+
+    fn crank(&self, pool: &PoolAccounts, user_stake: &UserStake) -> Result<String> {
+        let keeper = self.keypair.pubkey();
+
+        // The payout goes to the account the owner registered, which must still exist
+        let payout_token_account = user_stake.payout_token_account;
+        if self.rpc.get_account(&payout_token_account).is_err() {
+            return Err(anyhow!("registered payout account {payout_token_account} does not exist"));
+        }
+
+        // Positions from before balance checkpoints need them initialized first
+        let checkpoints = pda::find_stake_checkpoints(&pda::find_user_stake(&pool.stake_pool, &user_stake.owner).0).0;
+        if self.rpc.get_account(&checkpoints).is_err() {
+            return Err(anyhow!("position has no balance checkpoints {checkpoints}, run init-checkpoints first"));
+        }
+
+        let keeper_token_account = associated_token_address(&keeper, &pool.token_mint, &pool.token_program);
+        let mut ixs = Vec::new();
+        if user_stake.keeper_tip_bps > 0 {
+            ixs.push(create_associated_token_account_idempotent(
+                &keeper,
+                &keeper,
+                &pool.token_mint,
+                &pool.token_program,
+            ));
+        }
+        ixs.push(instructions::crank_withdraw(
+            pool,
+            &user_stake.owner,
+            &payout_token_account,
+            &keeper,
+            &keeper_token_account,
+        ));
+
+        let blockhash = self.rpc.get_latest_blockhash()?;
+        let transaction = Transaction::new_signed_with_payer(&ixs, Some(&keeper), &[&self.keypair], blockhash);
+        Ok(self.rpc.send_and_confirm_transaction(&transaction)?.to_string())
+    }
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let path = match &cli.keypair {
+        Some(path) => path.clone(),
+        None => default_keypair_path()?,
+    };
+    let keypair =
+        read_keypair_file(&path).map_err(|err| anyhow!("failed to read keypair {}: {err}", path.display()))?;
+    let rpc = RpcClient::new_with_commitment(cli.url.clone(), CommitmentConfig::confirmed());
+    let keeper = Keeper { rpc, keypair, cli };
+
+    loop {
+        match keeper.run_once() {
+            Ok(cranked) if !keeper.cli.dry_run => println!("Cranked {cranked} withdrawals"),
+            Ok(_) => {}
+            // A failed scan (e.g. the RPC node being unreachable) is retried on the next pass
+            Err(err) if keeper.cli.follow => eprintln!("scan failed: {err}"),
+            Err(err) => return Err(err),
+        }
+        if !keeper.cli.follow {
+            break;
+        }
+        thread::sleep(Duration::from_secs(keeper.cli.interval));
+    }
+    Ok(())
+}
@@ -1,22 +1,84 @@
+// Toolchain only: every Anchor `Result` carries the large `anchor_lang::error::Error`
+#![allow(clippy::result_large_err)]
+
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Token, TokenAccount, Transfer};
+use anchor_lang::system_program::{self, CreateAccount};
+use anchor_lang::Discriminator;
+use cfx_stake_interface::PositionSnapshot;
+use solana_program::hash::hash;
+use solana_program::program::set_return_data;
 use solana_program::pubkey::Pubkey;
 
+pub mod token_interface;
+
+#[cfg(test)]
+mod tests;
+
+use token_interface::{InitializeAccount3, TransferChecked};
+
+// Also hardcoded in cfx-stake-interface
 declare_id!("HupexUsRkmBGiFxSM14JwUJs7ADJNFfQ6UygRuKrHyp8");
 
 // Constants definition
-const MIN_STAKE_AMOUNT: u64 = 10_000 * 1_000_000; // 10,000 CFX (6 decimals)
+// Default pool limits, expressed in whole tokens and scaled by the mint decimals stored on the pool
+const MIN_STAKE_TOKENS: u64 = 10_000; // 10,000 CFX
+
+// Maximum individual stake amount
+const MAX_INDIVIDUAL_STAKE_TOKENS: u64 = 100_000_000; // 100,000,000 CFX
+
+
+const MIN_UNSTAKE_TOKENS: u64 = 10_000; // 10,000 CFX
+// Maximum individual unstake amount
+const MAX_INDIVIDUAL_UNSTAKE_TOKENS: u64 = 100_000_000; // 100,000,000 CFX
+
+// Maximum pool size
+const MAX_POOL_SIZE_TOKENS: u64 = 900_000_000; // 900,000,000 CFX
 
-// Maximum individual stake amount (100,000,000 CFX with 6 decimals)
-const MAX_INDIVIDUAL_STAKE: u64 = 100_000_000 * 1_000_000; // 100,000,000 CFX
+// Maximum number of pools listed in a mint's pool registry
+const MAX_POOLS_PER_MINT: usize = 16;
 
+// Maximum number of service tiers in a pool's tier table
+pub const MAX_TIERS: usize = 8;
+
+// Balance checkpoints kept per position and per pool; the oldest are overwritten
+const USER_CHECKPOINTS: usize = 32;
+const POOL_CHECKPOINTS: usize = 512;
+
+// Per-pool staking limits, in whole tokens (stored in the zero-copy StakePool)
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct PoolLimits {
+    pub min_stake: u64,
+    pub max_individual_stake: u64,
+    pub min_unstake: u64,
+    pub max_individual_unstake: u64,
+    pub max_pool_size: u64,
+}
+
+impl Default for PoolLimits {
+    fn default() -> Self {
+        Self {
+            min_stake: MIN_STAKE_TOKENS,
+            max_individual_stake: MAX_INDIVIDUAL_STAKE_TOKENS,
+            min_unstake: MIN_UNSTAKE_TOKENS,
+            max_individual_unstake: MAX_INDIVIDUAL_UNSTAKE_TOKENS,
+            max_pool_size: MAX_POOL_SIZE_TOKENS,
+        }
+    }
+}
 
-const MIN_UNSTAKE_AMOUNT: u64 = 10_000 * 1_000_000; // 10,000 CFX (6 decimals)
-// Maximum individual unstake amount (100,000,000 CFX with 6 decimals)
-const MAX_INDIVIDUAL_UNSTAKE: u64 = 100_000_000 * 1_000_000; // 100,000,000 CFX
+impl PoolLimits {
+    // min_stake(8) + max_individual_stake(8) + min_unstake(8) + max_individual_unstake(8) + max_pool_size(8)
+    pub const LEN: usize = 8 * 5;
 
-// Maximum pool size (900,000,000 CFX with 6 decimals)
-const MAX_POOL_SIZE: u64 = 900_000_000 * 1_000_000; // 900,000,000 CFX
+    /// Whether the limits are internally consistent
+    pub fn is_valid(&self) -> bool {
+        self.min_stake > 0
+            && self.min_stake <= self.max_individual_stake
+            && self.max_individual_stake <= self.max_pool_size
+            && self.min_unstake <= self.max_individual_unstake
+    }
+}
 
 // Multisig proposal types
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
@@ -25,6 +87,12 @@ pub enum ProposalType {
     UpdateAuthority,    // Update authority
     UpdateTeamWallet,   // Update team wallet (deprecated)
     AdminWithdraw,      // Admin withdraw from token vault
+    SetTransferConsent, // Require recipient consent for position transfers
+    SetOutflowCap,      // Set the per-epoch withdrawal outflow cap
+    SetCircuitBreaker,  // Configure the vault outflow circuit breaker
+    ResetCircuitBreaker, // Resume the pool after the circuit breaker tripped
+    RescueTokens,       // Recover foreign tokens sent to pool-owned token accounts
+    SetTierTable,       // Replace the pool's service tier thresholds
 }
 
 // Multisig proposal status
@@ -36,6 +104,13 @@ pub enum ProposalStatus {
     Rejected,   // Proposal rejected or expired
 }
 
+// Clock used to measure lock durations
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum LockClock {
+    Slot,           // Lock durations measured in slots (default)
+    UnixTimestamp,  // Lock durations measured in seconds of Clock::unix_timestamp
+}
+
 // Slot-based timing constants
 // Solana average slot time is ~400ms, so:
 // - 1 day ≈ 216,000 slots (24 * 60 * 60 * 1000 / 400)
@@ -47,20 +122,76 @@ const DEFAULT_LOCK_DURATION_SLOTS: u64 = 30 * SLOTS_PER_DAY; // 30 days in slots
 // Maximum lock duration (1 year in slots for safety)
 const MAX_LOCK_DURATION_SLOTS: u64 = 365 * SLOTS_PER_DAY; // 1 year in slots
 
+// Wall-clock timing constants for pools using LockClock::UnixTimestamp
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+const DEFAULT_LOCK_DURATION_SECONDS: u64 = 30 * SECONDS_PER_DAY; // 30 days in seconds
+const MAX_LOCK_DURATION_SECONDS: u64 = 365 * SECONDS_PER_DAY; // 1 year in seconds
+
+// Maximum vesting schedule length (4 years)
+const MAX_VESTING_DURATION_SLOTS: u64 = 4 * 365 * SLOTS_PER_DAY;
+const MAX_VESTING_DURATION_SECONDS: u64 = 4 * 365 * SECONDS_PER_DAY;
+
+// Vesting schedule attached to a stake position, in the pool's lock clock units.
+// Nothing vests before `cliff`; from there the position vests linearly from `start` to `end`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub struct VestingSchedule {
+    pub total: u64,     // Amount subject to vesting (0 if the position has no schedule)
+    pub start: u64,     // Vesting start
+    pub cliff: u64,     // Nothing is released before the cliff
+    pub end: u64,       // Fully vested at the end
+}
+
+impl VestingSchedule {
+    // total(8) + start(8) + cliff(8) + end(8)
+    pub const LEN: usize = 8 * 4;
+
+    /// Amount vested at `now`
+    pub fn vested(&self, now: u64) -> u64 {
+        if now < self.cliff || now <= self.start {
+            0
+        } else if now >= self.end {
+            self.total
+        } else {
+            let elapsed = (now - self.start) as u128;
+            let duration = (self.end - self.start) as u128;
+            (self.total as u128 * elapsed / duration) as u64
+        }
+    }
+
+    /// Amount still locked by the schedule at `now`
+    pub fn unvested(&self, now: u64) -> u64 {
+        self.total - self.vested(now)
+    }
+}
+
 // Maximum byte number of proposal data
 const MAX_PROPOSAL_DATA_LEN: usize = 256;
 
+// Denominator for basis-point ratios
+const BASIS_POINTS: u16 = 10_000;
+
+// Circuit breaker window buckets: the window is split into one fewer bucket than
+// this, and the bucket in progress is counted on top, so every outflow within
+// the last `breaker_window_slots` slots is always counted
+const BREAKER_BUCKETS: usize = 5;
+
+// Largest keeper tip a position can offer for completing its withdrawal (1%)
+const MAX_KEEPER_TIP_BPS: u16 = 100;
+
+// Proposals that are not executed within 7 days expire and can be closed
+const PROPOSAL_EXPIRY_SLOTS: u64 = 7 * SLOTS_PER_DAY;
+
 // Reentrancy guard macro
 macro_rules! reentrancy_guard {
     ($stake_pool:expr) => {
-        require!(!$stake_pool.reentrancy_guard, StakeError::ReentrancyDetected);
-        $stake_pool.reentrancy_guard = true;
+        require!($stake_pool.reentrancy_guard == 0, StakeError::ReentrancyDetected);
+        $stake_pool.reentrancy_guard = 1;
     };
 }
 
 macro_rules! reentrancy_guard_end {
     ($stake_pool:expr) => {
-        $stake_pool.reentrancy_guard = false;
+        $stake_pool.reentrancy_guard = 0;
     };
 }
 
@@ -73,12 +204,53 @@ pub mod cfx_stake_core {
         ctx: Context<Initialize>,
         _stake_pool_bump: u8,
         lock_duration_slots: Option<u64>,
+        lock_clock: Option<LockClock>,
+        lock_duration_seconds: Option<u64>,
+        pool_id: u64,
+        limits: Option<PoolLimits>,
     ) -> Result<()> {
-        let stake_pool = &mut ctx.accounts.stake_pool;
+        let token_program_id = ctx.accounts.token_program.key();
+        let mint_info = ctx.accounts.token_mint.to_account_info();
+
+        // The mint must belong to the token program the pool will use
+        require!(
+            token_interface::is_token_program(&token_program_id) && *mint_info.owner == token_program_id,
+            StakeError::InvalidTokenProgram
+        );
+        let decimals = token_interface::unpack_mint(&mint_info)?.decimals;
+        token_interface::check_mint_extensions(&mint_info)?;
+
+        // Create the token vault, sized for any extensions the mint requires
+        let vault_len = token_interface::token_account_len(&mint_info)?;
+        let cpi_accounts = CreateAccount {
+            from: ctx.accounts.authority.to_account_info(),
+            to: ctx.accounts.token_vault.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(ctx.accounts.system_program.to_account_info(), cpi_accounts);
+        system_program::create_account(
+            cpi_ctx,
+            ctx.accounts.rent.minimum_balance(vault_len),
+            vault_len as u64,
+            &token_program_id,
+        )?;
+
+        let cpi_accounts = InitializeAccount3 {
+            account: ctx.accounts.token_vault.to_account_info(),
+            mint: mint_info,
+        };
+        let cpi_ctx = CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts);
+        token_interface::initialize_account3(cpi_ctx, &ctx.accounts.stake_pool.key())?;
+
+        // List the new pool in the mint's registry
+        let stake_pool_key = ctx.accounts.stake_pool.key();
+        ctx.accounts.pool_registry.register(stake_pool_key)?;
+
+        let mut stake_pool = ctx.accounts.stake_pool.load_init()?;
         stake_pool.authority = ctx.accounts.authority.key();
         stake_pool.token_mint = ctx.accounts.token_mint.key();
         stake_pool.token_vault = ctx.accounts.token_vault.key();
         stake_pool.bump = *ctx.bumps.get("stake_pool").unwrap();
+        stake_pool.version = StakePool::VERSION;
         let duration = lock_duration_slots.unwrap_or(DEFAULT_LOCK_DURATION_SLOTS);
         // Safety check: ensure lock duration is reasonable (not more than 1 year)
         require!(
@@ -86,9 +258,144 @@ pub mod cfx_stake_core {
             StakeError::ExcessiveLockDuration
         );
         stake_pool.lock_duration_slots = duration;
+
+        // Wall-clock lock duration, used when the pool measures locks with unix timestamps
+        let lock_clock = lock_clock.unwrap_or(LockClock::Slot);
+        let duration_seconds = match lock_clock {
+            LockClock::Slot => StakePool::slots_to_seconds(duration),
+            LockClock::UnixTimestamp => lock_duration_seconds.unwrap_or(DEFAULT_LOCK_DURATION_SECONDS),
+        };
+        require!(
+            duration_seconds <= MAX_LOCK_DURATION_SECONDS,
+            StakeError::ExcessiveLockDuration
+        );
+        stake_pool.lock_clock = lock_clock as u8;
+        stake_pool.lock_duration_seconds = duration_seconds;
         stake_pool.total_staked = 0;
-        stake_pool.emergency_mode = false;
-        stake_pool.reentrancy_guard = false;
+        stake_pool.emergency_mode = 0;
+        stake_pool.reentrancy_guard = 0;
+        stake_pool.token_program = token_program_id;
+        stake_pool.decimals = decimals;
+        stake_pool.pool_id = pool_id;
+
+        stake_pool.limits = limits.unwrap_or_default();
+        stake_pool.check_limits()?;
+
+        // Pool-wide balance history starts empty
+        let clock = Clock::get()?;
+        let mut pool_checkpoints = ctx.accounts.pool_checkpoints.load_init()?;
+        pool_checkpoints.init(stake_pool_key, *ctx.bumps.get("pool_checkpoints").unwrap(), clock.slot, 0, true);
+
+        // Emit event
+        emit!(PoolInitializedEvent {
+            stake_pool: stake_pool_key,
+            authority: stake_pool.authority,
+            token_mint: stake_pool.token_mint,
+            token_vault: stake_pool.token_vault,
+            token_program: stake_pool.token_program,
+            pool_id,
+            decimals,
+            lock_clock,
+            lock_duration_slots: stake_pool.lock_duration_slots,
+            lock_duration_seconds: stake_pool.lock_duration_seconds,
+            limits: stake_pool.limits,
+            seq: stake_pool.next_event_seq(),
+            slot: clock.slot,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    // Initialize the registry listing all stake pools of a mint (once per mint)
+    pub fn initialize_pool_registry(ctx: Context<InitializePoolRegistry>) -> Result<()> {
+        let pool_registry = &mut ctx.accounts.pool_registry;
+        pool_registry.token_mint = ctx.accounts.token_mint.key();
+        pool_registry.pools = Vec::new();
+        pool_registry.bump = *ctx.bumps.get("pool_registry").unwrap();
+
+        Ok(())
+    }
+
+    // Add a pool created before its mint's registry existed
+    pub fn register_pool(ctx: Context<RegisterPool>) -> Result<()> {
+        ctx.accounts.pool_registry.register(ctx.accounts.stake_pool.key())
+    }
+
+    // Create a pool's (empty) service tier table; thresholds are set by a SetTierTable proposal
+    pub fn initialize_tier_table(ctx: Context<InitializeTierTable>) -> Result<()> {
+        let tier_table = &mut ctx.accounts.tier_table;
+        tier_table.stake_pool = ctx.accounts.stake_pool.key();
+        tier_table.tiers = Vec::new();
+        tier_table.bump = *ctx.bumps.get("tier_table").unwrap();
+        tier_table.version = TierTable::VERSION;
+
+        // Emit event
+        let clock = Clock::get()?;
+        emit!(TierTableInitializedEvent {
+            stake_pool: tier_table.stake_pool,
+            tier_table: tier_table.key(),
+            payer: ctx.accounts.payer.key(),
+            slot: clock.slot,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    // Start the balance history of a position created before checkpoints existed (callable by anyone)
+    pub fn initialize_stake_checkpoints(ctx: Context<InitializeStakeCheckpoints>) -> Result<()> {
+        let user_stake = &ctx.accounts.user_stake;
+        let clock = Clock::get()?;
+
+        // Earlier balances are unknown, so lookups before this slot fail
+        let mut checkpoints = ctx.accounts.checkpoints.load_init()?;
+        checkpoints.init(
+            user_stake.key(),
+            *ctx.bumps.get("checkpoints").unwrap(),
+            clock.slot,
+            user_stake.staked_amount,
+            false,
+        );
+
+        // Emit event
+        emit!(CheckpointsInitializedEvent {
+            stake_pool: user_stake.stake_pool,
+            checkpoints: ctx.accounts.checkpoints.key(),
+            user: Some(user_stake.owner),
+            amount: user_stake.staked_amount,
+            slot: clock.slot,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    // Start the pool-wide balance history of a pool created before checkpoints existed (callable by anyone)
+    pub fn initialize_pool_checkpoints(ctx: Context<InitializePoolCheckpoints>) -> Result<()> {
+        let stake_pool_key = ctx.accounts.stake_pool.key();
+        let stake_pool = ctx.accounts.stake_pool.load()?;
+        let clock = Clock::get()?;
+
+        // Earlier totals are unknown, so lookups before this slot fail
+        let mut pool_checkpoints = ctx.accounts.pool_checkpoints.load_init()?;
+        pool_checkpoints.init(
+            stake_pool_key,
+            *ctx.bumps.get("pool_checkpoints").unwrap(),
+            clock.slot,
+            stake_pool.total_staked,
+            false,
+        );
+
+        // Emit event
+        emit!(CheckpointsInitializedEvent {
+            stake_pool: stake_pool_key,
+            checkpoints: ctx.accounts.pool_checkpoints.key(),
+            user: None,
+            amount: stake_pool.total_staked,
+            slot: clock.slot,
+            timestamp: clock.unix_timestamp,
+        });
 
         Ok(())
     }
@@ -112,6 +419,20 @@ pub mod cfx_stake_core {
         multisig_config.stake_pool = ctx.accounts.stake_pool.key();
         multisig_config.proposal_count = 0;
         multisig_config.bump = *ctx.bumps.get("multisig_config").unwrap();
+        multisig_config.version = MultisigConfig::VERSION;
+
+        // Emit event
+        let clock = Clock::get()?;
+        emit!(MultisigInitializedEvent {
+            stake_pool: multisig_config.stake_pool,
+            multisig_config: multisig_config.key(),
+            signers,
+            threshold,
+            authority: ctx.accounts.authority.key(),
+            seq: ctx.accounts.stake_pool.load_mut()?.next_event_seq(),
+            slot: clock.slot,
+            timestamp: clock.unix_timestamp,
+        });
 
         Ok(())
     }
@@ -142,14 +463,29 @@ pub mod cfx_stake_core {
         proposal.signatures = [false; 3];
         proposal.signatures[signer_index] = true; // Proposer automatically signs
         proposal.signature_count = 1;
-        proposal.created_at = Clock::get()?.slot;
+        let clock = Clock::get()?;
+        proposal.created_at = clock.slot;
         proposal.executed_at = None;
         proposal.data = data;
         proposal.bump = *ctx.bumps.get("proposal").unwrap();
+        proposal.version = MultisigProposal::VERSION;
 
         // Increment proposal count
         multisig_config.proposal_count += 1;
 
+        // Emit event
+        emit!(ProposalCreatedEvent {
+            stake_pool: multisig_config.stake_pool,
+            multisig_config: multisig_config.key(),
+            proposal_id: proposal.id,
+            proposal_type,
+            proposer: proposal.proposer,
+            data: proposal.data.clone(),
+            seq: ctx.accounts.stake_pool.load_mut()?.next_event_seq(),
+            slot: clock.slot,
+            timestamp: clock.unix_timestamp,
+        });
+
         Ok(())
     }
 
@@ -161,6 +497,10 @@ pub mod cfx_stake_core {
         // Verify proposal is still pending
         require!(proposal.status == ProposalStatus::Pending, StakeError::ProposalAlreadyExecuted);
 
+        // Verify proposal has not expired
+        let clock = Clock::get()?;
+        require!(!proposal.is_expired(clock.slot), StakeError::ProposalExpired);
+
         // Verify signer is one of the multisig signers
         let signer_index = multisig_config.signers
             .iter()
@@ -179,44 +519,165 @@ pub mod cfx_stake_core {
             proposal.status = ProposalStatus::Approved;
         }
 
+        // Emit event
+        emit!(ProposalSignedEvent {
+            stake_pool: multisig_config.stake_pool,
+            multisig_config: multisig_config.key(),
+            proposal_id: proposal.id,
+            signer: ctx.accounts.signer.key(),
+            signature_count: proposal.signature_count,
+            threshold: multisig_config.threshold,
+            status: proposal.status,
+            seq: ctx.accounts.stake_pool.load_mut()?.next_event_seq(),
+            slot: clock.slot,
+            timestamp: clock.unix_timestamp,
+        });
+
         Ok(())
     }
 
     // Execute approved multisig proposal
     pub fn execute_proposal(ctx: Context<ExecuteProposal>) -> Result<()> {
         let proposal = &mut ctx.accounts.proposal;
-        let stake_pool = &mut ctx.accounts.stake_pool;
+        let mut stake_pool = ctx.accounts.stake_pool.load_mut()?;
+        let stake_pool_key = ctx.accounts.stake_pool.key();
 
         // Verify proposal is approved
         require!(proposal.status == ProposalStatus::Approved, StakeError::ProposalNotApproved);
 
+        // Verify proposal has not expired
+        let clock = Clock::get()?;
+        require!(!proposal.is_expired(clock.slot), StakeError::ProposalExpired);
+
         // Execute based on proposal type
         match proposal.proposal_type {
             ProposalType::TogglePause => {
-                stake_pool.emergency_mode = !stake_pool.emergency_mode;
+                let was_paused = stake_pool.emergency_mode != 0;
+                stake_pool.emergency_mode = u8::from(!was_paused);
+                emit!(PauseEvent {
+                    stake_pool: stake_pool_key,
+                    paused: !was_paused,
+                    was_paused,
+                    authority: ctx.accounts.executor.key(),
+                    seq: stake_pool.next_event_seq(),
+                    slot: clock.slot,
+                    timestamp: clock.unix_timestamp,
+                });
             },
             ProposalType::UpdateAuthority => {
                 // Extract new authority from proposal data
                 if proposal.data.len() >= 32 {
                     let new_authority = Pubkey::try_from(&proposal.data[0..32])
                         .map_err(|_| StakeError::InvalidProposalType)?;
+                    let old_authority = stake_pool.authority;
                     stake_pool.authority = new_authority;
+                    emit!(AuthorityUpdatedEvent {
+                        stake_pool: stake_pool_key,
+                        old_authority,
+                        new_authority,
+                        seq: stake_pool.next_event_seq(),
+                        slot: clock.slot,
+                        timestamp: clock.unix_timestamp,
+                    });
                 }
             },
             ProposalType::UpdateTeamWallet => {
                 // This proposal type is no longer supported since we removed team wallet
                 return Err(StakeError::InvalidProposalType.into());
             },
-            ProposalType::AdminWithdraw => {
-                // AdminWithdraw requires special handling with additional accounts
-                // This will be handled by a separate function
+            ProposalType::AdminWithdraw | ProposalType::RescueTokens | ProposalType::SetTierTable => {
+                // AdminWithdraw, RescueTokens and SetTierTable require special handling with additional accounts
+                // These are handled by separate functions
                 return Err(StakeError::InvalidProposalType.into());
             },
+            ProposalType::SetTransferConsent => {
+                // Data format: [required: 1 byte]
+                require!(!proposal.data.is_empty(), StakeError::InvalidProposalType);
+                let old_required = stake_pool.require_transfer_consent != 0;
+                let new_required = proposal.data[0] != 0;
+                stake_pool.require_transfer_consent = u8::from(new_required);
+                emit!(TransferConsentUpdatedEvent {
+                    stake_pool: stake_pool_key,
+                    old_required,
+                    new_required,
+                    seq: stake_pool.next_event_seq(),
+                    slot: clock.slot,
+                    timestamp: clock.unix_timestamp,
+                });
+            },
+            ProposalType::SetOutflowCap => {
+                // Data format: [cap: 8 bytes, base units per epoch, 0 = unlimited]
+                require!(proposal.data.len() >= 8, StakeError::InvalidProposalType);
+                let cap = u64::from_le_bytes(proposal.data[0..8].try_into().unwrap());
+                let old_cap = stake_pool.outflow_cap;
+                stake_pool.outflow_cap = cap;
+                stake_pool.outflow_queue_epoch = 0;
+                stake_pool.outflow_queue_used = 0;
+                emit!(OutflowCapUpdatedEvent {
+                    stake_pool: stake_pool_key,
+                    old_cap,
+                    new_cap: cap,
+                    seq: stake_pool.next_event_seq(),
+                    slot: clock.slot,
+                    timestamp: clock.unix_timestamp,
+                });
+            },
+            ProposalType::SetCircuitBreaker => {
+                // Data format: [threshold_bps: 2 bytes, 0 = disabled][window_slots: 8 bytes]
+                require!(proposal.data.len() >= 10, StakeError::InvalidProposalType);
+                let threshold_bps = u16::from_le_bytes(proposal.data[0..2].try_into().unwrap());
+                let window_slots = u64::from_le_bytes(proposal.data[2..10].try_into().unwrap());
+                require!(
+                    threshold_bps <= BASIS_POINTS && (threshold_bps == 0 || window_slots > 0),
+                    StakeError::InvalidCircuitBreakerConfig
+                );
+                let old_threshold_bps = stake_pool.breaker_threshold_bps;
+                let old_window_slots = stake_pool.breaker_window_slots;
+                stake_pool.breaker_threshold_bps = threshold_bps;
+                stake_pool.breaker_window_slots = window_slots;
+                stake_pool.clear_breaker_window();
+                emit!(CircuitBreakerUpdatedEvent {
+                    stake_pool: stake_pool_key,
+                    old_threshold_bps,
+                    new_threshold_bps: threshold_bps,
+                    old_window_slots,
+                    new_window_slots: window_slots,
+                    seq: stake_pool.next_event_seq(),
+                    slot: clock.slot,
+                    timestamp: clock.unix_timestamp,
+                });
+            },
+            ProposalType::ResetCircuitBreaker => {
+                let was_tripped = stake_pool.breaker_tripped != 0;
+                let window_outflow = stake_pool.breaker_window_outflow;
+                stake_pool.breaker_tripped = 0;
+                stake_pool.clear_breaker_window();
+                emit!(CircuitBreakerResetEvent {
+                    stake_pool: stake_pool_key,
+                    was_tripped,
+                    window_outflow,
+                    seq: stake_pool.next_event_seq(),
+                    slot: clock.slot,
+                    timestamp: clock.unix_timestamp,
+                });
+            },
         }
 
         // Mark proposal as executed
         proposal.status = ProposalStatus::Executed;
-        proposal.executed_at = Some(Clock::get()?.slot);
+        proposal.executed_at = Some(clock.slot);
+
+        // Emit event
+        emit!(ProposalExecutedEvent {
+            stake_pool: stake_pool_key,
+            multisig_config: proposal.multisig_config,
+            proposal_id: proposal.id,
+            proposal_type: proposal.proposal_type,
+            executor: ctx.accounts.executor.key(),
+            seq: stake_pool.next_event_seq(),
+            slot: clock.slot,
+            timestamp: clock.unix_timestamp,
+        });
 
         Ok(())
     }
@@ -231,63 +692,73 @@ pub mod cfx_stake_core {
         user_stake.unlock_slot = 0;
         user_stake.withdrawal_requested = false;
         user_stake.bump = *ctx.bumps.get("user_stake").unwrap();
+        user_stake.version = UserStake::VERSION;
+
+        // Balance history starts empty, or carries on from a closed position at this address
+        let clock = Clock::get()?;
+        open_checkpoints(&ctx.accounts.checkpoints, user_stake.key(), *ctx.bumps.get("checkpoints").unwrap(), &clock)?;
+
+        // Emit event
+        emit!(UserStakeCreatedEvent {
+            user: user_stake.owner,
+            stake_pool: user_stake.stake_pool,
+            user_stake: user_stake.key(),
+            payer: ctx.accounts.owner.key(),
+            seq: ctx.accounts.stake_pool.load_mut()?.next_event_seq(),
+            slot: clock.slot,
+            timestamp: clock.unix_timestamp,
+        });
 
         Ok(())
     }
 
     // Stake tokens
     pub fn stake(ctx: Context<Stake>, amount: u64) -> Result<()> {
-        let stake_pool = &mut ctx.accounts.stake_pool;
+        let mut stake_pool = ctx.accounts.stake_pool.load_mut()?;
 
         // Apply reentrancy guard
         reentrancy_guard!(stake_pool);
 
-        // Ensure amount is greater than 0
-        require!(amount > 0, StakeError::AmountMustBeGreaterThanZero);
-
-        // Ensure amount meets minimum stake requirement
-        require!(amount >= MIN_STAKE_AMOUNT, StakeError::BelowMinimumStakeAmount);
-
-        // Ensure amount doesn't exceed maximum individual stake limit
-        require!(amount <= MAX_INDIVIDUAL_STAKE, StakeError::ExceedsMaximumStakeAmount);
-
-        // Check if contract is paused (allow staking when not paused)
-        require!(!stake_pool.emergency_mode, StakeError::ContractPaused);
-
         let user_stake = &mut ctx.accounts.user_stake;
 
-        // Check individual stake limits and pool capacity
-        let new_total_individual = user_stake.staked_amount.checked_add(amount).ok_or(StakeError::ArithmeticOverflow)?;
-        require!(new_total_individual <= MAX_INDIVIDUAL_STAKE, StakeError::ExceedsMaximumStakeAmount);
+        // Validate amount, pause state, pending requests and limits
+        stake_pool.check_stake(user_stake, amount)?;
 
-        let new_pool_total = stake_pool.total_staked.checked_add(amount).ok_or(StakeError::ArithmeticOverflow)?;
-        require!(new_pool_total <= MAX_POOL_SIZE, StakeError::ExceedsMaximumPoolSize);
+        // Verify the source token account belongs to the owner and holds the pool mint
+        let user_token_account = token_interface::unpack_token_account(&ctx.accounts.user_token_account)?;
+        require!(user_token_account.mint == stake_pool.token_mint, StakeError::InvalidTokenMint);
+        require!(user_token_account.owner == ctx.accounts.owner.key(), StakeError::InvalidUser);
+        let decimals = stake_pool.mint_decimals(&ctx.accounts.token_mint)?;
 
         // Transfer tokens to contract vault
-        let cpi_accounts = Transfer {
+        let cpi_accounts = TransferChecked {
             from: ctx.accounts.user_token_account.to_account_info(),
+            mint: ctx.accounts.token_mint.to_account_info(),
             to: ctx.accounts.token_vault.to_account_info(),
             authority: ctx.accounts.owner.to_account_info(),
         };
 
         let cpi_program = ctx.accounts.token_program.to_account_info();
         let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
-        token::transfer(cpi_ctx, amount)?;
+        let received = deposit_to_vault(cpi_ctx, amount, decimals)?;
 
-        // Update stake amounts
-        user_stake.staked_amount = user_stake.staked_amount.checked_add(amount).ok_or(StakeError::ArithmeticOverflow)?;
-        stake_pool.total_staked = stake_pool.total_staked.checked_add(amount).ok_or(StakeError::ArithmeticOverflow)?;
+        // Staking again cancels a pending request (only reachable in pools without an outflow cap)
+        user_stake.clear_request();
 
-        // Update common fields
-        user_stake.last_stake_slot = Clock::get()?.slot;
-        user_stake.withdrawal_requested = false;
+        // Update stake amounts
+        let clock = Clock::get()?;
+        stake_pool.credit_stake(user_stake, received, &clock)?;
+        record_checkpoints(&ctx.accounts.checkpoints, &ctx.accounts.pool_checkpoints, user_stake, &stake_pool, &clock)?;
 
         // Emit event
         emit!(StakeEvent {
             user: ctx.accounts.owner.key(),
-            amount_staked: amount,
+            stake_pool: ctx.accounts.stake_pool.key(),
+            amount_staked: received,
             total_staked: user_stake.staked_amount,
-            timestamp: Clock::get()?.slot,
+            seq: stake_pool.next_event_seq(),
+            slot: clock.slot,
+            timestamp: clock.unix_timestamp,
         });
 
         // Release reentrancy guard
@@ -298,42 +769,177 @@ pub mod cfx_stake_core {
 
 
 
+    // Create a user stake account on behalf of a beneficiary (funder pays the rent)
+    pub fn create_user_stake_for(ctx: Context<CreateUserStakeFor>) -> Result<()> {
+        let user_stake = &mut ctx.accounts.user_stake;
+        user_stake.owner = ctx.accounts.beneficiary.key();
+        user_stake.stake_pool = ctx.accounts.stake_pool.key();
+        user_stake.staked_amount = 0;
+        user_stake.last_stake_slot = 0;
+        user_stake.unlock_slot = 0;
+        user_stake.withdrawal_requested = false;
+        user_stake.bump = *ctx.bumps.get("user_stake").unwrap();
+        user_stake.version = UserStake::VERSION;
+
+        // Balance history starts empty, or carries on from a closed position at this address
+        let clock = Clock::get()?;
+        open_checkpoints(&ctx.accounts.checkpoints, user_stake.key(), *ctx.bumps.get("checkpoints").unwrap(), &clock)?;
+
+        // Emit event
+        emit!(UserStakeCreatedEvent {
+            user: user_stake.owner,
+            stake_pool: user_stake.stake_pool,
+            user_stake: user_stake.key(),
+            payer: ctx.accounts.funder.key(),
+            seq: ctx.accounts.stake_pool.load_mut()?.next_event_seq(),
+            slot: clock.slot,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    // Stake tokens from a funder into a beneficiary's position; only the beneficiary can withdraw
+    pub fn stake_for(ctx: Context<StakeFor>, amount: u64) -> Result<()> {
+        let (received, clock) = ctx.accounts.deposit(amount)?;
+
+        // Emit event
+        let seq = ctx.accounts.stake_pool.load_mut()?.next_event_seq();
+        emit!(StakeForEvent {
+            funder: ctx.accounts.funder.key(),
+            beneficiary: ctx.accounts.beneficiary.key(),
+            stake_pool: ctx.accounts.stake_pool.key(),
+            amount_staked: received,
+            total_staked: ctx.accounts.user_stake.staked_amount,
+            seq,
+            slot: clock.slot,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    // Stake tokens into a beneficiary's position under a vesting schedule (e.g. team or investor allocations)
+    pub fn stake_vested(
+        ctx: Context<StakeFor>,
+        amount: u64,
+        start: u64,
+        cliff: u64,
+        end: u64,
+    ) -> Result<()> {
+        // Validate the schedule and the beneficiary's consent
+        let stake_pool = ctx.accounts.stake_pool.load()?;
+        let schedule = VestingSchedule { total: 0, start, cliff, end };
+        let now = stake_pool.clock_now(&Clock::get()?);
+        stake_pool.check_vesting_grant(&ctx.accounts.user_stake, &schedule, now, ctx.accounts.beneficiary.is_signer)?;
+        drop(stake_pool);
+
+        let (received, clock) = ctx.accounts.deposit(amount)?;
+
+        let vesting = VestingSchedule { total: received, ..schedule };
+        ctx.accounts.user_stake.vesting = vesting;
+
+        // Emit event
+        let seq = ctx.accounts.stake_pool.load_mut()?.next_event_seq();
+        emit!(VestedStakeEvent {
+            funder: ctx.accounts.funder.key(),
+            beneficiary: ctx.accounts.beneficiary.key(),
+            stake_pool: ctx.accounts.stake_pool.key(),
+            amount_staked: received,
+            vesting,
+            seq,
+            slot: clock.slot,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    // Move a whole position to another wallet's (empty) user stake account
+    pub fn transfer_position(ctx: Context<TransferPosition>) -> Result<()> {
+        let mut stake_pool = ctx.accounts.stake_pool.load_mut()?;
+        let from_user_stake = &mut ctx.accounts.from_user_stake;
+        let to_user_stake = &mut ctx.accounts.to_user_stake;
+
+        // Validate both positions and the recipient's consent
+        stake_pool.check_transfer(from_user_stake, to_user_stake, ctx.accounts.new_owner.is_signer)?;
+
+        let amount = from_user_stake.staked_amount;
+        from_user_stake.transfer_to(to_user_stake);
+
+        // The pool total is unchanged; only the two positions get a checkpoint
+        let clock = Clock::get()?;
+        ctx.accounts.from_checkpoints.load_mut()?.record(clock.slot, 0);
+        ctx.accounts.to_checkpoints.load_mut()?.record(clock.slot, amount);
+
+        // Emit event
+        emit!(PositionTransferredEvent {
+            from: ctx.accounts.owner.key(),
+            to: ctx.accounts.new_owner.key(),
+            stake_pool: ctx.accounts.stake_pool.key(),
+            amount,
+            seq: stake_pool.next_event_seq(),
+            slot: clock.slot,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
     // Request withdrawal (allowed even when contract is paused)
     pub fn request_withdrawal(ctx: Context<RequestWithdrawal>) -> Result<()> {
         let user_stake = &mut ctx.accounts.user_stake;
-        let stake_pool = &ctx.accounts.stake_pool;
+        let mut stake_pool = ctx.accounts.stake_pool.load_mut()?;
 
         // Ensure user has staked tokens
         require!(user_stake.staked_amount > 0, StakeError::NoStakedTokens);
         // Ensure user has not already requested withdrawal
         require!(!user_stake.withdrawal_requested, StakeError::WithdrawalAlreadyRequested);
 
-        // Validate unstake amount limits
-        require!(user_stake.staked_amount >= MIN_UNSTAKE_AMOUNT, StakeError::BelowMinimumUnstakeAmount);
-        require!(user_stake.staked_amount <= MAX_INDIVIDUAL_UNSTAKE, StakeError::ExceedsMaximumUnstakeAmount);
-
-        // Set unlock slot based on emergency mode
-        let current_slot = Clock::get()?.slot;
-        if stake_pool.emergency_mode {
-            // In emergency mode, allow immediate withdrawal
-            user_stake.unlock_slot = current_slot;
-        } else {
-            // Normal mode: apply lock duration
-            let lock_duration_slots = stake_pool.lock_duration_slots;
-
-            // Safety check: ensure lock duration is reasonable (not more than 1 year)
-            require!(lock_duration_slots <= MAX_LOCK_DURATION_SLOTS, StakeError::ExcessiveLockDuration);
-
-            user_stake.unlock_slot = current_slot.checked_add(lock_duration_slots).ok_or(StakeError::ArithmeticOverflow)?;
+        // Validate unstake amount limits (vesting positions are released in parts,
+        // so the minimum only applies to regular positions)
+        if !user_stake.has_vesting() {
+            require!(user_stake.staked_amount >= stake_pool.min_unstake_amount()?, StakeError::BelowMinimumUnstakeAmount);
         }
-        user_stake.withdrawal_requested = true;
+        require!(user_stake.staked_amount <= stake_pool.max_individual_unstake()?, StakeError::ExceedsMaximumUnstakeAmount);
+
+        // Set the unlock point and reserve outflow capacity for the payout
+        let clock = Clock::get()?;
+        let queued = stake_pool.reserve_withdrawal(user_stake, &clock)?;
 
         // Emit event
         emit!(WithdrawalRequestEvent {
             user: ctx.accounts.owner.key(),
+            stake_pool: ctx.accounts.stake_pool.key(),
             unlock_slot: user_stake.unlock_slot,
-            timestamp: current_slot,
-            emergency_mode: stake_pool.emergency_mode,
+            unlock_timestamp: user_stake.unlock_timestamp,
+            seq: stake_pool.next_event_seq(),
+            slot: clock.slot,
+            timestamp: clock.unix_timestamp,
+            emergency_mode: stake_pool.emergency_mode != 0,
+            queued,
+        });
+
+        Ok(())
+    }
+
+    // Push a position's lock end later (in the pool's lock clock units)
+    pub fn extend_lock(ctx: Context<ExtendLock>, new_lock_end: u64) -> Result<()> {
+        let user_stake = &mut ctx.accounts.user_stake;
+        let stake_pool = ctx.accounts.stake_pool.load()?;
+
+        let clock = Clock::get()?;
+        let old_lock_end = stake_pool.extend_lock(user_stake, new_lock_end, &clock)?;
+
+        // Emit event
+        emit!(LockExtendedEvent {
+            user: ctx.accounts.owner.key(),
+            stake_pool: ctx.accounts.stake_pool.key(),
+            old_lock_end,
+            new_lock_end,
+            voting_power: user_stake.voting_power(&stake_pool, &clock),
+            slot: clock.slot,
+            timestamp: clock.unix_timestamp,
         });
 
         Ok(())
@@ -341,14 +947,20 @@ pub mod cfx_stake_core {
 
     // Toggle contract pause (only affects new stakes, withdrawals always allowed)
     pub fn toggle_pause(ctx: Context<TogglePause>, pause: bool) -> Result<()> {
-        let stake_pool = &mut ctx.accounts.stake_pool;
-        stake_pool.emergency_mode = pause;
+        let mut stake_pool = ctx.accounts.stake_pool.load_mut()?;
+        let was_paused = stake_pool.emergency_mode != 0;
+        stake_pool.emergency_mode = u8::from(pause);
 
         // Emit event
+        let clock = Clock::get()?;
         emit!(PauseEvent {
+            stake_pool: ctx.accounts.stake_pool.key(),
             paused: pause,
+            was_paused,
             authority: ctx.accounts.authority.key(),
-            timestamp: Clock::get()?.slot,
+            seq: stake_pool.next_event_seq(),
+            slot: clock.slot,
+            timestamp: clock.unix_timestamp,
         });
 
         Ok(())
@@ -356,56 +968,59 @@ pub mod cfx_stake_core {
 
     // Withdraw tokens (allowed even when contract is paused)
     pub fn withdraw(ctx: Context<Withdraw>) -> Result<()> {
-        let stake_pool = &mut ctx.accounts.stake_pool;
+        let mut stake_pool = ctx.accounts.stake_pool.load_mut()?;
 
         // Apply reentrancy guard
         reentrancy_guard!(stake_pool);
 
         let user_stake = &mut ctx.accounts.user_stake;
 
-        // Ensure user has staked tokens
-        require!(user_stake.staked_amount > 0, StakeError::NoStakedTokens);
-
-        // Ensure user has requested withdrawal
-        require!(user_stake.withdrawal_requested, StakeError::WithdrawalNotRequested);
-
-        // Validate unstake amount limits
-        require!(user_stake.staked_amount >= MIN_UNSTAKE_AMOUNT, StakeError::BelowMinimumUnstakeAmount);
-        require!(user_stake.staked_amount <= MAX_INDIVIDUAL_UNSTAKE, StakeError::ExceedsMaximumUnstakeAmount);
-
-        // Ensure lock period has passed
-        let current_slot = Clock::get()?.slot;
-        require!(current_slot >= user_stake.unlock_slot, StakeError::TokensStillLocked);
+        // Validate the request, lock and limits; get the amount released now
+        let clock = Clock::get()?;
+        let staked_amount = stake_pool.check_withdrawal(user_stake, &clock)?;
 
-        // Get amount to withdraw
-        let staked_amount = user_stake.staked_amount;
+        // Verify the destination token account belongs to the owner and holds the pool mint
+        let user_token_account = token_interface::unpack_token_account(&ctx.accounts.user_token_account)?;
+        require!(user_token_account.mint == stake_pool.token_mint, StakeError::InvalidTokenMint);
+        require!(user_token_account.owner == ctx.accounts.owner.key(), StakeError::InvalidUser);
+        let decimals = stake_pool.mint_decimals(&ctx.accounts.token_mint)?;
 
         // Check if stake pool vault has sufficient funds
         require!(
-            ctx.accounts.token_vault.amount >= staked_amount,
+            token_interface::unpack_token_account(&ctx.accounts.token_vault)?.amount >= staked_amount,
             StakeError::InsufficientFunds
         );
 
         // Compute new stake pool state
         let new_total = stake_pool.total_staked.checked_sub(staked_amount).ok_or(StakeError::ArithmeticOverflow)?;
 
-        // Transfer full amount from stake pool vault to user account
+        // Transfer withdrawable amount from stake pool vault to user account.
+        // The pool PDA signs, so its data must not stay borrowed during the CPI.
+        let (token_mint, pool_id_seed, bump) = stake_pool.signer_seed_parts();
+        drop(stake_pool);
         let seeds = &[
             b"stake_pool".as_ref(),
-            stake_pool.token_mint.as_ref(),
-            &[stake_pool.bump],
+            token_mint.as_ref(),
+            pool_id_seed.as_slice(),
+            &[bump],
         ];
         let signer = &[&seeds[..]];
 
-        let cpi_accounts = Transfer {
+        let cpi_accounts = TransferChecked {
             from: ctx.accounts.token_vault.to_account_info(),
+            mint: ctx.accounts.token_mint.to_account_info(),
             to: ctx.accounts.user_token_account.to_account_info(),
             authority: ctx.accounts.stake_pool_authority.to_account_info(),
         };
 
         let cpi_program = ctx.accounts.token_program.to_account_info();
         let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
-        token::transfer(cpi_ctx, staked_amount)?;
+        token_interface::transfer_checked(cpi_ctx, staked_amount, decimals)?;
+
+        // Track the outflow against the circuit breaker (measured before total_staked drops)
+        let stake_pool_key = ctx.accounts.stake_pool.key();
+        let mut stake_pool = ctx.accounts.stake_pool.load_mut()?;
+        stake_pool.record_outflow(stake_pool_key, staked_amount, &clock)?;
 
         // Update new stake pool state
         stake_pool.total_staked = new_total;
@@ -413,15 +1028,156 @@ pub mod cfx_stake_core {
         // Emit event before resetting
         emit!(WithdrawEvent {
             user: ctx.accounts.owner.key(),
+            stake_pool: stake_pool_key,
             amount_withdrawn: staked_amount,
-            timestamp: Clock::get()?.slot,
+            seq: stake_pool.next_event_seq(),
+            slot: clock.slot,
+            timestamp: clock.unix_timestamp,
         });
 
         // Reset stake information
-        user_stake.staked_amount = 0;
-        user_stake.last_stake_slot = 0;
-        user_stake.unlock_slot = 0;
-        user_stake.withdrawal_requested = false;
+        user_stake.settle_withdrawal(staked_amount)?;
+        record_checkpoints(&ctx.accounts.checkpoints, &ctx.accounts.pool_checkpoints, user_stake, &stake_pool, &clock)?;
+
+        // Release reentrancy guard
+        reentrancy_guard_end!(stake_pool);
+
+        Ok(())
+    }
+
+    // Opt a position in or out of keeper-completed withdrawals, registering the
+    // token account that cranked payouts go to
+    pub fn set_auto_withdraw(ctx: Context<SetAutoWithdraw>, enabled: bool, keeper_tip_bps: u16) -> Result<()> {
+        // The payout account must belong to the owner and hold the pool mint
+        let payout_token_account = if enabled {
+            let payout = token_interface::unpack_token_account(&ctx.accounts.payout_token_account)?;
+            require!(payout.mint == ctx.accounts.stake_pool.load()?.token_mint, StakeError::InvalidTokenMint);
+            require!(payout.owner == ctx.accounts.owner.key(), StakeError::InvalidUser);
+            ctx.accounts.payout_token_account.key()
+        } else {
+            Pubkey::default()
+        };
+
+        let user_stake = &mut ctx.accounts.user_stake;
+        let old_enabled = user_stake.auto_withdraw;
+        let old_keeper_tip_bps = user_stake.keeper_tip_bps;
+        let old_payout_token_account = user_stake.payout_token_account;
+        user_stake.set_auto_withdraw(enabled, keeper_tip_bps, payout_token_account)?;
+
+        // Emit event
+        let clock = Clock::get()?;
+        emit!(AutoWithdrawUpdatedEvent {
+            user: ctx.accounts.owner.key(),
+            stake_pool: ctx.accounts.stake_pool.key(),
+            old_enabled,
+            new_enabled: enabled,
+            old_keeper_tip_bps,
+            new_keeper_tip_bps: keeper_tip_bps,
+            old_payout_token_account,
+            new_payout_token_account: payout_token_account,
+            slot: clock.slot,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    // Complete a matured withdrawal for an opted-in position, paying the owner's
+    // registered payout account and the keeper's tip (callable by anyone)
+    pub fn crank_withdraw(ctx: Context<CrankWithdraw>) -> Result<()> {
+        let mut stake_pool = ctx.accounts.stake_pool.load_mut()?;
+
+        // Apply reentrancy guard
+        reentrancy_guard!(stake_pool);
+
+        // Validate the opt-in, request, lock and limits; get the amount released now
+        let user_stake = &mut ctx.accounts.user_stake;
+        let clock = Clock::get()?;
+        let amount = stake_pool.check_crank(user_stake, &clock)?;
+
+        // The registered payout account must still belong to the owner and hold the pool mint
+        let owner_token_account = token_interface::unpack_token_account(&ctx.accounts.owner_token_account)?;
+        require!(owner_token_account.mint == stake_pool.token_mint, StakeError::InvalidTokenMint);
+        require!(owner_token_account.owner == user_stake.owner, StakeError::InvalidUser);
+        let decimals = stake_pool.mint_decimals(&ctx.accounts.token_mint)?;
+
+        // Split off the keeper's tip
+        let (owner_amount, keeper_tip) = user_stake.split_keeper_tip(amount);
+        if keeper_tip > 0 {
+            let keeper_token_account = token_interface::unpack_token_account(&ctx.accounts.keeper_token_account)?;
+            require!(keeper_token_account.mint == stake_pool.token_mint, StakeError::InvalidTokenMint);
+        }
+
+        // Check if stake pool vault has sufficient funds
+        require!(
+            token_interface::unpack_token_account(&ctx.accounts.token_vault)?.amount >= amount,
+            StakeError::InsufficientFunds
+        );
+
+        // Compute new stake pool state
+        let new_total = stake_pool.total_staked.checked_sub(amount).ok_or(StakeError::ArithmeticOverflow)?;
+
+        // Pay the owner and the keeper from the vault.
+        // The pool PDA signs, so its data must not stay borrowed during the CPIs.
+        let (token_mint, pool_id_seed, bump) = stake_pool.signer_seed_parts();
+        drop(stake_pool);
+        let seeds = &[
+            b"stake_pool".as_ref(),
+            token_mint.as_ref(),
+            pool_id_seed.as_slice(),
+            &[bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        for (to, payout) in [
+            (&ctx.accounts.owner_token_account, owner_amount),
+            (&ctx.accounts.keeper_token_account, keeper_tip),
+        ] {
+            if payout == 0 {
+                continue;
+            }
+            let cpi_accounts = TransferChecked {
+                from: ctx.accounts.token_vault.to_account_info(),
+                mint: ctx.accounts.token_mint.to_account_info(),
+                to: to.to_account_info(),
+                authority: ctx.accounts.stake_pool_authority.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+            token_interface::transfer_checked(cpi_ctx, payout, decimals)?;
+        }
+
+        // Track the outflow against the circuit breaker (measured before total_staked drops)
+        let stake_pool_key = ctx.accounts.stake_pool.key();
+        let mut stake_pool = ctx.accounts.stake_pool.load_mut()?;
+        stake_pool.record_outflow(stake_pool_key, amount, &clock)?;
+
+        // Update new stake pool state
+        stake_pool.total_staked = new_total;
+
+        // Emit events
+        emit!(WithdrawEvent {
+            user: user_stake.owner,
+            stake_pool: stake_pool_key,
+            amount_withdrawn: amount,
+            seq: stake_pool.next_event_seq(),
+            slot: clock.slot,
+            timestamp: clock.unix_timestamp,
+        });
+        emit!(CrankWithdrawEvent {
+            user: user_stake.owner,
+            stake_pool: stake_pool_key,
+            keeper: ctx.accounts.keeper.key(),
+            owner_amount,
+            keeper_tip,
+            seq: stake_pool.next_event_seq(),
+            slot: clock.slot,
+            timestamp: clock.unix_timestamp,
+        });
+
+        // Reset stake information
+        user_stake.settle_withdrawal(amount)?;
+        record_checkpoints(&ctx.accounts.checkpoints, &ctx.accounts.pool_checkpoints, user_stake, &stake_pool, &clock)?;
 
         // Release reentrancy guard
         reentrancy_guard_end!(stake_pool);
@@ -432,7 +1188,7 @@ pub mod cfx_stake_core {
     // Execute admin withdrawal proposal
     pub fn execute_admin_withdraw(ctx: Context<ExecuteAdminWithdraw>) -> Result<()> {
         let proposal = &mut ctx.accounts.proposal;
-        let stake_pool = &mut ctx.accounts.stake_pool;
+        let mut stake_pool = ctx.accounts.stake_pool.load_mut()?;
 
         // Verify proposal is approved
         require!(proposal.status == ProposalStatus::Approved, StakeError::ProposalNotApproved);
@@ -440,9 +1196,15 @@ pub mod cfx_stake_core {
         // Verify this is an AdminWithdraw proposal
         require!(proposal.proposal_type == ProposalType::AdminWithdraw, StakeError::InvalidProposalType);
 
+        // Verify proposal has not expired
+        require!(!proposal.is_expired(Clock::get()?.slot), StakeError::ProposalExpired);
+
         // Apply reentrancy guard
         reentrancy_guard!(stake_pool);
 
+        // Vault outflows stop while the circuit breaker is tripped
+        require!(stake_pool.breaker_tripped == 0, StakeError::CircuitBreakerTripped);
+
         // Extract amount and recipient from proposal data
         // Data format: [amount: 8 bytes][recipient: 32 bytes]
         require!(proposal.data.len() >= 40, StakeError::InvalidProposalType);
@@ -456,44 +1218,68 @@ pub mod cfx_stake_core {
             .map_err(|_| StakeError::InvalidProposalType)?;
 
         // Verify recipient matches the provided account
-        require!(recipient == ctx.accounts.recipient_token_account.owner, StakeError::InvalidUser);
-
-        // Ensure amount is greater than 0
-        require!(amount > 0, StakeError::AmountMustBeGreaterThanZero);
-
-        // Check if stake pool vault has sufficient funds
-        require!(
-            ctx.accounts.token_vault.amount >= amount,
-            StakeError::InsufficientFunds
-        );
-
-        // Transfer tokens from stake pool vault to recipient account
+        let recipient_token_account = token_interface::unpack_token_account(&ctx.accounts.recipient_token_account)?;
+        require!(recipient_token_account.mint == stake_pool.token_mint, StakeError::InvalidTokenMint);
+        require!(recipient == recipient_token_account.owner, StakeError::InvalidUser);
+        let decimals = stake_pool.mint_decimals(&ctx.accounts.token_mint)?;
+
+        // Only the reconciled surplus can leave; staked principal stays in the vault
+        let vault_balance = token_interface::unpack_token_account(&ctx.accounts.token_vault)?.amount;
+        stake_pool.check_surplus_withdrawal(amount, vault_balance)?;
+
+        // Transfer tokens from stake pool vault to recipient account.
+        // The pool PDA signs, so its data must not stay borrowed during the CPI.
+        let (token_mint, pool_id_seed, bump) = stake_pool.signer_seed_parts();
+        drop(stake_pool);
         let seeds = &[
             b"stake_pool".as_ref(),
-            stake_pool.token_mint.as_ref(),
-            &[stake_pool.bump],
+            token_mint.as_ref(),
+            pool_id_seed.as_slice(),
+            &[bump],
         ];
         let signer = &[&seeds[..]];
 
-        let cpi_accounts = Transfer {
+        let cpi_accounts = TransferChecked {
             from: ctx.accounts.token_vault.to_account_info(),
+            mint: ctx.accounts.token_mint.to_account_info(),
             to: ctx.accounts.recipient_token_account.to_account_info(),
             authority: ctx.accounts.stake_pool_authority.to_account_info(),
         };
 
         let cpi_program = ctx.accounts.token_program.to_account_info();
         let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
-        token::transfer(cpi_ctx, amount)?;
+        token_interface::transfer_checked(cpi_ctx, amount, decimals)?;
+
+        let stake_pool_key = ctx.accounts.stake_pool.key();
+        let mut stake_pool = ctx.accounts.stake_pool.load_mut()?;
+        stake_pool.surplus -= amount;
+
+        // Track the outflow against the circuit breaker
+        let clock = Clock::get()?;
+        stake_pool.record_outflow(stake_pool_key, amount, &clock)?;
 
         // Mark proposal as executed
         proposal.status = ProposalStatus::Executed;
-        proposal.executed_at = Some(Clock::get()?.slot);
+        proposal.executed_at = Some(clock.slot);
 
-        // Emit event
+        // Emit events
         emit!(AdminWithdrawEvent {
+            stake_pool: stake_pool_key,
             recipient,
             amount_withdrawn: amount,
-            timestamp: Clock::get()?.slot,
+            seq: stake_pool.next_event_seq(),
+            slot: clock.slot,
+            timestamp: clock.unix_timestamp,
+        });
+        emit!(ProposalExecutedEvent {
+            stake_pool: stake_pool_key,
+            multisig_config: proposal.multisig_config,
+            proposal_id: proposal.id,
+            proposal_type: proposal.proposal_type,
+            executor: ctx.accounts.executor.key(),
+            seq: stake_pool.next_event_seq(),
+            slot: clock.slot,
+            timestamp: clock.unix_timestamp,
         });
 
         // Release reentrancy guard
@@ -502,516 +1288,3535 @@ pub mod cfx_stake_core {
         Ok(())
     }
 
+    // Execute rescue proposal for foreign tokens held by a pool-owned token account
+    pub fn execute_rescue_tokens(ctx: Context<ExecuteRescueTokens>) -> Result<()> {
+        let proposal = &mut ctx.accounts.proposal;
+        let stake_pool = ctx.accounts.stake_pool.load()?;
 
-}
+        // Verify proposal is approved
+        require!(proposal.status == ProposalStatus::Approved, StakeError::ProposalNotApproved);
 
-// Utility functions for slot-time conversion
-impl StakePool {
-    /// Convert slots to approximate seconds
-    pub fn slots_to_seconds(slots: u64) -> u64 {
-        slots * AVERAGE_SLOT_TIME_MS / 1000
-    }
+        // Verify this is a RescueTokens proposal
+        require!(proposal.proposal_type == ProposalType::RescueTokens, StakeError::InvalidProposalType);
 
-    /// Convert seconds to approximate slots
-    pub fn seconds_to_slots(seconds: u64) -> u64 {
-        seconds * 1000 / AVERAGE_SLOT_TIME_MS
-    }
+        // Verify proposal has not expired
+        require!(!proposal.is_expired(Clock::get()?.slot), StakeError::ProposalExpired);
 
-    /// Convert slots to approximate days
-    pub fn slots_to_days(slots: u64) -> f64 {
-        slots as f64 / SLOTS_PER_DAY as f64
-    }
+        // Extract amount, recipient and source account from proposal data
+        // Data format: [amount: 8 bytes][recipient: 32 bytes][source_token_account: 32 bytes]
+        require!(proposal.data.len() >= 72, StakeError::InvalidProposalType);
 
-    /// Convert days to slots
-    pub fn days_to_slots(days: u64) -> u64 {
-        days * SLOTS_PER_DAY
-    }
-}
+        let amount = u64::from_le_bytes(
+            proposal.data[0..8].try_into()
+                .map_err(|_| StakeError::InvalidProposalType)?
+        );
 
-// Account structures
-#[account]
-pub struct StakePool {
-    pub authority: Pubkey,              // Stake pool administrator (will be replaced by multisig)
-    pub token_mint: Pubkey,             // Token mint
-    pub token_vault: Pubkey,            // Token vault
-    pub lock_duration_slots: u64,       // Lock duration (in slots)
-    pub total_staked: u64,              // Total staked amount
-    pub emergency_mode: bool,           // Emergency pause flag
-    pub reentrancy_guard: bool,         // Reentrancy protection flag
-    pub bump: u8,                       // PDA bump
-}
+        let recipient = Pubkey::try_from(&proposal.data[8..40])
+            .map_err(|_| StakeError::InvalidProposalType)?;
 
-#[account]
-pub struct UserStake {
-    pub owner: Pubkey,                  // User address
-    pub stake_pool: Pubkey,             // Stake pool address
-    pub staked_amount: u64,             // Staked amount
-    pub last_stake_slot: u64,           // Last stake slot
-    pub unlock_slot: u64,               // Unlock slot
-    pub withdrawal_requested: bool,     // Whether withdrawal has been requested
-    pub bump: u8,                       // PDA bump
-}
+        let source = Pubkey::try_from(&proposal.data[40..72])
+            .map_err(|_| StakeError::InvalidProposalType)?;
 
-// Multisig configuration account
-#[account]
-pub struct MultisigConfig {
-    pub signers: [Pubkey; 3],           // 3 multisig signers
-    pub threshold: u8,                  // Required signatures (e.g., 2 out of 3)
-    pub stake_pool: Pubkey,             // Associated stake pool
-    pub proposal_count: u64,            // Total number of proposals created
-    pub bump: u8,                       // PDA bump
-}
+        // Never touch the stake vault or the staked mint; the source must be held by the pool PDA
+        require!(source == ctx.accounts.source_token_account.key(), StakeError::InvalidRescueAccount);
+        let source_token_account = token_interface::unpack_token_account(&ctx.accounts.source_token_account)?;
+        stake_pool.check_rescue_source(
+            &source,
+            &source_token_account.mint,
+            &source_token_account.owner,
+            &ctx.accounts.stake_pool_authority.key(),
+        )?;
+
+        // The source must be owned by the given token program
+        require!(
+            *ctx.accounts.source_token_account.owner == ctx.accounts.token_program.key(),
+            StakeError::InvalidTokenProgram
+        );
 
-// Multisig proposal account
-#[account]
-pub struct MultisigProposal {
-    pub id: u64,                        // Proposal ID
-    pub proposal_type: ProposalType,    // Type of proposal
-    pub proposer: Pubkey,               // Who created the proposal
-    pub multisig_config: Pubkey,        // Associated multisig config
-    pub status: ProposalStatus,         // Current status
-    pub signatures: [bool; 3],          // Signature status for each signer
-    pub signature_count: u8,            // Number of signatures collected
-    pub created_at: u64,                // Creation slot
-    pub executed_at: Option<u64>,       // Execution slot (if executed)
-    pub data: Vec<u8>,                  // Proposal-specific data
-    pub bump: u8,                       // PDA bump
-}
+        // Verify recipient matches the provided account
+        let recipient_token_account = token_interface::unpack_token_account(&ctx.accounts.recipient_token_account)?;
+        require!(recipient_token_account.mint == source_token_account.mint, StakeError::InvalidTokenMint);
+        require!(recipient == recipient_token_account.owner, StakeError::InvalidUser);
 
-impl StakePool {
-    // authority(32) + token_mint(32) + token_vault(32) +
-    // lock_duration_slots(8) + total_staked(8) + emergency_mode(1) + reentrancy_guard(1) + bump(1)
-    pub const LEN: usize = 32 + 32 + 32 + 8 + 8 + 1 + 1 + 1;
-}
+        require!(ctx.accounts.mint.key() == source_token_account.mint, StakeError::InvalidTokenMint);
+        let decimals = token_interface::unpack_mint(&ctx.accounts.mint)?.decimals;
 
-impl UserStake {
-    // owner(32) + stake_pool(32) + staked_amount(8) +
-    // last_stake_slot(8) + unlock_slot(8) + withdrawal_requested(1) + bump(1)
-    pub const LEN: usize = 32 + 32 + 8 + 8 + 8 + 1 + 1;
-}
+        // Ensure amount is greater than 0 and available
+        require!(amount > 0, StakeError::AmountMustBeGreaterThanZero);
+        require!(source_token_account.amount >= amount, StakeError::InsufficientFunds);
 
-impl MultisigConfig {
-    // signers(32*3) + threshold(1) + stake_pool(32) + proposal_count(8) + bump(1)
-    pub const LEN: usize = 32 * 3 + 1 + 32 + 8 + 1;
-}
+        // Transfer tokens from the pool-owned account to the recipient account.
+        // The pool PDA signs, so its data must not stay borrowed during the CPI.
+        let (token_mint, pool_id_seed, bump) = stake_pool.signer_seed_parts();
+        drop(stake_pool);
+        let seeds = &[
+            b"stake_pool".as_ref(),
+            token_mint.as_ref(),
+            pool_id_seed.as_slice(),
+            &[bump],
+        ];
+        let signer = &[&seeds[..]];
 
-impl MultisigProposal {
-    // id(8) + proposal_type(1) + proposer(32) + multisig_config(32) + status(1) +
-    // signatures(1*3) + signature_count(1) + created_at(8) + executed_at(9) + data(4+256) + bump(1)
-    pub const LEN: usize = 8 + 1 + 32 + 32 + 1 + 3 + 1 + 8 + 9 + 4 + 256 + 1;
-}
+        let cpi_accounts = TransferChecked {
+            from: ctx.accounts.source_token_account.to_account_info(),
+            mint: ctx.accounts.mint.to_account_info(),
+            to: ctx.accounts.recipient_token_account.to_account_info(),
+            authority: ctx.accounts.stake_pool_authority.to_account_info(),
+        };
 
-// Error enumeration
-#[error_code]
-pub enum StakeError {
-    #[msg("Amount must be greater than zero")]
-    AmountMustBeGreaterThanZero,
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token_interface::transfer_checked(cpi_ctx, amount, decimals)?;
 
-    #[msg("Below minimum stake amount")]
-    BelowMinimumStakeAmount,
+        // Mark proposal as executed
+        let clock = Clock::get()?;
+        proposal.status = ProposalStatus::Executed;
+        proposal.executed_at = Some(clock.slot);
+
+        // Emit events
+        let mut stake_pool = ctx.accounts.stake_pool.load_mut()?;
+        let stake_pool_key = ctx.accounts.stake_pool.key();
+        emit!(TokensRescuedEvent {
+            stake_pool: stake_pool_key,
+            mint: source_token_account.mint,
+            source,
+            recipient,
+            amount,
+            seq: stake_pool.next_event_seq(),
+            slot: clock.slot,
+            timestamp: clock.unix_timestamp,
+        });
+        emit!(ProposalExecutedEvent {
+            stake_pool: stake_pool_key,
+            multisig_config: proposal.multisig_config,
+            proposal_id: proposal.id,
+            proposal_type: proposal.proposal_type,
+            executor: ctx.accounts.executor.key(),
+            seq: stake_pool.next_event_seq(),
+            slot: clock.slot,
+            timestamp: clock.unix_timestamp,
+        });
 
-    #[msg("Contract is paused")]
-    ContractPaused,
+        Ok(())
+    }
 
-    #[msg("No staked tokens")]
-    NoStakedTokens,
+    // Execute a SetTierTable proposal (requires multi-sig approval)
+    pub fn execute_set_tier_table(ctx: Context<ExecuteSetTierTable>) -> Result<()> {
+        let proposal = &mut ctx.accounts.proposal;
 
-    #[msg("Withdrawal already requested")]
-    WithdrawalAlreadyRequested,
+        // Verify proposal is approved
+        require!(proposal.status == ProposalStatus::Approved, StakeError::ProposalNotApproved);
 
-    #[msg("Withdrawal not requested")]
-    WithdrawalNotRequested,
+        // Verify this is a SetTierTable proposal
+        require!(proposal.proposal_type == ProposalType::SetTierTable, StakeError::InvalidProposalType);
 
-    #[msg("Tokens still locked")]
-    TokensStillLocked,
+        // Verify proposal has not expired
+        let clock = Clock::get()?;
+        require!(!proposal.is_expired(clock.slot), StakeError::ProposalExpired);
 
-    #[msg("Arithmetic overflow")]
-    ArithmeticOverflow,
+        // Data format: [count: 1 byte][min_stake: 8 bytes, min_lock: 8 bytes] * count
+        let tiers = TierTable::parse_tiers(&proposal.data, &*ctx.accounts.stake_pool.load()?)?;
+        let tier_table = &mut ctx.accounts.tier_table;
+        let old_tiers = std::mem::replace(&mut tier_table.tiers, tiers);
 
-    #[msg("Invalid token mint")]
-    InvalidTokenMint,
+        // Tiers cached on positions under the previous table no longer count
+        tier_table.revision = tier_table.revision.checked_add(1).ok_or(StakeError::ArithmeticOverflow)?;
 
-    #[msg("Invalid team wallet")]
-    InvalidTeamWallet,
+        // Mark proposal as executed
+        proposal.status = ProposalStatus::Executed;
+        proposal.executed_at = Some(clock.slot);
+
+        // Emit events
+        let mut stake_pool = ctx.accounts.stake_pool.load_mut()?;
+        let stake_pool_key = ctx.accounts.stake_pool.key();
+        emit!(TierTableUpdatedEvent {
+            stake_pool: stake_pool_key,
+            tier_table: tier_table.key(),
+            old_tiers,
+            new_tiers: tier_table.tiers.clone(),
+            seq: stake_pool.next_event_seq(),
+            slot: clock.slot,
+            timestamp: clock.unix_timestamp,
+        });
+        emit!(ProposalExecutedEvent {
+            stake_pool: stake_pool_key,
+            multisig_config: proposal.multisig_config,
+            proposal_id: proposal.id,
+            proposal_type: proposal.proposal_type,
+            executor: ctx.accounts.executor.key(),
+            seq: stake_pool.next_event_seq(),
+            slot: clock.slot,
+            timestamp: clock.unix_timestamp,
+        });
 
-    #[msg("Insufficient funds in stake pool vault")]
-    InsufficientFunds,
+        Ok(())
+    }
 
-    #[msg("Invalid user")]
-    InvalidUser,
+    // Convert a stake pool account to the current zero-copy layout and fill defaults for new fields (callable by anyone)
+    pub fn migrate_stake_pool(ctx: Context<MigrateStakePool>) -> Result<()> {
+        let info = ctx.accounts.stake_pool.to_account_info();
+
+        // Accounts already at the zero-copy size use the current layout
+        let old_len = info.data_len();
+        require!(old_len != 8 + StakePool::LEN, StakeError::AccountAlreadyMigrated);
+
+        // Version 2 pools are zero-copy already; growing them zero-fills the larger reserved space
+        if old_len == 8 + StakePool::V2_LEN {
+            resize_account::<StakePool>(
+                &info,
+                &ctx.accounts.payer,
+                &ctx.accounts.system_program,
+                8 + StakePool::LEN,
+            )?;
+            let loader = AccountLoader::<StakePool>::try_from(&info)?;
+            let mut stake_pool = loader.load_mut()?;
+            let from_version = stake_pool.version;
+            stake_pool.version = StakePool::VERSION;
+            let seq = stake_pool.next_event_seq();
+            return emit_migration(info.key(), info.key(), seq, from_version, StakePool::VERSION, old_len);
+        }
 
-    #[msg("Reentrancy attack detected")]
-    ReentrancyDetected,
+        // Copy the Borsh data out before the account is resized
+        let data = info.try_borrow_data()?.to_vec();
+        resize_account::<StakePool>(
+            &info,
+            &ctx.accounts.payer,
+            &ctx.accounts.system_program,
+            8 + StakePool::LEN,
+        )?;
+
+        let old = StakePoolV1::decode(&data)?;
+        let mut stake_pool = old.upgrade();
+
+        // Pools created before the token program was recorded keep the behavior they were deployed with
+        if old_len < 8 + StakePool::LEGACY_LEN + 32 {
+            require!(ctx.accounts.token_mint.key() == stake_pool.token_mint, StakeError::InvalidTokenMint);
+            require!(
+                *ctx.accounts.token_mint.owner == token_interface::TOKEN_PROGRAM_ID,
+                StakeError::InvalidTokenProgram
+            );
+            stake_pool.token_program = token_interface::TOKEN_PROGRAM_ID;
+            stake_pool.decimals = token_interface::unpack_mint(&ctx.accounts.token_mint)?.decimals;
+            stake_pool.lock_clock = LockClock::Slot as u8;
+            stake_pool.lock_duration_seconds = StakePool::slots_to_seconds(stake_pool.lock_duration_slots);
+            stake_pool.pool_id = 0;
+            stake_pool.limits = PoolLimits::default();
+        }
+        let seq = stake_pool.next_event_seq();
+        *AccountLoader::<StakePool>::try_from(&info)?.load_mut()? = stake_pool;
 
-    #[msg("Invalid multisig signer")]
-    InvalidMultisigSigner,
+        emit_migration(info.key(), info.key(), seq, old.version, StakePool::VERSION, old_len)
+    }
 
-    #[msg("Insufficient signatures")]
-    InsufficientSignatures,
+    // Grow a user stake account to the current layout (callable by anyone)
+    pub fn migrate_user_stake(ctx: Context<MigrateUserStake>) -> Result<()> {
+        let info = ctx.accounts.user_stake.to_account_info();
+        let old_len = resize_account::<UserStake>(
+            &info,
+            &ctx.accounts.payer,
+            &ctx.accounts.system_program,
+            8 + UserStake::LEN,
+        )?;
+
+        // Every field added since the original layout defaults to zero
+        let mut user_stake = UserStake::try_deserialize(&mut &info.try_borrow_data()?[..])?;
+        require!(user_stake.version < UserStake::VERSION, StakeError::AccountAlreadyMigrated);
+        require_keys_eq!(user_stake.stake_pool, ctx.accounts.stake_pool.key(), ErrorCode::ConstraintHasOne);
+
+        let from_version = user_stake.migrate(&*ctx.accounts.stake_pool.load()?);
+        user_stake.try_serialize(&mut &mut info.try_borrow_mut_data()?[..])?;
+
+        let seq = ctx.accounts.stake_pool.load_mut()?.next_event_seq();
+        emit_migration(info.key(), user_stake.stake_pool, seq, from_version, UserStake::VERSION, old_len)
+    }
 
-    #[msg("Proposal already executed")]
-    ProposalAlreadyExecuted,
+    // Grow a multisig config account to the current layout (callable by anyone)
+    pub fn migrate_multisig_config(ctx: Context<MigrateMultisigConfig>) -> Result<()> {
+        let info = ctx.accounts.multisig_config.to_account_info();
+        let old_len = resize_account::<MultisigConfig>(
+            &info,
+            &ctx.accounts.payer,
+            &ctx.accounts.system_program,
+            8 + MultisigConfig::LEN,
+        )?;
+
+        let mut multisig_config = MultisigConfig::try_deserialize(&mut &info.try_borrow_data()?[..])?;
+        require!(multisig_config.version < MultisigConfig::VERSION, StakeError::AccountAlreadyMigrated);
+        require_keys_eq!(multisig_config.stake_pool, ctx.accounts.stake_pool.key(), ErrorCode::ConstraintHasOne);
+        let from_version = multisig_config.version;
+        multisig_config.version = MultisigConfig::VERSION;
+        multisig_config.try_serialize(&mut &mut info.try_borrow_mut_data()?[..])?;
+
+        let seq = ctx.accounts.stake_pool.load_mut()?.next_event_seq();
+        emit_migration(info.key(), multisig_config.stake_pool, seq, from_version, MultisigConfig::VERSION, old_len)
+    }
 
-    #[msg("Proposal not approved")]
-    ProposalNotApproved,
+    // Grow a multisig proposal account to the current layout (callable by anyone)
+    pub fn migrate_proposal(ctx: Context<MigrateProposal>) -> Result<()> {
+        let info = ctx.accounts.proposal.to_account_info();
+        let old_len = resize_account::<MultisigProposal>(
+            &info,
+            &ctx.accounts.payer,
+            &ctx.accounts.system_program,
+            8 + MultisigProposal::LEN,
+        )?;
+
+        // Proposals with long data only decode once the missing tail has been added
+        let mut proposal = MultisigProposal::try_deserialize(&mut &info.try_borrow_data()?[..])?;
+        require!(proposal.version < MultisigProposal::VERSION, StakeError::AccountAlreadyMigrated);
+        require_keys_eq!(proposal.multisig_config, ctx.accounts.multisig_config.key(), ErrorCode::ConstraintHasOne);
+        let from_version = proposal.version;
+        proposal.version = MultisigProposal::VERSION;
+        proposal.try_serialize(&mut &mut info.try_borrow_mut_data()?[..])?;
+
+        let seq = ctx.accounts.stake_pool.load_mut()?.next_event_seq();
+        emit_migration(info.key(), ctx.accounts.stake_pool.key(), seq, from_version, MultisigProposal::VERSION, old_len)
+    }
 
-    #[msg("Invalid proposal type")]
-    InvalidProposalType,
+    // Reconcile the vault balance with the pool's liabilities (callable by anyone)
+    pub fn sync_vault(ctx: Context<SyncVault>) -> Result<()> {
+        let mut stake_pool = ctx.accounts.stake_pool.load_mut()?;
 
-    #[msg("Invalid threshold")]
-    InvalidThreshold,
+        // Credit the surplus (e.g. direct transfers into the vault) to the multisig-withdrawable ledger
+        let vault_balance = token_interface::unpack_token_account(&ctx.accounts.token_vault)?.amount;
+        let previous_surplus = stake_pool.surplus;
+        let deficit = stake_pool.reconcile_vault(vault_balance);
+        let (liabilities, surplus) = (stake_pool.total_staked, stake_pool.surplus);
 
-    #[msg("Already signed")]
-    AlreadySigned,
+        // Emit event
+        let clock = Clock::get()?;
+        emit!(VaultSyncedEvent {
+            stake_pool: ctx.accounts.stake_pool.key(),
+            vault_balance,
+            total_staked: liabilities,
+            surplus,
+            previous_surplus,
+            deficit,
+            seq: stake_pool.next_event_seq(),
+            slot: clock.slot,
+            timestamp: clock.unix_timestamp,
+        });
 
-    #[msg("Exceeds maximum stake amount")]
-    ExceedsMaximumStakeAmount,
+        Ok(())
+    }
 
-    #[msg("Exceeds maximum pool size")]
-    ExceedsMaximumPoolSize,
+    // View: a position's withdrawal status (returned via return data, for simulateTransaction)
+    pub fn get_position_status(ctx: Context<GetPositionStatus>) -> Result<PositionStatus> {
+        let stake_pool = ctx.accounts.stake_pool.load()?;
+        Ok(stake_pool.position_status(&ctx.accounts.user_stake, &Clock::get()?))
+    }
 
-    #[msg("Excessive lock duration")]
-    ExcessiveLockDuration,
+    // Recompute a position's service tier, cache it on the position and return it (callable by anyone, including via CPI)
+    pub fn verify_tier(ctx: Context<VerifyTier>) -> Result<u8> {
+        let stake_pool = ctx.accounts.stake_pool.load()?;
+        let user_stake = &mut ctx.accounts.user_stake;
+        let clock = Clock::get()?;
 
-    #[msg("Below minimum unstake amount")]
-    BelowMinimumUnstakeAmount,
+        let old_tier = user_stake.tier;
+        let tier = user_stake.verify_tier(&ctx.accounts.tier_table, &stake_pool, &clock)?;
 
-    #[msg("Exceeds maximum unstake amount")]
-    ExceedsMaximumUnstakeAmount,
+        // Emit event
+        emit!(TierVerifiedEvent {
+            user: user_stake.owner,
+            stake_pool: ctx.accounts.stake_pool.key(),
+            old_tier,
+            tier,
+            staked_amount: user_stake.staked_amount,
+            lock_end: user_stake.lock_end,
+            slot: clock.slot,
+            timestamp: clock.unix_timestamp,
+        });
 
-    #[msg("Duplicate signers for multisig")]
-    DuplicateSigner,
+        Ok(tier)
+    }
 
-    #[msg("Proposal data too large")]
-    ProposalDataTooLarge,
-}
+    // View: a position's staked amount and the pool total at the end of a past slot,
+    // looked up by binary search in the checkpoint ring buffers
+    pub fn stake_at(ctx: Context<StakeAt>, slot: u64) -> Result<StakeAtSlot> {
+        require!(slot <= Clock::get()?.slot, StakeError::CheckpointUnavailable);
 
-// Account validation structures
-#[derive(Accounts)]
-#[instruction(stake_pool_bump: u8)]
-pub struct Initialize<'info> {
-    #[account(
-        init,
-        payer = authority,
-        space = 8 + StakePool::LEN,
-        seeds = [b"stake_pool".as_ref(), token_mint.key().as_ref()],
-        bump,
-    )]
-    pub stake_pool: Account<'info, StakePool>,
+        let amount = ctx.accounts.checkpoints.load()?.amount_at(slot);
+        let pool_total = ctx.accounts.pool_checkpoints.load()?.amount_at(slot);
+        match (amount, pool_total) {
+            (Some(amount), Some(pool_total)) => Ok(StakeAtSlot { slot, amount, pool_total }),
+            _ => err!(StakeError::CheckpointUnavailable),
+        }
+    }
 
-    pub token_mint: Account<'info, token::Mint>,
+    // Stable position snapshot for other programs, in the layout defined by
+    // `cfx-stake-interface` (read-only; the discriminator and accounts never change)
+    pub fn get_position_snapshot(ctx: Context<GetPositionSnapshot>) -> Result<()> {
+        let stake_pool = ctx.accounts.stake_pool.load()?;
+        let user_stake = &ctx.accounts.user_stake;
+        let clock = Clock::get()?;
+
+        // Pools without a tier table report tier 0
+        let tier_table = &ctx.accounts.tier_table;
+        let tier = if tier_table.owner == &ID && !tier_table.data_is_empty() {
+            TierTable::try_deserialize(&mut &tier_table.try_borrow_data()?[..])?
+                .tier_of(&stake_pool, user_stake, &clock)?
+        } else {
+            0
+        };
 
-    #[account(
-        init,
-        payer = authority,
-        token::mint = token_mint,
-        token::authority = stake_pool,
-    )]
-    pub token_vault: Account<'info, TokenAccount>,
+        let snapshot = PositionSnapshot {
+            version: PositionSnapshot::VERSION,
+            owner: user_stake.owner,
+            stake_pool: user_stake.stake_pool,
+            staked_amount: user_stake.staked_amount,
+            lock_end: user_stake.lock_end,
+            lock_clock: match stake_pool.lock_clock() {
+                LockClock::Slot => cfx_stake_interface::LockClock::Slot,
+                LockClock::UnixTimestamp => cfx_stake_interface::LockClock::UnixTimestamp,
+            },
+            tier,
+            voting_power: user_stake.voting_power(&stake_pool, &clock),
+            withdrawal_requested: user_stake.withdrawal_requested,
+        };
+        set_return_data(&snapshot.to_bytes());
 
-    #[account(mut)]
-    pub authority: Signer<'info>,
+        Ok(())
+    }
 
-    pub system_program: Program<'info, System>,
-    pub token_program: Program<'info, Token>,
-    pub rent: Sysvar<'info, Rent>,
-}
+    // View: pool totals, state flags and limits in base units
+    pub fn get_pool_stats(ctx: Context<GetPoolStats>) -> Result<PoolStats> {
+        let stake_pool = ctx.accounts.stake_pool.load()?;
+        stake_pool.stats(ctx.accounts.stake_pool.key())
+    }
 
-#[derive(Accounts)]
-#[instruction(multisig_bump: u8)]
-pub struct InitializeMultisig<'info> {
-    #[account(
-        init,
-        payer = authority,
-        space = 8 + MultisigConfig::LEN,
-        seeds = [b"multisig_config", stake_pool.key().as_ref()],
-        bump,
-    )]
-    pub multisig_config: Account<'info, MultisigConfig>,
+    // Close an empty user stake account and return its rent to the owner
+    pub fn close_user_stake(ctx: Context<CloseUserStake>) -> Result<()> {
+        let user_stake = &ctx.accounts.user_stake;
 
-    #[account(
-        mut,
-        has_one = authority,
-    )]
-    pub stake_pool: Account<'info, StakePool>,
+        // Ensure nothing is left in the position
+        user_stake.check_close()?;
 
-    #[account(mut)]
-    pub authority: Signer<'info>,
+        // Emit event
+        let clock = Clock::get()?;
+        emit!(UserStakeClosedEvent {
+            user: ctx.accounts.owner.key(),
+            stake_pool: user_stake.stake_pool,
+            slot: clock.slot,
+            timestamp: clock.unix_timestamp,
+        });
 
-    pub system_program: Program<'info, System>,
-    pub rent: Sysvar<'info, Rent>,
-}
+        Ok(())
+    }
 
-#[derive(Accounts)]
-#[instruction(_proposal_bump: u8)]
-pub struct CreateProposal<'info> {
-    #[account(
-        init,
-        payer = proposer,
-        space = 8 + MultisigProposal::LEN,
-        seeds = [b"proposal", multisig_config.key().as_ref(), &multisig_config.proposal_count.to_le_bytes()],
-        bump,
+    // Close an executed, rejected or expired proposal and return its rent to the proposer
+    pub fn close_proposal(ctx: Context<CloseProposal>) -> Result<()> {
+        let multisig_config = &ctx.accounts.multisig_config;
+        let proposal = &ctx.accounts.proposal;
+        let clock = Clock::get()?;
+        let current_slot = clock.slot;
+
+        // Verify closer is one of the multisig signers
+        require!(
+            multisig_config.signers.contains(&ctx.accounts.closer.key()),
+            StakeError::InvalidMultisigSigner
+        );
+
+        // Only finished or expired proposals can be closed
+        let closable = match proposal.status {
+            ProposalStatus::Executed | ProposalStatus::Rejected => true,
+            ProposalStatus::Pending | ProposalStatus::Approved => proposal.is_expired(current_slot),
+        };
+        require!(closable, StakeError::ProposalNotClosable);
+
+        // Expired proposals are recorded as rejected
+        let final_status = match proposal.status {
+            ProposalStatus::Executed => ProposalStatus::Executed,
+            _ => ProposalStatus::Rejected,
+        };
+
+        // Keep a compact record of the proposal for the audit trail
+        let proposal_record = &mut ctx.accounts.proposal_record;
+        proposal_record.id = proposal.id;
+        proposal_record.proposal_type = proposal.proposal_type;
+        proposal_record.proposer = proposal.proposer;
+        proposal_record.multisig_config = proposal.multisig_config;
+        proposal_record.status = final_status;
+        proposal_record.signatures = proposal.signatures;
+        proposal_record.created_at = proposal.created_at;
+        proposal_record.executed_at = proposal.executed_at;
+        proposal_record.closed_at = current_slot;
+        proposal_record.data_hash = hash(&proposal.data).to_bytes();
+        proposal_record.bump = *ctx.bumps.get("proposal_record").unwrap();
+
+        // Emit event
+        emit!(ProposalClosedEvent {
+            stake_pool: multisig_config.stake_pool,
+            proposal_id: proposal.id,
+            multisig_config: proposal.multisig_config,
+            status: final_status,
+            closer: ctx.accounts.closer.key(),
+            rent_recipient: proposal.proposer,
+            slot: current_slot,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+}
+
+// Utility functions for slot-time conversion
+impl StakePool {
+    /// Convert slots to approximate seconds
+    pub fn slots_to_seconds(slots: u64) -> u64 {
+        slots * AVERAGE_SLOT_TIME_MS / 1000
+    }
+
+    /// Convert seconds to approximate slots
+    pub fn seconds_to_slots(seconds: u64) -> u64 {
+        seconds * 1000 / AVERAGE_SLOT_TIME_MS
+    }
+
+    /// Convert slots to approximate days
+    pub fn slots_to_days(slots: u64) -> f64 {
+        slots as f64 / SLOTS_PER_DAY as f64
+    }
+
+    /// Convert days to slots
+    pub fn days_to_slots(days: u64) -> u64 {
+        days * SLOTS_PER_DAY
+    }
+}
+
+// Pool identifier helpers
+impl StakePool {
+    /// Token mint, pool id seed and bump for signing as the pool PDA, copied out
+    /// so the pool data need not stay borrowed during a CPI
+    pub fn signer_seed_parts(&self) -> (Pubkey, Vec<u8>, u8) {
+        (self.token_mint, Self::pool_id_seed(self.pool_id), self.bump)
+    }
+
+    /// PDA seed for a pool id. Pool 0 uses an empty seed so it keeps the
+    /// original `[stake_pool, token_mint]` address.
+    pub fn pool_id_seed(pool_id: u64) -> Vec<u8> {
+        if pool_id == 0 {
+            Vec::new()
+        } else {
+            pool_id.to_le_bytes().to_vec()
+        }
+    }
+}
+
+// Event sequencing
+impl StakePool {
+    /// Advance and return the pool's event sequence number. Every event about
+    /// the pool carries one, so indexers can detect events they missed.
+    pub fn next_event_seq(&mut self) -> u64 {
+        self.event_seq = self.event_seq.wrapping_add(1);
+        self.event_seq
+    }
+}
+
+// Lock clock helpers
+impl StakePool {
+    /// Whether a position's unlock point has passed on the pool's lock clock
+    pub fn is_unlocked(&self, user_stake: &UserStake, clock: &Clock) -> bool {
+        match self.lock_clock() {
+            LockClock::Slot => clock.slot >= user_stake.unlock_slot,
+            LockClock::UnixTimestamp => clock.unix_timestamp >= user_stake.unlock_timestamp,
+        }
+    }
+
+    /// Clock used to measure lock durations
+    pub fn lock_clock(&self) -> LockClock {
+        if self.lock_clock == LockClock::UnixTimestamp as u8 {
+            LockClock::UnixTimestamp
+        } else {
+            LockClock::Slot
+        }
+    }
+
+    /// Current time on the pool's lock clock (slot or unix seconds)
+    pub fn clock_now(&self, clock: &Clock) -> u64 {
+        match self.lock_clock() {
+            LockClock::Slot => clock.slot,
+            LockClock::UnixTimestamp => clock.unix_timestamp.max(0) as u64,
+        }
+    }
+
+    /// Maximum lock duration on the pool's lock clock
+    pub fn max_lock_duration(&self) -> u64 {
+        match self.lock_clock() {
+            LockClock::Slot => MAX_LOCK_DURATION_SLOTS,
+            LockClock::UnixTimestamp => MAX_LOCK_DURATION_SECONDS,
+        }
+    }
+
+    /// Maximum vesting schedule length on the pool's lock clock
+    pub fn max_vesting_duration(&self) -> u64 {
+        match self.lock_clock() {
+            LockClock::Slot => MAX_VESTING_DURATION_SLOTS,
+            LockClock::UnixTimestamp => MAX_VESTING_DURATION_SECONDS,
+        }
+    }
+
+    /// Convert a duration on the pool's lock clock into (slots, seconds)
+    pub fn to_slots_and_seconds(&self, duration: u64) -> (u64, u64) {
+        match self.lock_clock() {
+            LockClock::Slot => (duration, Self::slots_to_seconds(duration)),
+            LockClock::UnixTimestamp => (Self::seconds_to_slots(duration), duration),
+        }
+    }
+
+    /// Length of an outflow epoch on the pool's lock clock (one day)
+    pub fn outflow_epoch_length(&self) -> u64 {
+        match self.lock_clock() {
+            LockClock::Slot => SLOTS_PER_DAY,
+            LockClock::UnixTimestamp => SECONDS_PER_DAY,
+        }
+    }
+}
+
+// Circuit breaker
+impl StakePool {
+    /// Length of one breaker bucket, so that the full buckets span the window
+    fn breaker_bucket_slots(&self) -> u64 {
+        let full_buckets = BREAKER_BUCKETS as u64 - 1;
+        self.breaker_window_slots.div_ceil(full_buckets).max(1)
+    }
+
+    /// Forget all outflows counted by the breaker
+    pub fn clear_breaker_window(&mut self) {
+        self.breaker_window_start = 0;
+        self.breaker_window_outflow = 0;
+        self.breaker_buckets = [0; BREAKER_BUCKETS];
+    }
+
+    /// Add a vault outflow to the rolling breaker window and trip the breaker once
+    /// the window's outflows reach `breaker_threshold_bps` of `total_staked`.
+    ///
+    /// Must be called before the outflow is taken off `total_staked`, so the
+    /// threshold is measured against the pool as it was. An empty pool has no
+    /// stake to protect and never trips.
+    ///
+    /// The outflow that crosses the threshold still goes through; every later one
+    /// is refused until a `ResetCircuitBreaker` proposal is executed.
+    pub fn record_outflow(&mut self, stake_pool: Pubkey, amount: u64, clock: &Clock) -> Result<()> {
+        if self.breaker_threshold_bps == 0 {
+            return Ok(());
+        }
+
+        // Empty the buckets that have left the window since the last outflow
+        let bucket_slots = self.breaker_bucket_slots();
+        let bucket = clock.slot / bucket_slots;
+        let last_bucket = self.breaker_window_start / bucket_slots;
+        let stale = bucket.saturating_sub(last_bucket).min(BREAKER_BUCKETS as u64);
+        for offset in 1..=stale {
+            self.breaker_buckets[((last_bucket + offset) % BREAKER_BUCKETS as u64) as usize] = 0;
+        }
+        self.breaker_window_start = self.breaker_window_start.max(bucket * bucket_slots);
+
+        let current = &mut self.breaker_buckets[(bucket % BREAKER_BUCKETS as u64) as usize];
+        *current = current.checked_add(amount).ok_or(StakeError::ArithmeticOverflow)?;
+        self.breaker_window_outflow = self.breaker_buckets
+            .iter()
+            .try_fold(0u64, |sum, outflow| sum.checked_add(*outflow))
+            .ok_or(StakeError::ArithmeticOverflow)?;
+
+        if self.total_staked == 0 {
+            return Ok(());
+        }
+        let threshold = self.total_staked as u128 * self.breaker_threshold_bps as u128 / BASIS_POINTS as u128;
+        if self.breaker_window_outflow as u128 >= threshold {
+            self.breaker_tripped = 1;
+            emit!(CircuitBreakerTrippedEvent {
+                stake_pool,
+                window_outflow: self.breaker_window_outflow,
+                total_staked: self.total_staked,
+                threshold_bps: self.breaker_threshold_bps,
+                seq: self.next_event_seq(),
+                slot: clock.slot,
+                timestamp: clock.unix_timestamp,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+// Withdrawal queue
+impl StakePool {
+    /// Reserve `amount` of outflow capacity for a withdrawal that would unlock at
+    /// `unlock_at`, returning the point from which it may leave the vault.
+    ///
+    /// Reservations are handed out in request order: each one takes the remaining
+    /// capacity of the queue's tail epoch and spills into the following epochs, so
+    /// a request never overtakes an earlier one.
+    pub fn reserve_outflow(&mut self, amount: u64, unlock_at: u64) -> Result<u64> {
+        if self.outflow_cap == 0 {
+            return Ok(unlock_at);
+        }
+        let epoch_length = self.outflow_epoch_length();
+        let cap = self.outflow_cap;
+
+        // Start a fresh epoch if the queue has drained before this unlock point
+        let unlock_epoch = unlock_at / epoch_length;
+        if self.outflow_queue_epoch < unlock_epoch {
+            self.outflow_queue_epoch = unlock_epoch;
+            self.outflow_queue_used = 0;
+        }
+
+        let free = cap - self.outflow_queue_used.min(cap);
+        if amount <= free {
+            self.outflow_queue_used += amount;
+        } else {
+            // Spill the rest over as many full epochs as needed
+            let rest = amount - free;
+            let extra_epochs = (rest - 1) / cap + 1;
+            self.outflow_queue_epoch = self.outflow_queue_epoch
+                .checked_add(extra_epochs)
+                .ok_or(StakeError::ArithmeticOverflow)?;
+            self.outflow_queue_used = rest - (extra_epochs - 1) * cap;
+        }
+
+        let epoch_start = self.outflow_queue_epoch
+            .checked_mul(epoch_length)
+            .ok_or(StakeError::ArithmeticOverflow)?;
+        Ok(unlock_at.max(epoch_start))
+    }
+}
+
+// Views for `get_position_status` and `get_pool_stats`
+impl StakePool {
+    /// What `withdraw` would do for `user_stake` now, and how long until it can
+    pub fn position_status(&self, user_stake: &UserStake, clock: &Clock) -> PositionStatus {
+        // Time left until the unlock point, on both clocks
+        let unlocked = user_stake.withdrawal_requested && self.is_unlocked(user_stake, clock);
+        let (slots_until_unlock, seconds_until_unlock) = if user_stake.withdrawal_requested {
+            let remaining = match self.lock_clock() {
+                LockClock::Slot => user_stake.unlock_slot.saturating_sub(clock.slot),
+                LockClock::UnixTimestamp => user_stake.unlock_timestamp.saturating_sub(clock.unix_timestamp).max(0) as u64,
+            };
+            self.to_slots_and_seconds(remaining)
+        } else {
+            (0, 0)
+        };
+
+        // Amount `withdraw` would pay out now
+        let withdrawable_amount = if unlocked && self.breaker_tripped == 0 {
+            user_stake.payout_amount(self, clock)
+        } else {
+            0
+        };
+
+        PositionStatus {
+            owner: user_stake.owner,
+            stake_pool: user_stake.stake_pool,
+            staked_amount: user_stake.staked_amount,
+            withdrawal_requested: user_stake.withdrawal_requested,
+            unlocked,
+            slots_until_unlock,
+            seconds_until_unlock,
+            withdrawable_amount,
+            unvested_amount: user_stake.vesting.unvested(self.clock_now(clock)),
+            lock_end: user_stake.lock_end,
+            voting_power: user_stake.voting_power(self, clock),
+        }
+    }
+
+    /// Totals, state and effective limits of the pool at `address`, in base units
+    pub fn stats(&self, address: Pubkey) -> Result<PoolStats> {
+        let max_pool_size = self.max_pool_size()?;
+
+        Ok(PoolStats {
+            stake_pool: address,
+            token_mint: self.token_mint,
+            total_staked: self.total_staked,
+            remaining_capacity: max_pool_size.saturating_sub(self.total_staked),
+            emergency_mode: self.emergency_mode != 0,
+            breaker_tripped: self.breaker_tripped != 0,
+            lock_clock: self.lock_clock(),
+            lock_duration_slots: self.lock_duration_slots,
+            lock_duration_seconds: self.lock_duration_seconds,
+            min_stake: self.min_stake_amount()?,
+            max_individual_stake: self.max_individual_stake()?,
+            min_unstake: self.min_unstake_amount()?,
+            max_individual_unstake: self.max_individual_unstake()?,
+            max_pool_size,
+            outflow_cap: self.outflow_cap,
+            surplus: self.surplus,
+        })
+    }
+}
+
+// Vault reconciliation for `sync_vault` and `execute_admin_withdraw`
+impl StakePool {
+    /// Record the vault balance above the staked principal as surplus and
+    /// return the shortfall, if any. The pool owes no rewards, so its only
+    /// liability is the staked principal.
+    pub fn reconcile_vault(&mut self, vault_balance: u64) -> u64 {
+        self.surplus = vault_balance.saturating_sub(self.total_staked);
+        self.total_staked.saturating_sub(vault_balance)
+    }
+
+    /// Validate an admin withdrawal of `amount`, which may only take the reconciled surplus
+    pub fn check_surplus_withdrawal(&self, amount: u64, vault_balance: u64) -> Result<()> {
+        require!(amount > 0, StakeError::AmountMustBeGreaterThanZero);
+        require!(amount <= self.surplus, StakeError::ExceedsSurplus);
+        require!(vault_balance >= amount, StakeError::InsufficientFunds);
+        Ok(())
+    }
+}
+
+// Foreign token rescue for `execute_rescue_tokens`
+impl StakePool {
+    /// A rescue may only move a token account of another mint held by the pool
+    /// PDA; the stake vault and the staked mint are never touched
+    pub fn check_rescue_source(&self, source: &Pubkey, mint: &Pubkey, owner: &Pubkey, pool_authority: &Pubkey) -> Result<()> {
+        require!(*source != self.token_vault, StakeError::InvalidRescueAccount);
+        require!(*mint != self.token_mint, StakeError::InvalidRescueAccount);
+        require!(owner == pool_authority, StakeError::InvalidRescueAccount);
+        Ok(())
+    }
+}
+
+// Shared lock logic for `extend_lock`
+impl StakePool {
+    /// Move a position's lock end to `new_lock_end`, returning the old one. The
+    /// lock end only moves later, into the future and at most the maximum lock
+    /// duration ahead.
+    pub fn extend_lock(&self, user_stake: &mut UserStake, new_lock_end: u64, clock: &Clock) -> Result<u64> {
+        // Ensure user has staked tokens and is not leaving the pool
+        require!(user_stake.staked_amount > 0, StakeError::NoStakedTokens);
+        require!(!user_stake.withdrawal_requested, StakeError::WithdrawalAlreadyRequested);
+
+        // The lock end can only move later, and must lie in the future
+        let now = self.clock_now(clock);
+        require!(new_lock_end > user_stake.lock_end && new_lock_end > now, StakeError::LockEndNotExtended);
+
+        // Safety check: ensure lock duration is reasonable (not more than 1 year)
+        require!(new_lock_end - now <= self.max_lock_duration(), StakeError::ExcessiveLockDuration);
+
+        Ok(std::mem::replace(&mut user_stake.lock_end, new_lock_end))
+    }
+}
+
+// Shared request logic for `request_withdrawal`
+impl StakePool {
+    /// Set the unlock point of a withdrawal from `user_stake` and reserve outflow
+    /// capacity for what will have vested by then, returning whether the request
+    /// had to queue behind the cap
+    pub fn reserve_withdrawal(&mut self, user_stake: &mut UserStake, clock: &Clock) -> Result<bool> {
+        let now = self.clock_now(clock);
+        let (lock_slots, lock_seconds, unlock_at) = if self.emergency_mode != 0 {
+            // In emergency mode, allow immediate withdrawal
+            (0, 0, now)
+        } else {
+            // Normal mode: apply lock duration
+            let lock_duration_slots = self.lock_duration_slots;
+            let lock_duration_seconds = self.lock_duration_seconds;
+
+            // Safety check: ensure lock duration is reasonable (not more than 1 year)
+            require!(lock_duration_slots <= MAX_LOCK_DURATION_SLOTS, StakeError::ExcessiveLockDuration);
+            require!(lock_duration_seconds <= MAX_LOCK_DURATION_SECONDS, StakeError::ExcessiveLockDuration);
+
+            // An explicit position lock end can only push the unlock later
+            let remaining = user_stake.lock_end.saturating_sub(now);
+            let (remaining_slots, remaining_seconds) = self.to_slots_and_seconds(remaining);
+            let pool_lock = match self.lock_clock() {
+                LockClock::Slot => lock_duration_slots,
+                LockClock::UnixTimestamp => lock_duration_seconds,
+            };
+            (
+                lock_duration_slots.max(remaining_slots),
+                lock_duration_seconds.max(remaining_seconds),
+                now.saturating_add(pool_lock.max(remaining)),
+            )
+        };
+
+        // Reserve what will have vested at the unlock point (later vesting needs a
+        // new request); requests beyond the cap wait in FIFO order
+        let reserve = user_stake.withdrawable_at(unlock_at);
+        require!(reserve > 0, StakeError::NothingVested);
+        let available_at = self.reserve_outflow(reserve, unlock_at)?;
+        let (queue_slots, queue_seconds) = self.to_slots_and_seconds(available_at.saturating_sub(now));
+
+        user_stake.unlock_slot = clock.slot
+            .checked_add(lock_slots.max(queue_slots))
+            .ok_or(StakeError::ArithmeticOverflow)?;
+        user_stake.unlock_timestamp = clock.unix_timestamp
+            .checked_add(lock_seconds.max(queue_seconds) as i64)
+            .ok_or(StakeError::ArithmeticOverflow)?;
+        user_stake.withdrawal_requested = true;
+        user_stake.withdrawal_reserved = reserve;
+        // A position on its way out holds no tier
+        user_stake.clear_tier();
+
+        Ok(available_at > unlock_at)
+    }
+}
+
+// Shared staking logic for `stake` and `stake_for`
+impl StakePool {
+    /// Validate a new stake of `amount` into `user_stake` against the pool state and limits
+    pub fn check_stake(&self, user_stake: &UserStake, amount: u64) -> Result<()> {
+        // In a capped pool a pending request holds outflow queue capacity, so it must
+        // run its course; topping the position up in between would orphan the reservation
+        if self.outflow_cap != 0 {
+            require!(!user_stake.withdrawal_requested, StakeError::WithdrawalAlreadyRequested);
+        }
+
+        // Ensure amount is greater than 0
+        require!(amount > 0, StakeError::AmountMustBeGreaterThanZero);
+
+        // Ensure amount meets minimum stake requirement
+        require!(amount >= self.min_stake_amount()?, StakeError::BelowMinimumStakeAmount);
+
+        // Ensure amount doesn't exceed maximum individual stake limit
+        require!(amount <= self.max_individual_stake()?, StakeError::ExceedsMaximumStakeAmount);
+
+        // Check if contract is paused (allow staking when not paused)
+        require!(self.emergency_mode == 0, StakeError::ContractPaused);
+        require!(self.breaker_tripped == 0, StakeError::CircuitBreakerTripped);
+
+        // Check individual stake limits and pool capacity
+        let new_total_individual = user_stake.staked_amount.checked_add(amount).ok_or(StakeError::ArithmeticOverflow)?;
+        require!(new_total_individual <= self.max_individual_stake()?, StakeError::ExceedsMaximumStakeAmount);
+
+        let new_pool_total = self.total_staked.checked_add(amount).ok_or(StakeError::ArithmeticOverflow)?;
+        require!(new_pool_total <= self.max_pool_size()?, StakeError::ExceedsMaximumPoolSize);
+
+        Ok(())
+    }
+
+    /// Validate moving `from` into the empty position `to`; the recipient must
+    /// have signed when the pool requires transfer consent
+    pub fn check_transfer(&self, from: &UserStake, to: &UserStake, recipient_signed: bool) -> Result<()> {
+        // Ensure there is something to move and no withdrawal is in flight
+        require!(from.staked_amount > 0, StakeError::NoStakedTokens);
+        require!(!from.withdrawal_requested, StakeError::WithdrawalAlreadyRequested);
+
+        // The destination must be an empty position
+        require!(to.staked_amount == 0 && !to.withdrawal_requested, StakeError::StakeAccountNotEmpty);
+
+        // Recipient consent, when the pool requires it
+        if self.require_transfer_consent != 0 {
+            require!(recipient_signed, StakeError::RecipientConsentRequired);
+        }
+        Ok(())
+    }
+
+    /// Validate a vesting schedule granted to `user_stake` at pool clock value `now`
+    pub fn check_vesting_grant(
+        &self,
+        user_stake: &UserStake,
+        schedule: &VestingSchedule,
+        now: u64,
+        beneficiary_signed: bool,
+    ) -> Result<()> {
+        // A schedule blocks further grants until it ends, so the beneficiary must accept it
+        require!(beneficiary_signed, StakeError::BeneficiaryConsentRequired);
+
+        // Validate the schedule against the pool's lock clock
+        let VestingSchedule { start, cliff, end, .. } = *schedule;
+        require!(start <= cliff && cliff <= end && start < end, StakeError::InvalidVestingSchedule);
+        require!(end.saturating_sub(now) <= self.max_vesting_duration(), StakeError::InvalidVestingSchedule);
+
+        // Only one active schedule per position
+        require!(user_stake.vesting.unvested(now) == 0, StakeError::VestingAlreadyActive);
+        Ok(())
+    }
+
+    /// Validate a funder's stake into someone else's position. Unlike the owner,
+    /// a funder must not cancel the beneficiary's pending withdrawal in any pool.
+    pub fn check_stake_for(&self, user_stake: &UserStake, amount: u64) -> Result<()> {
+        require!(!user_stake.withdrawal_requested, StakeError::WithdrawalAlreadyRequested);
+        self.check_stake(user_stake, amount)
+    }
+
+    /// Credit tokens that arrived in the vault to a position
+    pub fn credit_stake(&mut self, user_stake: &mut UserStake, received: u64, clock: &Clock) -> Result<()> {
+        user_stake.staked_amount = user_stake.staked_amount.checked_add(received).ok_or(StakeError::ArithmeticOverflow)?;
+        self.total_staked = self.total_staked.checked_add(received).ok_or(StakeError::ArithmeticOverflow)?;
+
+        // Update common fields
+        user_stake.last_stake_slot = clock.slot;
+        user_stake.last_stake_timestamp = clock.unix_timestamp;
+
+        Ok(())
+    }
+}
+
+// Shared withdrawal logic for `withdraw` and `crank_withdraw`
+impl StakePool {
+    /// Validate a matured withdrawal from `user_stake`, returning the amount it releases now
+    pub fn check_withdrawal(&self, user_stake: &UserStake, clock: &Clock) -> Result<u64> {
+        // Vault outflows stop while the circuit breaker is tripped
+        require!(self.breaker_tripped == 0, StakeError::CircuitBreakerTripped);
+
+        // Ensure user has staked tokens
+        require!(user_stake.staked_amount > 0, StakeError::NoStakedTokens);
+
+        // Ensure user has requested withdrawal
+        require!(user_stake.withdrawal_requested, StakeError::WithdrawalNotRequested);
+
+        // Validate unstake amount limits (vesting positions are released in parts,
+        // so the minimum only applies to regular positions)
+        if !user_stake.has_vesting() {
+            require!(user_stake.staked_amount >= self.min_unstake_amount()?, StakeError::BelowMinimumUnstakeAmount);
+        }
+        require!(user_stake.staked_amount <= self.max_individual_unstake()?, StakeError::ExceedsMaximumUnstakeAmount);
+
+        // Ensure lock period has passed on the pool's lock clock
+        require!(self.is_unlocked(user_stake, clock), StakeError::TokensStillLocked);
+
+        // Get amount to withdraw (only the vested portion of a vesting position, up to the reservation)
+        let amount = user_stake.payout_amount(self, clock);
+        require!(amount > 0, StakeError::NothingVested);
+
+        Ok(amount)
+    }
+
+    /// Validate a keeper-completed withdrawal: the position must have opted in
+    pub fn check_crank(&self, user_stake: &UserStake, clock: &Clock) -> Result<u64> {
+        require!(user_stake.auto_withdraw, StakeError::AutoWithdrawNotEnabled);
+        self.check_withdrawal(user_stake, clock)
+    }
+}
+
+impl<'info> StakeFor<'info> {
+    /// Move `amount` from the funder into the beneficiary's position, returning
+    /// the amount credited and the clock used
+    fn deposit(&mut self, amount: u64) -> Result<(u64, Clock)> {
+        let mut stake_pool = self.stake_pool.load_mut()?;
+
+        // Apply reentrancy guard
+        reentrancy_guard!(stake_pool);
+
+        let user_stake = &mut self.user_stake;
+
+        // Validate amount, pause state, pending requests and limits
+        stake_pool.check_stake_for(user_stake, amount)?;
+
+        // Verify the source token account belongs to the funder and holds the pool mint
+        let funder_token_account = token_interface::unpack_token_account(&self.funder_token_account)?;
+        require!(funder_token_account.mint == stake_pool.token_mint, StakeError::InvalidTokenMint);
+        require!(funder_token_account.owner == self.funder.key(), StakeError::InvalidUser);
+        let decimals = stake_pool.mint_decimals(&self.token_mint)?;
+
+        // Transfer tokens to contract vault
+        let cpi_accounts = TransferChecked {
+            from: self.funder_token_account.to_account_info(),
+            mint: self.token_mint.to_account_info(),
+            to: self.token_vault.to_account_info(),
+            authority: self.funder.to_account_info(),
+        };
+
+        let cpi_program = self.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        let received = deposit_to_vault(cpi_ctx, amount, decimals)?;
+
+        // Update stake amounts
+        let clock = Clock::get()?;
+        stake_pool.credit_stake(user_stake, received, &clock)?;
+        record_checkpoints(&self.checkpoints, &self.pool_checkpoints, user_stake, &stake_pool, &clock)?;
+
+        // Release reentrancy guard
+        reentrancy_guard_end!(stake_pool);
+
+        Ok((received, clock))
+    }
+}
+
+/// Check that `account` is a `T` owned by this program and resize it to `new_len` bytes,
+/// topping up rent from `payer`. New bytes are zeroed. Returns the length before the migration.
+fn resize_account<'info, T: Discriminator>(
+    account: &AccountInfo<'info>,
+    payer: &Signer<'info>,
+    system_program: &Program<'info, System>,
+    new_len: usize,
+) -> Result<usize> {
+    require_keys_eq!(*account.owner, crate::ID, ErrorCode::AccountOwnedByWrongProgram);
+    {
+        let data = account.try_borrow_data()?;
+        require!(
+            data.len() >= 8 && data[..8] == T::discriminator(),
+            ErrorCode::AccountDiscriminatorMismatch
+        );
+    }
+
+    let old_len = account.data_len();
+    if old_len == new_len {
+        return Ok(old_len);
+    }
+
+    // Keep the account rent exempt at its new size
+    let rent = Rent::get()?.minimum_balance(new_len);
+    let top_up = rent.saturating_sub(account.lamports());
+    if top_up > 0 {
+        let cpi_accounts = system_program::Transfer {
+            from: payer.to_account_info(),
+            to: account.clone(),
+        };
+        system_program::transfer(CpiContext::new(system_program.to_account_info(), cpi_accounts), top_up)?;
+    }
+
+    account.realloc(new_len, true)?;
+    Ok(old_len)
+}
+
+/// Emit `AccountMigratedEvent` for a migrated account of `stake_pool`
+fn emit_migration(
+    account: Pubkey,
+    stake_pool: Pubkey,
+    seq: u64,
+    from_version: u8,
+    to_version: u8,
+    old_len: usize,
+) -> Result<()> {
+    let clock = Clock::get()?;
+    emit!(AccountMigratedEvent {
+        account,
+        stake_pool,
+        from_version,
+        to_version,
+        old_len: old_len as u64,
+        seq,
+        slot: clock.slot,
+        timestamp: clock.unix_timestamp,
+    });
+    Ok(())
+}
+
+/// Transfer tokens into the vault and return the amount that actually arrived
+/// (Token-2022 transfer fees are withheld in the vault, not credited to it)
+fn deposit_to_vault<'info>(
+    cpi_ctx: CpiContext<'_, '_, '_, 'info, TransferChecked<'info>>,
+    amount: u64,
+    decimals: u8,
+) -> Result<u64> {
+    let token_vault = cpi_ctx.accounts.to.clone();
+    let vault_balance_before = token_interface::unpack_token_account(&token_vault)?.amount;
+
+    token_interface::transfer_checked(cpi_ctx, amount, decimals)?;
+
+    let vault_balance_after = token_interface::unpack_token_account(&token_vault)?.amount;
+    let received = vault_balance_after.checked_sub(vault_balance_before).ok_or(StakeError::ArithmeticOverflow)?;
+    require!(received > 0, StakeError::AmountMustBeGreaterThanZero);
+
+    Ok(received)
+}
+
+/// Checkpoint the position's and the pool's staked amounts after a change
+fn record_checkpoints(
+    checkpoints: &AccountLoader<StakeCheckpoints>,
+    pool_checkpoints: &AccountLoader<PoolCheckpoints>,
+    user_stake: &UserStake,
+    stake_pool: &StakePool,
+    clock: &Clock,
+) -> Result<()> {
+    checkpoints.load_mut()?.record(clock.slot, user_stake.staked_amount);
+    pool_checkpoints.load_mut()?.record(clock.slot, stake_pool.total_staked);
+    Ok(())
+}
+
+/// Start the balance history of a new position. A position reopened at the
+/// same address keeps the history `close_user_stake` left behind, which ends
+/// at zero, so lookups before the reopening stay answerable.
+fn open_checkpoints(
+    checkpoints: &AccountLoader<StakeCheckpoints>,
+    user_stake: Pubkey,
+    bump: u8,
+    clock: &Clock,
+) -> Result<()> {
+    let reopened = checkpoints.as_ref().try_borrow_data()?[..8] == StakeCheckpoints::DISCRIMINATOR;
+    if !reopened {
+        checkpoints.load_init()?.init(user_stake, bump, clock.slot, 0, true);
+    }
+    Ok(())
+}
+
+impl CheckpointRing {
+    /// Record `amount` as the balance from `slot` on. A second change in the
+    /// same slot replaces that slot's checkpoint; a full ring drops its oldest.
+    fn record(&mut self, entries: &mut [Checkpoint], slot: u64, amount: u64) {
+        let capacity = entries.len() as u32;
+        if self.len > 0 {
+            let last = (self.next + capacity - 1) % capacity;
+            if entries[last as usize].slot == slot {
+                entries[last as usize].amount = amount;
+                return;
+            }
+        }
+        entries[self.next as usize] = Checkpoint { slot, amount };
+        self.next = (self.next + 1) % capacity;
+        if self.len < capacity {
+            self.len += 1;
+        } else {
+            // The evicted checkpoint was the only link back to a zero balance
+            self.complete = 0;
+        }
+    }
+
+    /// Index of the oldest stored checkpoint in `entries`
+    fn oldest(&self, capacity: u32) -> u32 {
+        (self.next + capacity - self.len) % capacity
+    }
+
+    /// Stored checkpoints, oldest first
+    pub fn ordered<'a>(&self, entries: &'a [Checkpoint]) -> impl Iterator<Item = &'a Checkpoint> + 'a {
+        let capacity = entries.len() as u32;
+        let oldest = self.oldest(capacity);
+        (0..self.len).map(move |index| &entries[((oldest + index) % capacity) as usize])
+    }
+
+    /// Balance at the end of `slot`, or `None` if it predates the history kept
+    fn amount_at(&self, entries: &[Checkpoint], slot: u64) -> Option<u64> {
+        let capacity = entries.len() as u32;
+        let oldest = self.oldest(capacity);
+        let entry = |index: u32| &entries[((oldest + index) % capacity) as usize];
+
+        // Number of checkpoints at or before `slot`; the last of them holds the balance
+        let (mut low, mut high) = (0, self.len);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if entry(mid).slot <= slot {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        match low {
+            0 if self.complete != 0 => Some(0),
+            0 => None,
+            count => Some(entry(count - 1).amount),
+        }
+    }
+}
+
+impl UserStake {
+    /// Move the position (amount and lock state) to `to` and reset this one
+    pub fn transfer_to(&mut self, to: &mut UserStake) {
+        to.staked_amount = self.staked_amount;
+        to.last_stake_slot = self.last_stake_slot;
+        to.unlock_slot = self.unlock_slot;
+        to.withdrawal_requested = self.withdrawal_requested;
+        to.last_stake_timestamp = self.last_stake_timestamp;
+        to.unlock_timestamp = self.unlock_timestamp;
+        to.lock_end = self.lock_end;
+        to.vesting = self.vesting;
+
+        self.clear();
+    }
+
+    /// Only a position with no tokens and no pending request can be closed
+    pub fn check_close(&self) -> Result<()> {
+        require!(self.staked_amount == 0, StakeError::StakeAccountNotEmpty);
+        require!(!self.withdrawal_requested, StakeError::WithdrawalAlreadyRequested);
+        Ok(())
+    }
+
+    /// Reset the position to an empty state
+    pub fn clear(&mut self) {
+        self.staked_amount = 0;
+        self.last_stake_slot = 0;
+        self.last_stake_timestamp = 0;
+        self.clear_request();
+        self.lock_end = 0;
+        self.vesting = VestingSchedule::default();
+        self.clear_tier();
+    }
+
+    /// Drop the cached tier
+    pub fn clear_tier(&mut self) {
+        self.tier = 0;
+        self.tier_valid_until = 0;
+    }
+
+    /// Recompute the tier under `tier_table` and cache it with the slot, the
+    /// lapse point of its lock minimum and the table revision
+    pub fn verify_tier(&mut self, tier_table: &TierTable, stake_pool: &StakePool, clock: &Clock) -> Result<u8> {
+        let tier = tier_table.tier_of(stake_pool, self, clock)?;
+        self.tier = tier;
+        self.tier_verified_slot = clock.slot;
+        self.tier_valid_until = tier_table.tier_valid_until(tier, self.lock_end);
+        self.tier_table_revision = tier_table.revision;
+        Ok(tier)
+    }
+
+    /// Cached tier, or 0 once the remaining lock has run below the tier's minimum
+    /// or the tier table has changed since the tier was verified
+    pub fn cached_tier(&self, tier_table: &TierTable, stake_pool: &StakePool, clock: &Clock) -> u8 {
+        if self.tier_table_revision == tier_table.revision && stake_pool.clock_now(clock) <= self.tier_valid_until {
+            self.tier
+        } else {
+            0
+        }
+    }
+
+    /// Opt in to (or out of) keeper-completed withdrawals; cranked payouts go to
+    /// `payout_token_account`, which is cleared when opting out
+    pub fn set_auto_withdraw(&mut self, enabled: bool, keeper_tip_bps: u16, payout_token_account: Pubkey) -> Result<()> {
+        require!(keeper_tip_bps <= MAX_KEEPER_TIP_BPS, StakeError::ExcessiveKeeperTip);
+        self.auto_withdraw = enabled;
+        self.keeper_tip_bps = keeper_tip_bps;
+        self.payout_token_account = if enabled { payout_token_account } else { Pubkey::default() };
+        Ok(())
+    }
+
+    /// Split a cranked payout into the owner's share and the keeper's tip, rounding the tip down
+    pub fn split_keeper_tip(&self, amount: u64) -> (u64, u64) {
+        let keeper_tip = (amount as u128 * self.keeper_tip_bps as u128 / BASIS_POINTS as u128) as u64;
+        (amount - keeper_tip, keeper_tip)
+    }
+
+    /// Bring a position decoded from an older layout up to `VERSION` and return
+    /// the version it was at. Positions that opted in before payout accounts
+    /// were registered keep paying the owner's associated token account
+    pub fn migrate(&mut self, stake_pool: &StakePool) -> u8 {
+        if self.auto_withdraw && self.payout_token_account == Pubkey::default() {
+            self.payout_token_account = token_interface::associated_token_address(
+                &self.owner,
+                &stake_pool.token_mint,
+                &stake_pool.token_program,
+            );
+        }
+        std::mem::replace(&mut self.version, Self::VERSION)
+    }
+
+    /// Take a paid-out `amount` off the position after a withdrawal
+    pub fn settle_withdrawal(&mut self, amount: u64) -> Result<()> {
+        self.staked_amount = self.staked_amount.checked_sub(amount).ok_or(StakeError::ArithmeticOverflow)?;
+        if self.staked_amount == 0 {
+            self.clear();
+        } else {
+            // Unvested tokens stay staked; releasing them needs a new withdrawal request
+            self.clear_request();
+            // The tier was granted for the larger amount
+            self.clear_tier();
+        }
+        Ok(())
+    }
+
+    /// Drop the pending withdrawal request, if any
+    pub fn clear_request(&mut self) {
+        self.unlock_slot = 0;
+        self.unlock_timestamp = 0;
+        self.withdrawal_requested = false;
+        self.withdrawal_reserved = 0;
+    }
+
+    /// Whether the position carries a vesting schedule
+    pub fn has_vesting(&self) -> bool {
+        self.vesting.total > 0
+    }
+
+    /// Staked amount not held back by the vesting schedule
+    pub fn withdrawable_amount(&self, stake_pool: &StakePool, clock: &Clock) -> u64 {
+        self.withdrawable_at(stake_pool.clock_now(clock))
+    }
+
+    /// Staked amount not held back by the vesting schedule at pool clock value `now`
+    pub fn withdrawable_at(&self, now: u64) -> u64 {
+        self.staked_amount.saturating_sub(self.vesting.unvested(now))
+    }
+
+    /// Amount a matured request pays out now: the withdrawable amount, capped at
+    /// what the request reserved in the outflow queue
+    pub fn payout_amount(&self, stake_pool: &StakePool, clock: &Clock) -> u64 {
+        let amount = self.withdrawable_amount(stake_pool, clock);
+        match self.withdrawal_reserved {
+            0 => amount,
+            reserved => amount.min(reserved),
+        }
+    }
+
+    /// Voting power: the staked amount, boosted linearly up to 2x by the
+    /// time remaining until the position's lock end
+    pub fn voting_power(&self, stake_pool: &StakePool, clock: &Clock) -> u64 {
+        let max_lock = stake_pool.max_lock_duration();
+        let remaining = self.lock_end.saturating_sub(stake_pool.clock_now(clock)).min(max_lock);
+        let boost = (self.staked_amount as u128 * remaining as u128 / max_lock as u128) as u64;
+        self.staked_amount.saturating_add(boost)
+    }
+}
+
+// Limits scaled by the mint decimals
+impl StakePool {
+    /// Convert a whole-token amount to base units using the pool's mint decimals
+    pub fn to_base_units(&self, tokens: u64) -> Result<u64> {
+        10u64
+            .checked_pow(self.decimals as u32)
+            .and_then(|scale| tokens.checked_mul(scale))
+            .ok_or_else(|| StakeError::ArithmeticOverflow.into())
+    }
+
+    pub fn min_stake_amount(&self) -> Result<u64> {
+        self.to_base_units(self.limits.min_stake)
+    }
+
+    pub fn max_individual_stake(&self) -> Result<u64> {
+        self.to_base_units(self.limits.max_individual_stake)
+    }
+
+    pub fn min_unstake_amount(&self) -> Result<u64> {
+        self.to_base_units(self.limits.min_unstake)
+    }
+
+    pub fn max_individual_unstake(&self) -> Result<u64> {
+        self.to_base_units(self.limits.max_individual_unstake)
+    }
+
+    pub fn max_pool_size(&self) -> Result<u64> {
+        self.to_base_units(self.limits.max_pool_size)
+    }
+
+    /// Limits must be consistent and, as the largest ones fit, every limit must
+    /// be representable in base units for the pool's decimals
+    pub fn check_limits(&self) -> Result<()> {
+        require!(self.limits.is_valid(), StakeError::InvalidPoolLimits);
+        self.max_pool_size().map_err(|_| StakeError::UnsupportedMintDecimals)?;
+        self.max_individual_unstake().map_err(|_| StakeError::UnsupportedMintDecimals)?;
+        Ok(())
+    }
+
+    /// Decimals to use for transfers, verifying the mint still matches the pool
+    pub fn mint_decimals(&self, token_mint: &AccountInfo) -> Result<u8> {
+        let mint = token_interface::unpack_mint(token_mint)?;
+        require!(mint.decimals == self.decimals, StakeError::InvalidMintDecimals);
+        Ok(self.decimals)
+    }
+}
+
+// Account structures
+//
+// The stake pool is read and written on every stake and withdrawal, so it is a
+// zero-copy account used in place rather than Borsh-decoded and re-encoded.
+// Fields are grouped by alignment (u64s, pubkeys, u16, bytes) so the `repr(C)`
+// layout has no implicit padding, and flags are stored as `u8`.
+#[account(zero_copy)]
+pub struct StakePool {
+    pub lock_duration_slots: u64,       // Lock duration (in slots)
+    pub total_staked: u64,              // Total staked amount
+    pub lock_duration_seconds: u64,     // Lock duration (in seconds, for LockClock::UnixTimestamp)
+    pub pool_id: u64,                   // Pool identifier, unique per mint
+    pub limits: PoolLimits,             // Staking limits (in whole tokens)
+    pub outflow_cap: u64,               // Maximum withdrawal outflow per epoch (base units, 0 = unlimited)
+    pub outflow_queue_epoch: u64,       // Latest epoch holding queued withdrawals
+    pub outflow_queue_used: u64,        // Capacity reserved in that epoch
+    pub breaker_window_slots: u64,      // Circuit breaker window length (in slots)
+    pub breaker_window_start: u64,      // Start slot of the newest breaker bucket
+    pub breaker_window_outflow: u64,    // Vault outflows in the rolling window (sum of the buckets)
+    pub surplus: u64,                   // Vault balance above liabilities, withdrawable by multisig
+    pub authority: Pubkey,              // Stake pool administrator (will be replaced by multisig)
+    pub token_mint: Pubkey,             // Token mint
+    pub token_vault: Pubkey,            // Token vault
+    pub token_program: Pubkey,          // Token program owning the mint (SPL Token or Token-2022)
+    pub breaker_threshold_bps: u16,     // Outflow share of total_staked that trips the breaker (0 = disabled)
+    pub emergency_mode: u8,             // Emergency pause flag
+    pub reentrancy_guard: u8,           // Reentrancy protection flag
+    pub bump: u8,                       // PDA bump
+    pub decimals: u8,                   // Mint decimals used to scale limits
+    pub lock_clock: u8,                 // Clock used to measure lock durations (LockClock as u8)
+    pub require_transfer_consent: u8,   // Position transfers need the recipient's signature
+    pub breaker_tripped: u8,            // Outflows and new stakes halted until reset by multisig
+    pub version: u8,                    // Account layout version
+    pub padding: [u8; 6],               // Explicit padding keeping event_seq 8-byte aligned
+    pub event_seq: u64,                 // Sequence number of the pool's latest event
+    pub breaker_buckets: [u64; BREAKER_BUCKETS], // Outflows per breaker bucket, indexed by bucket number
+    pub reserved: [u8; 64],             // Reserved for future fields
+}
+
+// Borsh layout of `StakePool` before it became zero-copy (version 1 and earlier),
+// read by `migrate_stake_pool`
+#[derive(AnchorDeserialize)]
+pub struct StakePoolV1 {
+    pub authority: Pubkey,
+    pub token_mint: Pubkey,
+    pub token_vault: Pubkey,
+    pub lock_duration_slots: u64,
+    pub total_staked: u64,
+    pub emergency_mode: bool,
+    pub reentrancy_guard: bool,
+    pub bump: u8,
+    pub token_program: Pubkey,
+    pub decimals: u8,
+    pub lock_clock: LockClock,
+    pub lock_duration_seconds: u64,
+    pub pool_id: u64,
+    pub limits: PoolLimits,
+    pub require_transfer_consent: bool,
+    pub outflow_cap: u64,
+    pub outflow_queue_epoch: u64,
+    pub outflow_queue_used: u64,
+    pub breaker_threshold_bps: u16,
+    pub breaker_window_slots: u64,
+    pub breaker_window_start: u64,
+    pub breaker_window_outflow: u64,
+    pub breaker_tripped: bool,
+    pub surplus: u64,
+    pub version: u8,
+    pub reserved: [u8; 64],
+}
+
+// Registry of all stake pools for a mint
+#[account]
+pub struct PoolRegistry {
+    pub token_mint: Pubkey,             // Token mint
+    pub pools: Vec<Pubkey>,             // Stake pools for this mint
+    pub bump: u8,                       // PDA bump
+}
+
+// Service tier thresholds, in whole tokens and pool lock clock units
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StakeTier {
+    pub min_stake: u64,                 // Minimum staked amount
+    pub min_lock: u64,                  // Minimum time left until the position's lock end
+}
+
+// Service tier table of a stake pool (tier n is `tiers[n - 1]`, 0 = no tier)
+#[account]
+pub struct TierTable {
+    pub stake_pool: Pubkey,             // Associated stake pool
+    pub tiers: Vec<StakeTier>,          // Tiers in ascending order
+    pub bump: u8,                       // PDA bump
+    pub version: u8,                    // Account layout version
+    pub revision: u64,                  // Number of `SetTierTable` changes applied
+    pub reserved: [u8; 24],             // Reserved for future fields
+}
+
+// A staked amount in effect from `slot` on
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct Checkpoint {
+    pub slot: u64,
+    pub amount: u64,
+}
+
+// Ring buffer bookkeeping shared by the checkpoint accounts
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default)]
+#[repr(C)]
+pub struct CheckpointRing {
+    pub len: u32,                       // Checkpoints stored
+    pub next: u32,                      // Index the next checkpoint is written to
+    pub complete: u8,                   // History reaches back to a zero balance
+    pub padding: [u8; 7],               // Explicit padding keeping entries 8-byte aligned
+}
+
+// Balance history of a position, in slot order. Zero-copy like the stake pool,
+// since it is written on every stake and withdrawal.
+#[account(zero_copy)]
+pub struct StakeCheckpoints {
+    pub user_stake: Pubkey,             // Associated position
+    pub ring: CheckpointRing,           // Ring buffer state
+    pub bump: u8,                       // PDA bump
+    pub version: u8,                    // Account layout version
+    pub padding: [u8; 6],               // Explicit padding keeping entries 8-byte aligned
+    pub entries: [Checkpoint; USER_CHECKPOINTS],
+}
+
+// Total staked history of a stake pool, in slot order
+#[account(zero_copy)]
+pub struct PoolCheckpoints {
+    pub stake_pool: Pubkey,             // Associated stake pool
+    pub ring: CheckpointRing,           // Ring buffer state
+    pub bump: u8,                       // PDA bump
+    pub version: u8,                    // Account layout version
+    pub padding: [u8; 6],               // Explicit padding keeping entries 8-byte aligned
+    pub entries: [Checkpoint; POOL_CHECKPOINTS],
+}
+
+#[account]
+pub struct UserStake {
+    pub owner: Pubkey,                  // User address
+    pub stake_pool: Pubkey,             // Stake pool address
+    pub staked_amount: u64,             // Staked amount
+    pub last_stake_slot: u64,           // Last stake slot
+    pub unlock_slot: u64,               // Unlock slot
+    pub withdrawal_requested: bool,     // Whether withdrawal has been requested
+    pub bump: u8,                       // PDA bump
+    pub last_stake_timestamp: i64,      // Last stake unix timestamp
+    pub unlock_timestamp: i64,          // Unlock unix timestamp
+    pub lock_end: u64,                  // Explicit lock end (pool lock clock units), 0 if none
+    pub vesting: VestingSchedule,       // Vesting schedule (total == 0 if none)
+    pub version: u8,                    // Account layout version
+    pub auto_withdraw: bool,            // Keepers may complete matured withdrawals to payout_token_account
+    pub keeper_tip_bps: u16,            // Share of a cranked withdrawal paid to the keeper
+    pub tier: u8,                       // Service tier at the last `verify_tier` (0 = none)
+    pub tier_verified_slot: u64,        // Slot of the last `verify_tier`
+    pub tier_valid_until: u64,          // Last pool clock value at which the remaining lock still meets the tier
+    pub withdrawal_reserved: u64,       // Most the pending request pays out (0 for requests made before it was recorded)
+    pub payout_token_account: Pubkey,   // Token account registered for keeper-completed withdrawals
+    pub tier_table_revision: u64,       // Tier table revision the cached tier was computed from
+    pub reserved: [u8; 24],             // Reserved for future fields
+}
+
+// Multisig configuration account
+#[account]
+pub struct MultisigConfig {
+    pub signers: [Pubkey; 3],           // 3 multisig signers
+    pub threshold: u8,                  // Required signatures (e.g., 2 out of 3)
+    pub stake_pool: Pubkey,             // Associated stake pool
+    pub proposal_count: u64,            // Total number of proposals created
+    pub bump: u8,                       // PDA bump
+    pub version: u8,                    // Account layout version
+    pub reserved: [u8; 32],             // Reserved for future fields
+}
+
+// Multisig proposal account
+#[account]
+pub struct MultisigProposal {
+    pub id: u64,                        // Proposal ID
+    pub proposal_type: ProposalType,    // Type of proposal
+    pub proposer: Pubkey,               // Who created the proposal
+    pub multisig_config: Pubkey,        // Associated multisig config
+    pub status: ProposalStatus,         // Current status
+    pub signatures: [bool; 3],          // Signature status for each signer
+    pub signature_count: u8,            // Number of signatures collected
+    pub created_at: u64,                // Creation slot
+    pub executed_at: Option<u64>,       // Execution slot (if executed)
+    pub data: Vec<u8>,                  // Proposal-specific data
+    pub bump: u8,                       // PDA bump
+    pub version: u8,                    // Account layout version
+    pub reserved: [u8; 32],             // Reserved for future fields
+}
+
+// Compact history of a closed proposal
+#[account]
+pub struct ProposalRecord {
+    pub id: u64,                        // Proposal ID
+    pub proposal_type: ProposalType,    // Type of proposal
+    pub proposer: Pubkey,               // Who created the proposal
+    pub multisig_config: Pubkey,        // Associated multisig config
+    pub status: ProposalStatus,         // Final status (Executed or Rejected)
+    pub signatures: [bool; 3],          // Signature status for each signer
+    pub created_at: u64,                // Creation slot
+    pub executed_at: Option<u64>,       // Execution slot (if executed)
+    pub closed_at: u64,                 // Slot at which the proposal was closed
+    pub data_hash: [u8; 32],            // SHA-256 hash of the proposal data
+    pub bump: u8,                       // PDA bump
+}
+
+impl StakePool {
+    // lock_duration_slots(8) + total_staked(8) + lock_duration_seconds(8) + pool_id(8) + limits(40) +
+    // outflow_cap(8) + outflow_queue_epoch(8) + outflow_queue_used(8) + breaker_window_slots(8) +
+    // breaker_window_start(8) + breaker_window_outflow(8) + surplus(8) +
+    // authority(32) + token_mint(32) + token_vault(32) + token_program(32) + breaker_threshold_bps(2) +
+    // emergency_mode(1) + reentrancy_guard(1) + bump(1) + decimals(1) + lock_clock(1) +
+    // require_transfer_consent(1) + breaker_tripped(1) + version(1) + padding(6) + event_seq(8) +
+    // breaker_buckets(8*5) + reserved(64)
+    pub const LEN: usize = 8 * 4 + PoolLimits::LEN + 8 * 7 + 32 * 4 + 2 + 8 + 6 + 8 + 8 * BREAKER_BUCKETS + 64;
+
+    // First zero-copy layout (version 2), identical up to a reserved(8) tail
+    pub const V2_LEN: usize = Self::LEN - 64 + 8;
+
+    // Original layout, before the token program was recorded:
+    // authority(32) + token_mint(32) + token_vault(32) +
+    // lock_duration_slots(8) + total_staked(8) + emergency_mode(1) + reentrancy_guard(1) + bump(1)
+    pub const LEGACY_LEN: usize = 32 + 32 + 32 + 8 + 8 + 1 + 1 + 1;
+
+    pub const VERSION: u8 = 3;
+}
+
+// The zero-copy layout must match LEN exactly, with no implicit padding
+const _: () = assert!(std::mem::size_of::<StakePool>() == StakePool::LEN);
+// `migrate_stake_pool` tells zero-copy pools from Borsh ones by their size
+const _: () = assert!(StakePool::V2_LEN != StakePoolV1::LEN);
+
+impl StakePoolV1 {
+    // authority(32) + token_mint(32) + token_vault(32) +
+    // lock_duration_slots(8) + total_staked(8) + emergency_mode(1) + reentrancy_guard(1) + bump(1) +
+    // token_program(32) + decimals(1) + lock_clock(1) + lock_duration_seconds(8) + pool_id(8) +
+    // limits(40) + require_transfer_consent(1) + outflow_cap(8) + outflow_queue_epoch(8) +
+    // outflow_queue_used(8) + breaker_threshold_bps(2) + breaker_window_slots(8) +
+    // breaker_window_start(8) + breaker_window_outflow(8) + breaker_tripped(1) + surplus(8) +
+    // version(1) + reserved(64)
+    pub const LEN: usize = 32 + 32 + 32 + 8 + 8 + 1 + 1 + 1 + 32 + 1 + 1 + 8 + 8 + PoolLimits::LEN + 1 + 8 + 8 + 8
+        + 2 + 8 + 8 + 8 + 1 + 8 + 1 + 64;
+
+    /// Decode Borsh pool data (with its discriminator) of any version up to 1;
+    /// fields missing from older layouts read as zero
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        let mut data = data.to_vec();
+        data.resize(8 + Self::LEN, 0);
+        Ok(Self::deserialize(&mut &data[8..])?)
+    }
+
+    /// The same pool in the current zero-copy layout
+    pub fn upgrade(&self) -> StakePool {
+        StakePool {
+            lock_duration_slots: self.lock_duration_slots,
+            total_staked: self.total_staked,
+            lock_duration_seconds: self.lock_duration_seconds,
+            pool_id: self.pool_id,
+            limits: self.limits,
+            outflow_cap: self.outflow_cap,
+            outflow_queue_epoch: self.outflow_queue_epoch,
+            outflow_queue_used: self.outflow_queue_used,
+            breaker_window_slots: self.breaker_window_slots,
+            breaker_window_start: self.breaker_window_start,
+            breaker_window_outflow: self.breaker_window_outflow,
+            surplus: self.surplus,
+            authority: self.authority,
+            token_mint: self.token_mint,
+            token_vault: self.token_vault,
+            token_program: self.token_program,
+            breaker_threshold_bps: self.breaker_threshold_bps,
+            emergency_mode: u8::from(self.emergency_mode),
+            reentrancy_guard: u8::from(self.reentrancy_guard),
+            bump: self.bump,
+            decimals: self.decimals,
+            lock_clock: self.lock_clock as u8,
+            require_transfer_consent: u8::from(self.require_transfer_consent),
+            breaker_tripped: u8::from(self.breaker_tripped),
+            version: StakePool::VERSION,
+            padding: [0; 6],
+            event_seq: 0,
+            breaker_buckets: [0; BREAKER_BUCKETS],
+            reserved: [0; 64],
+        }
+    }
+}
+
+impl PoolRegistry {
+    // token_mint(32) + pools(4+32*16) + bump(1)
+    pub const LEN: usize = 32 + 4 + 32 * MAX_POOLS_PER_MINT + 1;
+
+    /// List a pool of this mint, at most once and up to `MAX_POOLS_PER_MINT` pools
+    pub fn register(&mut self, stake_pool: Pubkey) -> Result<()> {
+        require!(!self.pools.contains(&stake_pool), StakeError::PoolAlreadyRegistered);
+        require!(self.pools.len() < MAX_POOLS_PER_MINT, StakeError::PoolRegistryFull);
+        self.pools.push(stake_pool);
+        Ok(())
+    }
+}
+
+impl StakeTier {
+    // min_stake(8) + min_lock(8)
+    pub const LEN: usize = 8 + 8;
+}
+
+impl TierTable {
+    // stake_pool(32) + tiers(4+16*8) + bump(1) + version(1) + revision(8) + reserved(24)
+    pub const LEN: usize = 32 + 4 + StakeTier::LEN * MAX_TIERS + 1 + 1 + 8 + 24;
+
+    pub const VERSION: u8 = 1;
+
+    /// Decode and validate `SetTierTable` proposal data. Each tier must raise
+    /// at least one threshold without lowering the other, and its minimum stake
+    /// must fit in base units of the pool's mint.
+    pub fn parse_tiers(data: &[u8], stake_pool: &StakePool) -> Result<Vec<StakeTier>> {
+        let (&count, rest) = data.split_first().ok_or(StakeError::InvalidTierTable)?;
+        let count = count as usize;
+        require!(count <= MAX_TIERS, StakeError::InvalidTierTable);
+        require!(rest.len() >= count * StakeTier::LEN, StakeError::InvalidTierTable);
+
+        let tiers: Vec<StakeTier> = rest
+            .chunks_exact(StakeTier::LEN)
+            .take(count)
+            .map(|chunk| StakeTier {
+                min_stake: u64::from_le_bytes(chunk[0..8].try_into().unwrap()),
+                min_lock: u64::from_le_bytes(chunk[8..16].try_into().unwrap()),
+            })
+            .collect();
+        for tier in &tiers {
+            require!(stake_pool.to_base_units(tier.min_stake).is_ok(), StakeError::InvalidTierTable);
+        }
+        for pair in tiers.windows(2) {
+            let (lower, upper) = (pair[0], pair[1]);
+            require!(
+                upper.min_stake >= lower.min_stake && upper.min_lock >= lower.min_lock && upper != lower,
+                StakeError::InvalidTierTable
+            );
+        }
+
+        Ok(tiers)
+    }
+
+    /// Highest tier whose thresholds the position meets; a position with a
+    /// pending withdrawal holds no tier
+    pub fn tier_of(&self, stake_pool: &StakePool, user_stake: &UserStake, clock: &Clock) -> Result<u8> {
+        if user_stake.withdrawal_requested {
+            return Ok(0);
+        }
+        let remaining_lock = user_stake.lock_end.saturating_sub(stake_pool.clock_now(clock));
+
+        let mut tier = 0;
+        for (index, threshold) in self.tiers.iter().enumerate() {
+            if user_stake.staked_amount < stake_pool.to_base_units(threshold.min_stake)?
+                || remaining_lock < threshold.min_lock
+            {
+                break;
+            }
+            tier = index as u8 + 1;
+        }
+        Ok(tier)
+    }
+
+    /// Last pool clock value at which a position locked until `lock_end`
+    /// still meets the lock minimum of `tier`
+    pub fn tier_valid_until(&self, tier: u8, lock_end: u64) -> u64 {
+        match tier.checked_sub(1).and_then(|index| self.tiers.get(index as usize)) {
+            Some(threshold) if threshold.min_lock > 0 => lock_end.saturating_sub(threshold.min_lock),
+            _ => u64::MAX,
+        }
+    }
+}
+
+impl Checkpoint {
+    // slot(8) + amount(8)
+    pub const LEN: usize = 8 + 8;
+}
+
+impl CheckpointRing {
+    // len(4) + next(4) + complete(1) + padding(7)
+    pub const LEN: usize = 4 + 4 + 1 + 7;
+}
+
+impl StakeCheckpoints {
+    // user_stake(32) + ring(16) + bump(1) + version(1) + padding(6) + entries(16*32)
+    pub const LEN: usize = 32 + CheckpointRing::LEN + 1 + 1 + 6 + Checkpoint::LEN * USER_CHECKPOINTS;
+
+    pub const VERSION: u8 = 1;
+
+    /// Start the history at `slot` with `amount`. `complete` says whether the
+    /// balance was zero before that, which holds only for a new account.
+    pub fn init(&mut self, user_stake: Pubkey, bump: u8, slot: u64, amount: u64, complete: bool) {
+        self.user_stake = user_stake;
+        self.bump = bump;
+        self.version = Self::VERSION;
+        self.ring = CheckpointRing { complete: u8::from(complete), ..CheckpointRing::default() };
+        self.record(slot, amount);
+    }
+
+    pub fn record(&mut self, slot: u64, amount: u64) {
+        self.ring.record(&mut self.entries, slot, amount);
+    }
+
+    pub fn amount_at(&self, slot: u64) -> Option<u64> {
+        self.ring.amount_at(&self.entries, slot)
+    }
+}
+
+impl PoolCheckpoints {
+    // stake_pool(32) + ring(16) + bump(1) + version(1) + padding(6) + entries(16*512)
+    pub const LEN: usize = 32 + CheckpointRing::LEN + 1 + 1 + 6 + Checkpoint::LEN * POOL_CHECKPOINTS;
+
+    pub const VERSION: u8 = 1;
+
+    /// Start the history at `slot` with `amount`; see `StakeCheckpoints::init`
+    pub fn init(&mut self, stake_pool: Pubkey, bump: u8, slot: u64, amount: u64, complete: bool) {
+        self.stake_pool = stake_pool;
+        self.bump = bump;
+        self.version = Self::VERSION;
+        self.ring = CheckpointRing { complete: u8::from(complete), ..CheckpointRing::default() };
+        self.record(slot, amount);
+    }
+
+    pub fn record(&mut self, slot: u64, amount: u64) {
+        self.ring.record(&mut self.entries, slot, amount);
+    }
+
+    pub fn amount_at(&self, slot: u64) -> Option<u64> {
+        self.ring.amount_at(&self.entries, slot)
+    }
+}
+
+// The zero-copy layouts must match LEN exactly, with no implicit padding
+const _: () = assert!(std::mem::size_of::<StakeCheckpoints>() == StakeCheckpoints::LEN);
+const _: () = assert!(std::mem::size_of::<PoolCheckpoints>() == PoolCheckpoints::LEN);
+
+impl UserStake {
+    // owner(32) + stake_pool(32) + staked_amount(8) +
+    // last_stake_slot(8) + unlock_slot(8) + withdrawal_requested(1) + bump(1) +
+    // last_stake_timestamp(8) + unlock_timestamp(8) + lock_end(8) + vesting(32) +
+    // version(1) + auto_withdraw(1) + keeper_tip_bps(2) + tier(1) + tier_verified_slot(8) +
+    // tier_valid_until(8) + withdrawal_reserved(8) + payout_token_account(32) + tier_table_revision(8) +
+    // reserved(24)
+    pub const LEN: usize =
+        32 + 32 + 8 + 8 + 8 + 1 + 1 + 8 + 8 + 8 + VestingSchedule::LEN + 1 + 1 + 2 + 1 + 8 + 8 + 8 + 32 + 8 + 24;
+
+    pub const VERSION: u8 = 2;
+}
+
+impl MultisigConfig {
+    // signers(32*3) + threshold(1) + stake_pool(32) + proposal_count(8) + bump(1) +
+    // version(1) + reserved(32)
+    pub const LEN: usize = 32 * 3 + 1 + 32 + 8 + 1 + 1 + 32;
+
+    pub const VERSION: u8 = 1;
+}
+
+impl MultisigProposal {
+    // id(8) + proposal_type(1) + proposer(32) + multisig_config(32) + status(1) +
+    // signatures(1*3) + signature_count(1) + created_at(8) + executed_at(9) + data(4+256) + bump(1) +
+    // version(1) + reserved(32)
+    pub const LEN: usize = 8 + 1 + 32 + 32 + 1 + 3 + 1 + 8 + 9 + 4 + 256 + 1 + 1 + 32;
+
+    pub const VERSION: u8 = 1;
+
+    /// Whether an unfinished proposal has outlived its expiry window. Proposals
+    /// created before expiry existed (version 0) are measured from `created_at` too.
+    pub fn is_expired(&self, current_slot: u64) -> bool {
+        matches!(self.status, ProposalStatus::Pending | ProposalStatus::Approved)
+            && current_slot >= self.created_at.saturating_add(PROPOSAL_EXPIRY_SLOTS)
+    }
+}
+
+impl ProposalRecord {
+    // id(8) + proposal_type(1) + proposer(32) + multisig_config(32) + status(1) +
+    // signatures(1*3) + created_at(8) + executed_at(9) + closed_at(8) + data_hash(32) + bump(1)
+    pub const LEN: usize = 8 + 1 + 32 + 32 + 1 + 3 + 8 + 9 + 8 + 32 + 1;
+}
+
+// View instruction results
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct PositionStatus {
+    pub owner: Pubkey,
+    pub stake_pool: Pubkey,
+    pub staked_amount: u64,
+    pub withdrawal_requested: bool,
+    pub unlocked: bool,                 // Withdrawal requested and lock period over
+    pub slots_until_unlock: u64,        // 0 once unlocked or if no withdrawal is requested
+    pub seconds_until_unlock: u64,
+    pub withdrawable_amount: u64,       // Amount `withdraw` would pay out now
+    pub unvested_amount: u64,           // Amount held back by the vesting schedule
+    pub lock_end: u64,
+    pub voting_power: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct StakeAtSlot {
+    pub slot: u64,
+    pub amount: u64,                    // Position's staked amount at the end of `slot`
+    pub pool_total: u64,                // Pool's total staked at the end of `slot`
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct PoolStats {
+    pub stake_pool: Pubkey,
+    pub token_mint: Pubkey,
+    pub total_staked: u64,
+    pub remaining_capacity: u64,        // Room left below max_pool_size
+    pub emergency_mode: bool,
+    pub breaker_tripped: bool,
+    pub lock_clock: LockClock,
+    pub lock_duration_slots: u64,
+    pub lock_duration_seconds: u64,
+    pub min_stake: u64,                 // Limits in base units
+    pub max_individual_stake: u64,
+    pub min_unstake: u64,
+    pub max_individual_unstake: u64,
+    pub max_pool_size: u64,
+    pub outflow_cap: u64,
+    pub surplus: u64,
+}
+
+// Error enumeration
+#[error_code]
+pub enum StakeError {
+    #[msg("Amount must be greater than zero")]
+    AmountMustBeGreaterThanZero,
+
+    #[msg("Below minimum stake amount")]
+    BelowMinimumStakeAmount,
+
+    #[msg("Contract is paused")]
+    ContractPaused,
+
+    #[msg("No staked tokens")]
+    NoStakedTokens,
+
+    #[msg("Withdrawal already requested")]
+    WithdrawalAlreadyRequested,
+
+    #[msg("Withdrawal not requested")]
+    WithdrawalNotRequested,
+
+    #[msg("Tokens still locked")]
+    TokensStillLocked,
+
+    #[msg("Arithmetic overflow")]
+    ArithmeticOverflow,
+
+    #[msg("Invalid token mint")]
+    InvalidTokenMint,
+
+    #[msg("Invalid team wallet")]
+    InvalidTeamWallet,
+
+    #[msg("Insufficient funds in stake pool vault")]
+    InsufficientFunds,
+
+    #[msg("Invalid user")]
+    InvalidUser,
+
+    #[msg("Reentrancy attack detected")]
+    ReentrancyDetected,
+
+    #[msg("Invalid multisig signer")]
+    InvalidMultisigSigner,
+
+    #[msg("Insufficient signatures")]
+    InsufficientSignatures,
+
+    #[msg("Proposal already executed")]
+    ProposalAlreadyExecuted,
+
+    #[msg("Proposal not approved")]
+    ProposalNotApproved,
+
+    #[msg("Invalid proposal type")]
+    InvalidProposalType,
+
+    #[msg("Invalid threshold")]
+    InvalidThreshold,
+
+    #[msg("Already signed")]
+    AlreadySigned,
+
+    #[msg("Exceeds maximum stake amount")]
+    ExceedsMaximumStakeAmount,
+
+    #[msg("Exceeds maximum pool size")]
+    ExceedsMaximumPoolSize,
+
+    #[msg("Excessive lock duration")]
+    ExcessiveLockDuration,
+
+    #[msg("Below minimum unstake amount")]
+    BelowMinimumUnstakeAmount,
+
+    #[msg("Exceeds maximum unstake amount")]
+    ExceedsMaximumUnstakeAmount,
+
+    #[msg("Duplicate signers for multisig")]
+    DuplicateSigner,
+
+    #[msg("Proposal data too large")]
+    ProposalDataTooLarge,
+
+    #[msg("Proposal expired")]
+    ProposalExpired,
+
+    #[msg("Proposal cannot be closed yet")]
+    ProposalNotClosable,
+
+    #[msg("Stake account still holds tokens")]
+    StakeAccountNotEmpty,
+
+    #[msg("Invalid token program")]
+    InvalidTokenProgram,
+
+    #[msg("Mint decimals do not match the stake pool")]
+    InvalidMintDecimals,
+
+    #[msg("Mint decimals too large for the stake pool limits")]
+    UnsupportedMintDecimals,
+
+    #[msg("Invalid pool limits")]
+    InvalidPoolLimits,
+
+    #[msg("Pool registry is full")]
+    PoolRegistryFull,
+
+    #[msg("Pool already registered")]
+    PoolAlreadyRegistered,
+
+    #[msg("Recipient consent required")]
+    RecipientConsentRequired,
+
+    #[msg("New lock end must be later than the current one")]
+    LockEndNotExtended,
+
+    #[msg("Invalid vesting schedule")]
+    InvalidVestingSchedule,
+
+    #[msg("Position already has an active vesting schedule")]
+    VestingAlreadyActive,
+
+    #[msg("Nothing vested yet")]
+    NothingVested,
+
+    #[msg("Circuit breaker tripped: vault outflows are halted until reset by multisig")]
+    CircuitBreakerTripped,
+
+    #[msg("Invalid circuit breaker configuration")]
+    InvalidCircuitBreakerConfig,
+
+    #[msg("Amount exceeds the reconciled vault surplus")]
+    ExceedsSurplus,
+
+    #[msg("Account cannot be rescued")]
+    InvalidRescueAccount,
+
+    #[msg("Account is already at the current layout version")]
+    AccountAlreadyMigrated,
+
+    #[msg("Keeper tip exceeds the maximum")]
+    ExcessiveKeeperTip,
+
+    #[msg("Position has not opted in to keeper withdrawals")]
+    AutoWithdrawNotEnabled,
+
+    #[msg("Invalid tier table")]
+    InvalidTierTable,
+
+    #[msg("No checkpoint covers the requested slot")]
+    CheckpointUnavailable,
+
+    #[msg("Mint uses a Token-2022 extension the stake pool does not support")]
+    UnsupportedMintExtension,
+
+    #[msg("Beneficiary must sign to accept a vesting schedule")]
+    BeneficiaryConsentRequired,
+}
+
+// Account validation structures
+#[derive(Accounts)]
+#[instruction(
+    stake_pool_bump: u8,
+    lock_duration_slots: Option<u64>,
+    lock_clock: Option<LockClock>,
+    lock_duration_seconds: Option<u64>,
+    pool_id: u64,
+)]
+pub struct Initialize<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + StakePool::LEN,
+        seeds = [b"stake_pool".as_ref(), token_mint.key().as_ref(), StakePool::pool_id_seed(pool_id).as_slice()],
+        bump,
+    )]
+    pub stake_pool: AccountLoader<'info, StakePool>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + PoolCheckpoints::LEN,
+        seeds = [b"pool_checkpoints".as_ref(), stake_pool.key().as_ref()],
+        bump,
+    )]
+    pub pool_checkpoints: AccountLoader<'info, PoolCheckpoints>,
+
+    #[account(
+        mut,
+        seeds = [b"pool_registry".as_ref(), token_mint.key().as_ref()],
+        bump = pool_registry.bump,
+    )]
+    pub pool_registry: Account<'info, PoolRegistry>,
+
+    /// CHECK: Mint owned by the SPL Token or Token-2022 program, unpacked in the handler
+    pub token_mint: UncheckedAccount<'info>,
+
+    /// CHECK: New token vault account, created and initialized in the handler
+    #[account(mut)]
+    pub token_vault: Signer<'info>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+    /// CHECK: Either the SPL Token or Token-2022 program, verified in the handler
+    pub token_program: UncheckedAccount<'info>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct InitializePoolRegistry<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + PoolRegistry::LEN,
+        seeds = [b"pool_registry".as_ref(), token_mint.key().as_ref()],
+        bump,
+    )]
+    pub pool_registry: Account<'info, PoolRegistry>,
+
+    /// CHECK: Mint owned by the SPL Token or Token-2022 program
+    #[account(
+        constraint = token_interface::is_token_program(token_mint.owner) @ StakeError::InvalidTokenProgram,
+    )]
+    pub token_mint: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RegisterPool<'info> {
+    #[account(
+        seeds = [b"stake_pool".as_ref(), stake_pool.load()?.token_mint.as_ref(), StakePool::pool_id_seed(stake_pool.load()?.pool_id).as_slice()],
+        bump = stake_pool.load()?.bump,
+        has_one = authority,
+    )]
+    pub stake_pool: AccountLoader<'info, StakePool>,
+
+    #[account(
+        mut,
+        seeds = [b"pool_registry".as_ref(), stake_pool.load()?.token_mint.as_ref()],
+        bump = pool_registry.bump,
+    )]
+    pub pool_registry: Account<'info, PoolRegistry>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeTierTable<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + TierTable::LEN,
+        seeds = [b"tier_table".as_ref(), stake_pool.key().as_ref()],
+        bump,
+    )]
+    pub tier_table: Account<'info, TierTable>,
+
+    #[account(
+        seeds = [b"stake_pool".as_ref(), stake_pool.load()?.token_mint.as_ref(), StakePool::pool_id_seed(stake_pool.load()?.pool_id).as_slice()],
+        bump = stake_pool.load()?.bump,
+    )]
+    pub stake_pool: AccountLoader<'info, StakePool>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(multisig_bump: u8)]
+pub struct InitializeMultisig<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + MultisigConfig::LEN,
+        seeds = [b"multisig_config", stake_pool.key().as_ref()],
+        bump,
+    )]
+    pub multisig_config: Account<'info, MultisigConfig>,
+
+    #[account(
+        mut,
+        has_one = authority,
+    )]
+    pub stake_pool: AccountLoader<'info, StakePool>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+#[instruction(_proposal_bump: u8)]
+pub struct CreateProposal<'info> {
+    #[account(
+        init,
+        payer = proposer,
+        space = 8 + MultisigProposal::LEN,
+        seeds = [b"proposal", multisig_config.key().as_ref(), &multisig_config.proposal_count.to_le_bytes()],
+        bump,
+    )]
+    pub proposal: Account<'info, MultisigProposal>,
+
+    #[account(
+        mut,
+        has_one = stake_pool,
+    )]
+    pub multisig_config: Account<'info, MultisigConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"stake_pool".as_ref(), stake_pool.load()?.token_mint.as_ref(), StakePool::pool_id_seed(stake_pool.load()?.pool_id).as_slice()],
+        bump = stake_pool.load()?.bump,
+    )]
+    pub stake_pool: AccountLoader<'info, StakePool>,
+
+    #[account(mut)]
+    pub proposer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct SignProposal<'info> {
+    #[account(
+        mut,
+        has_one = multisig_config,
+    )]
+    pub proposal: Account<'info, MultisigProposal>,
+
+    #[account(has_one = stake_pool)]
+    pub multisig_config: Account<'info, MultisigConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"stake_pool".as_ref(), stake_pool.load()?.token_mint.as_ref(), StakePool::pool_id_seed(stake_pool.load()?.pool_id).as_slice()],
+        bump = stake_pool.load()?.bump,
+    )]
+    pub stake_pool: AccountLoader<'info, StakePool>,
+
+    pub signer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteProposal<'info> {
+    #[account(
+        mut,
+        has_one = multisig_config,
+    )]
+    pub proposal: Account<'info, MultisigProposal>,
+
+    #[account(has_one = stake_pool)]
+    pub multisig_config: Account<'info, MultisigConfig>,
+
+    #[account(mut)]
+    pub stake_pool: AccountLoader<'info, StakePool>,
+
+    pub executor: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(user_stake_bump: u8)]
+pub struct CreateUserStake<'info> {
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + UserStake::LEN,
+        seeds = [b"user_stake".as_ref(), stake_pool.key().as_ref(), owner.key().as_ref()],
+        bump,
+    )]
+    pub user_stake: Account<'info, UserStake>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = 8 + StakeCheckpoints::LEN,
+        seeds = [b"stake_checkpoints".as_ref(), user_stake.key().as_ref()],
+        bump,
+    )]
+    pub checkpoints: AccountLoader<'info, StakeCheckpoints>,
+
+    #[account(mut)]
+    pub stake_pool: AccountLoader<'info, StakePool>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct Stake<'info> {
+    #[account(
+        mut,
+        seeds = [b"user_stake".as_ref(), stake_pool.key().as_ref(), owner.key().as_ref()],
+        bump = user_stake.bump,
+        has_one = owner,
+        has_one = stake_pool,
+    )]
+    pub user_stake: Account<'info, UserStake>,
+
+    #[account(
+        mut,
+        seeds = [b"stake_checkpoints".as_ref(), user_stake.key().as_ref()],
+        bump = checkpoints.load()?.bump,
+    )]
+    pub checkpoints: AccountLoader<'info, StakeCheckpoints>,
+
+    #[account(
+        mut,
+        seeds = [b"stake_pool".as_ref(), stake_pool.load()?.token_mint.as_ref(), StakePool::pool_id_seed(stake_pool.load()?.pool_id).as_slice()],
+        bump = stake_pool.load()?.bump,
+    )]
+    pub stake_pool: AccountLoader<'info, StakePool>,
+
+    #[account(
+        mut,
+        seeds = [b"pool_checkpoints".as_ref(), stake_pool.key().as_ref()],
+        bump = pool_checkpoints.load()?.bump,
+    )]
+    pub pool_checkpoints: AccountLoader<'info, PoolCheckpoints>,
+
+    /// CHECK: This is the PDA that acts as the authority for the token vault
+    #[account(
+        seeds = [b"stake_pool".as_ref(), stake_pool.load()?.token_mint.as_ref(), StakePool::pool_id_seed(stake_pool.load()?.pool_id).as_slice()],
+        bump = stake_pool.load()?.bump,
+    )]
+    pub stake_pool_authority: AccountInfo<'info>,
+
+    /// CHECK: Verified against stake_pool.token_vault
+    #[account(
+        mut,
+        constraint = token_vault.key() == stake_pool.load()?.token_vault
+    )]
+    pub token_vault: UncheckedAccount<'info>,
+
+    /// CHECK: Verified against stake_pool.token_mint
+    #[account(
+        constraint = token_mint.key() == stake_pool.load()?.token_mint @ StakeError::InvalidTokenMint,
+    )]
+    pub token_mint: UncheckedAccount<'info>,
+
+    /// CHECK: Unpacked in the handler, which verifies its mint and owner
+    #[account(mut)]
+    pub user_token_account: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    /// CHECK: Verified against stake_pool.token_program
+    #[account(
+        constraint = token_program.key() == stake_pool.load()?.token_program @ StakeError::InvalidTokenProgram,
+    )]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+
+
+#[derive(Accounts)]
+pub struct CreateUserStakeFor<'info> {
+    #[account(
+        init,
+        payer = funder,
+        space = 8 + UserStake::LEN,
+        seeds = [b"user_stake".as_ref(), stake_pool.key().as_ref(), beneficiary.key().as_ref()],
+        bump,
+    )]
+    pub user_stake: Account<'info, UserStake>,
+
+    #[account(
+        init_if_needed,
+        payer = funder,
+        space = 8 + StakeCheckpoints::LEN,
+        seeds = [b"stake_checkpoints".as_ref(), user_stake.key().as_ref()],
+        bump,
+    )]
+    pub checkpoints: AccountLoader<'info, StakeCheckpoints>,
+
+    #[account(mut)]
+    pub stake_pool: AccountLoader<'info, StakePool>,
+
+    /// CHECK: Wallet that will own the position
+    pub beneficiary: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub funder: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct StakeFor<'info> {
+    #[account(
+        mut,
+        seeds = [b"user_stake".as_ref(), stake_pool.key().as_ref(), beneficiary.key().as_ref()],
+        bump = user_stake.bump,
+        has_one = stake_pool,
+        constraint = user_stake.owner == beneficiary.key() @ StakeError::InvalidUser,
+    )]
+    pub user_stake: Account<'info, UserStake>,
+
+    #[account(
+        mut,
+        seeds = [b"stake_checkpoints".as_ref(), user_stake.key().as_ref()],
+        bump = checkpoints.load()?.bump,
+    )]
+    pub checkpoints: AccountLoader<'info, StakeCheckpoints>,
+
+    #[account(
+        mut,
+        seeds = [b"stake_pool".as_ref(), stake_pool.load()?.token_mint.as_ref(), StakePool::pool_id_seed(stake_pool.load()?.pool_id).as_slice()],
+        bump = stake_pool.load()?.bump,
+    )]
+    pub stake_pool: AccountLoader<'info, StakePool>,
+
+    #[account(
+        mut,
+        seeds = [b"pool_checkpoints".as_ref(), stake_pool.key().as_ref()],
+        bump = pool_checkpoints.load()?.bump,
+    )]
+    pub pool_checkpoints: AccountLoader<'info, PoolCheckpoints>,
+
+    /// CHECK: Verified against stake_pool.token_vault
+    #[account(
+        mut,
+        constraint = token_vault.key() == stake_pool.load()?.token_vault
+    )]
+    pub token_vault: UncheckedAccount<'info>,
+
+    /// CHECK: Verified against stake_pool.token_mint
+    #[account(
+        constraint = token_mint.key() == stake_pool.load()?.token_mint @ StakeError::InvalidTokenMint,
+    )]
+    pub token_mint: UncheckedAccount<'info>,
+
+    /// CHECK: Unpacked in the handler, which verifies its mint and owner
+    #[account(mut)]
+    pub funder_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Wallet owning the credited position
+    pub beneficiary: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub funder: Signer<'info>,
+
+    /// CHECK: Verified against stake_pool.token_program
+    #[account(
+        constraint = token_program.key() == stake_pool.load()?.token_program @ StakeError::InvalidTokenProgram,
+    )]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct TransferPosition<'info> {
+    #[account(
+        mut,
+        seeds = [b"user_stake".as_ref(), stake_pool.key().as_ref(), owner.key().as_ref()],
+        bump = from_user_stake.bump,
+        has_one = owner,
+        has_one = stake_pool,
+    )]
+    pub from_user_stake: Account<'info, UserStake>,
+
+    #[account(
+        mut,
+        seeds = [b"stake_checkpoints".as_ref(), from_user_stake.key().as_ref()],
+        bump = from_checkpoints.load()?.bump,
+    )]
+    pub from_checkpoints: AccountLoader<'info, StakeCheckpoints>,
+
+    #[account(
+        mut,
+        seeds = [b"user_stake".as_ref(), stake_pool.key().as_ref(), new_owner.key().as_ref()],
+        bump = to_user_stake.bump,
+        has_one = stake_pool,
+        constraint = to_user_stake.owner == new_owner.key() @ StakeError::InvalidUser,
+    )]
+    pub to_user_stake: Account<'info, UserStake>,
+
+    #[account(
+        mut,
+        seeds = [b"stake_checkpoints".as_ref(), to_user_stake.key().as_ref()],
+        bump = to_checkpoints.load()?.bump,
+    )]
+    pub to_checkpoints: AccountLoader<'info, StakeCheckpoints>,
+
+    #[account(mut)]
+    pub stake_pool: AccountLoader<'info, StakePool>,
+
+    pub owner: Signer<'info>,
+
+    /// CHECK: Recipient wallet; must sign when the pool requires transfer consent
+    pub new_owner: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RequestWithdrawal<'info> {
+    #[account(
+        mut,
+        seeds = [b"user_stake".as_ref(), stake_pool.key().as_ref(), owner.key().as_ref()],
+        bump = user_stake.bump,
+        has_one = owner,
+        has_one = stake_pool,
+    )]
+    pub user_stake: Account<'info, UserStake>,
+
+    #[account(mut)]
+    pub stake_pool: AccountLoader<'info, StakePool>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ExtendLock<'info> {
+    #[account(
+        mut,
+        seeds = [b"user_stake".as_ref(), stake_pool.key().as_ref(), owner.key().as_ref()],
+        bump = user_stake.bump,
+        has_one = owner,
+        has_one = stake_pool,
+    )]
+    pub user_stake: Account<'info, UserStake>,
+
+    pub stake_pool: AccountLoader<'info, StakePool>,
+
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct VerifyTier<'info> {
+    #[account(
+        mut,
+        seeds = [b"user_stake".as_ref(), stake_pool.key().as_ref(), user_stake.owner.as_ref()],
+        bump = user_stake.bump,
+        has_one = stake_pool,
+    )]
+    pub user_stake: Account<'info, UserStake>,
+
+    #[account(
+        seeds = [b"stake_pool".as_ref(), stake_pool.load()?.token_mint.as_ref(), StakePool::pool_id_seed(stake_pool.load()?.pool_id).as_slice()],
+        bump = stake_pool.load()?.bump,
+    )]
+    pub stake_pool: AccountLoader<'info, StakePool>,
+
+    #[account(
+        seeds = [b"tier_table".as_ref(), stake_pool.key().as_ref()],
+        bump = tier_table.bump,
+        has_one = stake_pool,
+    )]
+    pub tier_table: Account<'info, TierTable>,
+}
+
+#[derive(Accounts)]
+pub struct GetPositionSnapshot<'info> {
+    #[account(
+        seeds = [b"user_stake".as_ref(), stake_pool.key().as_ref(), user_stake.owner.as_ref()],
+        bump = user_stake.bump,
+        has_one = stake_pool,
+    )]
+    pub user_stake: Account<'info, UserStake>,
+
+    #[account(
+        seeds = [b"stake_pool".as_ref(), stake_pool.load()?.token_mint.as_ref(), StakePool::pool_id_seed(stake_pool.load()?.pool_id).as_slice()],
+        bump = stake_pool.load()?.bump,
+    )]
+    pub stake_pool: AccountLoader<'info, StakePool>,
+
+    /// CHECK: The pool's tier table PDA, which may not exist yet; decoded in the handler when it does
+    #[account(
+        seeds = [b"tier_table".as_ref(), stake_pool.key().as_ref()],
+        bump,
+    )]
+    pub tier_table: UncheckedAccount<'info>,
+}
+
+// cfx-stake-interface hardcodes the snapshot instruction's discriminator
+const _: () = {
+    let (expected, actual) = (
+        cfx_stake_interface::GET_POSITION_SNAPSHOT_DISCRIMINATOR,
+        <instruction::GetPositionSnapshot as Discriminator>::DISCRIMINATOR,
+    );
+    let mut i = 0;
+    while i < expected.len() {
+        assert!(expected[i] == actual[i]);
+        i += 1;
+    }
+};
+
+#[derive(Accounts)]
+pub struct InitializeStakeCheckpoints<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + StakeCheckpoints::LEN,
+        seeds = [b"stake_checkpoints".as_ref(), user_stake.key().as_ref()],
+        bump,
+    )]
+    pub checkpoints: AccountLoader<'info, StakeCheckpoints>,
+
+    #[account(
+        seeds = [b"user_stake".as_ref(), stake_pool.key().as_ref(), user_stake.owner.as_ref()],
+        bump = user_stake.bump,
+        has_one = stake_pool,
+    )]
+    pub user_stake: Account<'info, UserStake>,
+
+    #[account(
+        seeds = [b"stake_pool".as_ref(), stake_pool.load()?.token_mint.as_ref(), StakePool::pool_id_seed(stake_pool.load()?.pool_id).as_slice()],
+        bump = stake_pool.load()?.bump,
+    )]
+    pub stake_pool: AccountLoader<'info, StakePool>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializePoolCheckpoints<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + PoolCheckpoints::LEN,
+        seeds = [b"pool_checkpoints".as_ref(), stake_pool.key().as_ref()],
+        bump,
+    )]
+    pub pool_checkpoints: AccountLoader<'info, PoolCheckpoints>,
+
+    #[account(
+        seeds = [b"stake_pool".as_ref(), stake_pool.load()?.token_mint.as_ref(), StakePool::pool_id_seed(stake_pool.load()?.pool_id).as_slice()],
+        bump = stake_pool.load()?.bump,
+    )]
+    pub stake_pool: AccountLoader<'info, StakePool>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct StakeAt<'info> {
+    #[account(
+        seeds = [b"user_stake".as_ref(), stake_pool.key().as_ref(), user_stake.owner.as_ref()],
+        bump = user_stake.bump,
+        has_one = stake_pool,
+    )]
+    pub user_stake: Account<'info, UserStake>,
+
+    #[account(
+        seeds = [b"stake_checkpoints".as_ref(), user_stake.key().as_ref()],
+        bump = checkpoints.load()?.bump,
+    )]
+    pub checkpoints: AccountLoader<'info, StakeCheckpoints>,
+
+    #[account(
+        seeds = [b"stake_pool".as_ref(), stake_pool.load()?.token_mint.as_ref(), StakePool::pool_id_seed(stake_pool.load()?.pool_id).as_slice()],
+        bump = stake_pool.load()?.bump,
+    )]
+    pub stake_pool: AccountLoader<'info, StakePool>,
+
+    #[account(
+        seeds = [b"pool_checkpoints".as_ref(), stake_pool.key().as_ref()],
+        bump = pool_checkpoints.load()?.bump,
+    )]
+    pub pool_checkpoints: AccountLoader<'info, PoolCheckpoints>,
+}
+
+#[derive(Accounts)]
+pub struct GetPositionStatus<'info> {
+    #[account(
+        seeds = [b"user_stake".as_ref(), stake_pool.key().as_ref(), user_stake.owner.as_ref()],
+        bump = user_stake.bump,
+        has_one = stake_pool,
+    )]
+    pub user_stake: Account<'info, UserStake>,
+
+    #[account(
+        seeds = [b"stake_pool".as_ref(), stake_pool.load()?.token_mint.as_ref(), StakePool::pool_id_seed(stake_pool.load()?.pool_id).as_slice()],
+        bump = stake_pool.load()?.bump,
+    )]
+    pub stake_pool: AccountLoader<'info, StakePool>,
+}
+
+#[derive(Accounts)]
+pub struct GetPoolStats<'info> {
+    #[account(
+        seeds = [b"stake_pool".as_ref(), stake_pool.load()?.token_mint.as_ref(), StakePool::pool_id_seed(stake_pool.load()?.pool_id).as_slice()],
+        bump = stake_pool.load()?.bump,
+    )]
+    pub stake_pool: AccountLoader<'info, StakePool>,
+}
+
+#[derive(Accounts)]
+pub struct TogglePause<'info> {
+    #[account(
+        mut,
+        seeds = [b"stake_pool".as_ref(), stake_pool.load()?.token_mint.as_ref(), StakePool::pool_id_seed(stake_pool.load()?.pool_id).as_slice()],
+        bump = stake_pool.load()?.bump,
+        has_one = authority,
+    )]
+    pub stake_pool: AccountLoader<'info, StakePool>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct Withdraw<'info> {
+    #[account(
+        mut,
+        seeds = [b"user_stake".as_ref(), stake_pool.key().as_ref(), owner.key().as_ref()],
+        bump = user_stake.bump,
+        has_one = owner,
+        has_one = stake_pool,
+    )]
+    pub user_stake: Account<'info, UserStake>,
+
+    #[account(
+        mut,
+        seeds = [b"stake_checkpoints".as_ref(), user_stake.key().as_ref()],
+        bump = checkpoints.load()?.bump,
+    )]
+    pub checkpoints: AccountLoader<'info, StakeCheckpoints>,
+
+    #[account(
+        mut,
+        seeds = [b"stake_pool".as_ref(), stake_pool.load()?.token_mint.as_ref(), StakePool::pool_id_seed(stake_pool.load()?.pool_id).as_slice()],
+        bump = stake_pool.load()?.bump,
+    )]
+    pub stake_pool: AccountLoader<'info, StakePool>,
+
+    #[account(
+        mut,
+        seeds = [b"pool_checkpoints".as_ref(), stake_pool.key().as_ref()],
+        bump = pool_checkpoints.load()?.bump,
+    )]
+    pub pool_checkpoints: AccountLoader<'info, PoolCheckpoints>,
+
+    /// CHECK: This is the PDA that acts as the authority for the token vault
+    #[account(
+        seeds = [b"stake_pool".as_ref(), stake_pool.load()?.token_mint.as_ref(), StakePool::pool_id_seed(stake_pool.load()?.pool_id).as_slice()],
+        bump = stake_pool.load()?.bump,
+    )]
+    pub stake_pool_authority: AccountInfo<'info>,
+
+    /// CHECK: Verified against stake_pool.token_vault
+    #[account(
+        mut,
+        constraint = token_vault.key() == stake_pool.load()?.token_vault
+    )]
+    pub token_vault: UncheckedAccount<'info>,
+
+    /// CHECK: Verified against stake_pool.token_mint
+    #[account(
+        constraint = token_mint.key() == stake_pool.load()?.token_mint @ StakeError::InvalidTokenMint,
+    )]
+    pub token_mint: UncheckedAccount<'info>,
+
+    /// CHECK: Unpacked in the handler, which verifies its mint and owner
+    #[account(mut)]
+    pub user_token_account: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    /// CHECK: Verified against stake_pool.token_program
+    #[account(
+        constraint = token_program.key() == stake_pool.load()?.token_program @ StakeError::InvalidTokenProgram,
+    )]
+    pub token_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetAutoWithdraw<'info> {
+    #[account(
+        mut,
+        seeds = [b"user_stake".as_ref(), stake_pool.key().as_ref(), owner.key().as_ref()],
+        bump = user_stake.bump,
+        has_one = owner,
+        has_one = stake_pool,
+    )]
+    pub user_stake: Account<'info, UserStake>,
+
+    pub stake_pool: AccountLoader<'info, StakePool>,
+
+    /// CHECK: Unpacked in the handler when enabling, which verifies its mint and owner
+    pub payout_token_account: UncheckedAccount<'info>,
+
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CrankWithdraw<'info> {
+    #[account(
+        mut,
+        seeds = [b"user_stake".as_ref(), stake_pool.key().as_ref(), user_stake.owner.as_ref()],
+        bump = user_stake.bump,
+        has_one = stake_pool,
+    )]
+    pub user_stake: Account<'info, UserStake>,
+
+    #[account(
+        mut,
+        seeds = [b"stake_checkpoints".as_ref(), user_stake.key().as_ref()],
+        bump = checkpoints.load()?.bump,
+    )]
+    pub checkpoints: AccountLoader<'info, StakeCheckpoints>,
+
+    #[account(
+        mut,
+        seeds = [b"stake_pool".as_ref(), stake_pool.load()?.token_mint.as_ref(), StakePool::pool_id_seed(stake_pool.load()?.pool_id).as_slice()],
+        bump = stake_pool.load()?.bump,
+    )]
+    pub stake_pool: AccountLoader<'info, StakePool>,
+
+    #[account(
+        mut,
+        seeds = [b"pool_checkpoints".as_ref(), stake_pool.key().as_ref()],
+        bump = pool_checkpoints.load()?.bump,
+    )]
+    pub pool_checkpoints: AccountLoader<'info, PoolCheckpoints>,
+
+    /// CHECK: This is the PDA that acts as the authority for the token vault
+    #[account(
+        seeds = [b"stake_pool".as_ref(), stake_pool.load()?.token_mint.as_ref(), StakePool::pool_id_seed(stake_pool.load()?.pool_id).as_slice()],
+        bump = stake_pool.load()?.bump,
+    )]
+    pub stake_pool_authority: AccountInfo<'info>,
+
+    /// CHECK: Verified against stake_pool.token_vault
+    #[account(
+        mut,
+        constraint = token_vault.key() == stake_pool.load()?.token_vault
+    )]
+    pub token_vault: UncheckedAccount<'info>,
+
+    /// CHECK: Verified against stake_pool.token_mint
+    #[account(
+        constraint = token_mint.key() == stake_pool.load()?.token_mint @ StakeError::InvalidTokenMint,
+    )]
+    pub token_mint: UncheckedAccount<'info>,
+
+    /// CHECK: The payout account registered by `set_auto_withdraw`; its mint and owner are verified in the handler
+    #[account(
+        mut,
+        address = user_stake.payout_token_account @ StakeError::InvalidUser,
     )]
-    pub proposal: Account<'info, MultisigProposal>,
+    pub owner_token_account: UncheckedAccount<'info>,
 
+    /// CHECK: Receives the keeper tip; its mint is verified in the handler when a tip is paid
     #[account(mut)]
-    pub multisig_config: Account<'info, MultisigConfig>,
+    pub keeper_token_account: UncheckedAccount<'info>,
 
-    #[account(mut)]
-    pub proposer: Signer<'info>,
+    pub keeper: Signer<'info>,
 
-    pub system_program: Program<'info, System>,
-    pub rent: Sysvar<'info, Rent>,
+    /// CHECK: Verified against stake_pool.token_program
+    #[account(
+        constraint = token_program.key() == stake_pool.load()?.token_program @ StakeError::InvalidTokenProgram,
+    )]
+    pub token_program: UncheckedAccount<'info>,
 }
 
 #[derive(Accounts)]
-pub struct SignProposal<'info> {
+pub struct ExecuteAdminWithdraw<'info> {
     #[account(
         mut,
         has_one = multisig_config,
     )]
     pub proposal: Account<'info, MultisigProposal>,
 
+    #[account(has_one = stake_pool)]
     pub multisig_config: Account<'info, MultisigConfig>,
 
-    pub signer: Signer<'info>,
-}
+    #[account(
+        mut,
+        seeds = [b"stake_pool".as_ref(), stake_pool.load()?.token_mint.as_ref(), StakePool::pool_id_seed(stake_pool.load()?.pool_id).as_slice()],
+        bump = stake_pool.load()?.bump,
+    )]
+    pub stake_pool: AccountLoader<'info, StakePool>,
 
-#[derive(Accounts)]
-pub struct ExecuteProposal<'info> {
+    /// CHECK: This is the PDA that acts as the authority for the token vault
+    #[account(
+        seeds = [b"stake_pool".as_ref(), stake_pool.load()?.token_mint.as_ref(), StakePool::pool_id_seed(stake_pool.load()?.pool_id).as_slice()],
+        bump = stake_pool.load()?.bump,
+    )]
+    pub stake_pool_authority: AccountInfo<'info>,
+
+    /// CHECK: Verified against stake_pool.token_vault
     #[account(
         mut,
-        has_one = multisig_config,
+        constraint = token_vault.key() == stake_pool.load()?.token_vault
     )]
-    pub proposal: Account<'info, MultisigProposal>,
+    pub token_vault: UncheckedAccount<'info>,
 
-    pub multisig_config: Account<'info, MultisigConfig>,
+    /// CHECK: Verified against stake_pool.token_mint
+    #[account(
+        constraint = token_mint.key() == stake_pool.load()?.token_mint @ StakeError::InvalidTokenMint,
+    )]
+    pub token_mint: UncheckedAccount<'info>,
 
+    /// CHECK: Unpacked in the handler, which verifies its mint and owner
     #[account(mut)]
-    pub stake_pool: Account<'info, StakePool>,
+    pub recipient_token_account: UncheckedAccount<'info>,
 
     pub executor: Signer<'info>,
-}
 
-#[derive(Accounts)]
-#[instruction(user_stake_bump: u8)]
-pub struct CreateUserStake<'info> {
+    /// CHECK: Verified against stake_pool.token_program
     #[account(
-        init,
-        payer = owner,
-        space = 8 + UserStake::LEN,
-        seeds = [b"user_stake".as_ref(), stake_pool.key().as_ref(), owner.key().as_ref()],
-        bump,
+        constraint = token_program.key() == stake_pool.load()?.token_program @ StakeError::InvalidTokenProgram,
     )]
-    pub user_stake: Account<'info, UserStake>,
-
-    pub stake_pool: Account<'info, StakePool>,
-
-    #[account(mut)]
-    pub owner: Signer<'info>,
-
-    pub system_program: Program<'info, System>,
-    pub rent: Sysvar<'info, Rent>,
+    pub token_program: UncheckedAccount<'info>,
 }
 
 #[derive(Accounts)]
-pub struct Stake<'info> {
+pub struct ExecuteRescueTokens<'info> {
     #[account(
         mut,
-        seeds = [b"user_stake".as_ref(), stake_pool.key().as_ref(), owner.key().as_ref()],
-        bump = user_stake.bump,
-        has_one = owner,
-        has_one = stake_pool,
+        has_one = multisig_config,
     )]
-    pub user_stake: Account<'info, UserStake>,
+    pub proposal: Account<'info, MultisigProposal>,
+
+    #[account(has_one = stake_pool)]
+    pub multisig_config: Account<'info, MultisigConfig>,
 
     #[account(
         mut,
-        seeds = [b"stake_pool".as_ref(), stake_pool.token_mint.as_ref()],
-        bump = stake_pool.bump,
+        seeds = [b"stake_pool".as_ref(), stake_pool.load()?.token_mint.as_ref(), StakePool::pool_id_seed(stake_pool.load()?.pool_id).as_slice()],
+        bump = stake_pool.load()?.bump,
     )]
-    pub stake_pool: Account<'info, StakePool>,
+    pub stake_pool: AccountLoader<'info, StakePool>,
 
-    /// CHECK: This is the PDA that acts as the authority for the token vault
+    /// CHECK: This is the PDA that owns the rescued token account
     #[account(
-        seeds = [b"stake_pool".as_ref(), stake_pool.token_mint.as_ref()],
-        bump = stake_pool.bump,
+        seeds = [b"stake_pool".as_ref(), stake_pool.load()?.token_mint.as_ref(), StakePool::pool_id_seed(stake_pool.load()?.pool_id).as_slice()],
+        bump = stake_pool.load()?.bump,
     )]
     pub stake_pool_authority: AccountInfo<'info>,
 
+    /// CHECK: Unpacked in the handler, which refuses the vault and the staked mint
+    #[account(mut)]
+    pub source_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Verified against the source account's mint in the handler
+    pub mint: UncheckedAccount<'info>,
+
+    /// CHECK: Unpacked in the handler, which verifies its mint and owner
+    #[account(mut)]
+    pub recipient_token_account: UncheckedAccount<'info>,
+
+    pub executor: Signer<'info>,
+
+    /// CHECK: Must be a token program and own the source account
     #[account(
-        mut,
-        constraint = token_vault.key() == stake_pool.token_vault
+        constraint = token_interface::is_token_program(token_program.key) @ StakeError::InvalidTokenProgram,
     )]
-    pub token_vault: Account<'info, TokenAccount>,
+    pub token_program: UncheckedAccount<'info>,
+}
 
+#[derive(Accounts)]
+pub struct ExecuteSetTierTable<'info> {
     #[account(
         mut,
-        constraint = user_token_account.mint == stake_pool.token_mint @ StakeError::InvalidTokenMint,
-        constraint = user_token_account.owner == owner.key(),
+        has_one = multisig_config,
     )]
-    pub user_token_account: Account<'info, TokenAccount>,
-
-    #[account(mut)]
-    pub owner: Signer<'info>,
-
-    pub token_program: Program<'info, Token>,
-}
+    pub proposal: Account<'info, MultisigProposal>,
 
+    #[account(has_one = stake_pool)]
+    pub multisig_config: Account<'info, MultisigConfig>,
 
+    #[account(
+        mut,
+        seeds = [b"stake_pool".as_ref(), stake_pool.load()?.token_mint.as_ref(), StakePool::pool_id_seed(stake_pool.load()?.pool_id).as_slice()],
+        bump = stake_pool.load()?.bump,
+    )]
+    pub stake_pool: AccountLoader<'info, StakePool>,
 
-#[derive(Accounts)]
-pub struct RequestWithdrawal<'info> {
     #[account(
         mut,
-        seeds = [b"user_stake".as_ref(), stake_pool.key().as_ref(), owner.key().as_ref()],
-        bump = user_stake.bump,
-        has_one = owner,
+        seeds = [b"tier_table".as_ref(), stake_pool.key().as_ref()],
+        bump = tier_table.bump,
         has_one = stake_pool,
     )]
-    pub user_stake: Account<'info, UserStake>,
+    pub tier_table: Account<'info, TierTable>,
 
-    pub stake_pool: Account<'info, StakePool>,
+    pub executor: Signer<'info>,
+}
 
+#[derive(Accounts)]
+pub struct MigrateStakePool<'info> {
+    /// CHECK: Owner and discriminator are verified in the handler before the account is resized
     #[account(mut)]
-    pub owner: Signer<'info>,
+    pub stake_pool: UncheckedAccount<'info>,
+
+    /// CHECK: Verified against the pool's token_mint in the handler
+    pub token_mint: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct TogglePause<'info> {
+pub struct MigrateUserStake<'info> {
+    /// CHECK: Owner and discriminator are verified in the handler before the account is resized
+    #[account(mut)]
+    pub user_stake: UncheckedAccount<'info>,
+
     #[account(
         mut,
-        seeds = [b"stake_pool".as_ref(), stake_pool.token_mint.as_ref()],
-        bump = stake_pool.bump,
-        has_one = authority,
+        seeds = [b"stake_pool".as_ref(), stake_pool.load()?.token_mint.as_ref(), StakePool::pool_id_seed(stake_pool.load()?.pool_id).as_slice()],
+        bump = stake_pool.load()?.bump,
     )]
-    pub stake_pool: Account<'info, StakePool>,
+    pub stake_pool: AccountLoader<'info, StakePool>,
 
     #[account(mut)]
-    pub authority: Signer<'info>,
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct Withdraw<'info> {
-    #[account(
-        mut,
-        seeds = [b"user_stake".as_ref(), stake_pool.key().as_ref(), owner.key().as_ref()],
-        bump = user_stake.bump,
-        has_one = owner,
-        has_one = stake_pool,
-    )]
-    pub user_stake: Account<'info, UserStake>,
+pub struct MigrateMultisigConfig<'info> {
+    /// CHECK: Owner and discriminator are verified in the handler before the account is resized
+    #[account(mut)]
+    pub multisig_config: UncheckedAccount<'info>,
 
     #[account(
         mut,
-        seeds = [b"stake_pool".as_ref(), stake_pool.token_mint.as_ref()],
-        bump = stake_pool.bump,
+        seeds = [b"stake_pool".as_ref(), stake_pool.load()?.token_mint.as_ref(), StakePool::pool_id_seed(stake_pool.load()?.pool_id).as_slice()],
+        bump = stake_pool.load()?.bump,
     )]
-    pub stake_pool: Account<'info, StakePool>,
+    pub stake_pool: AccountLoader<'info, StakePool>,
 
-    /// CHECK: This is the PDA that acts as the authority for the token vault
-    #[account(
-        seeds = [b"stake_pool".as_ref(), stake_pool.token_mint.as_ref()],
-        bump = stake_pool.bump,
-    )]
-    pub stake_pool_authority: AccountInfo<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateProposal<'info> {
+    /// CHECK: Owner and discriminator are verified in the handler before the account is resized
+    #[account(mut)]
+    pub proposal: UncheckedAccount<'info>,
 
     #[account(
-        mut,
-        constraint = token_vault.key() == stake_pool.token_vault
+        seeds = [b"multisig_config", stake_pool.key().as_ref()],
+        bump = multisig_config.bump,
     )]
-    pub token_vault: Account<'info, TokenAccount>,
+    pub multisig_config: Account<'info, MultisigConfig>,
 
     #[account(
         mut,
-        constraint = user_token_account.mint == stake_pool.token_mint @ StakeError::InvalidTokenMint,
-        constraint = user_token_account.owner == owner.key(),
+        seeds = [b"stake_pool".as_ref(), stake_pool.load()?.token_mint.as_ref(), StakePool::pool_id_seed(stake_pool.load()?.pool_id).as_slice()],
+        bump = stake_pool.load()?.bump,
     )]
-    pub user_token_account: Account<'info, TokenAccount>,
+    pub stake_pool: AccountLoader<'info, StakePool>,
 
     #[account(mut)]
-    pub owner: Signer<'info>,
+    pub payer: Signer<'info>,
 
-    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct ExecuteAdminWithdraw<'info> {
+pub struct SyncVault<'info> {
     #[account(
         mut,
-        has_one = multisig_config,
+        seeds = [b"stake_pool".as_ref(), stake_pool.load()?.token_mint.as_ref(), StakePool::pool_id_seed(stake_pool.load()?.pool_id).as_slice()],
+        bump = stake_pool.load()?.bump,
     )]
-    pub proposal: Account<'info, MultisigProposal>,
-
-    pub multisig_config: Account<'info, MultisigConfig>,
+    pub stake_pool: AccountLoader<'info, StakePool>,
 
+    /// CHECK: Verified against stake_pool.token_vault
     #[account(
-        mut,
-        seeds = [b"stake_pool".as_ref(), stake_pool.token_mint.as_ref()],
-        bump = stake_pool.bump,
+        constraint = token_vault.key() == stake_pool.load()?.token_vault
     )]
-    pub stake_pool: Account<'info, StakePool>,
+    pub token_vault: UncheckedAccount<'info>,
+}
 
-    /// CHECK: This is the PDA that acts as the authority for the token vault
+#[derive(Accounts)]
+pub struct CloseUserStake<'info> {
     #[account(
-        seeds = [b"stake_pool".as_ref(), stake_pool.token_mint.as_ref()],
-        bump = stake_pool.bump,
+        mut,
+        seeds = [b"user_stake".as_ref(), stake_pool.key().as_ref(), owner.key().as_ref()],
+        bump = user_stake.bump,
+        has_one = owner,
+        has_one = stake_pool,
+        close = owner,
     )]
-    pub stake_pool_authority: AccountInfo<'info>,
+    pub user_stake: Account<'info, UserStake>,
+
+    pub stake_pool: AccountLoader<'info, StakePool>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+}
 
+#[derive(Accounts)]
+pub struct CloseProposal<'info> {
     #[account(
         mut,
-        constraint = token_vault.key() == stake_pool.token_vault
+        has_one = multisig_config,
+        has_one = proposer,
+        close = proposer,
     )]
-    pub token_vault: Account<'info, TokenAccount>,
+    pub proposal: Account<'info, MultisigProposal>,
 
     #[account(
-        mut,
-        constraint = recipient_token_account.mint == stake_pool.token_mint @ StakeError::InvalidTokenMint,
+        init,
+        payer = closer,
+        space = 8 + ProposalRecord::LEN,
+        seeds = [b"proposal_record", multisig_config.key().as_ref(), &proposal.id.to_le_bytes()],
+        bump,
     )]
-    pub recipient_token_account: Account<'info, TokenAccount>,
+    pub proposal_record: Account<'info, ProposalRecord>,
 
-    pub executor: Signer<'info>,
+    pub multisig_config: Account<'info, MultisigConfig>,
 
-    pub token_program: Program<'info, Token>,
-}
+    /// CHECK: Receives the proposal rent, verified against proposal.proposer
+    #[account(mut)]
+    pub proposer: AccountInfo<'info>,
 
+    #[account(mut)]
+    pub closer: Signer<'info>,
 
+    pub system_program: Program<'info, System>,
+}
 
 // Events
 #[event]
 pub struct StakeEvent {
     pub user: Pubkey,
+    pub stake_pool: Pubkey,
     pub amount_staked: u64,
     pub total_staked: u64,
-    pub timestamp: u64,
+    pub seq: u64,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct StakeForEvent {
+    pub funder: Pubkey,
+    pub beneficiary: Pubkey,
+    pub stake_pool: Pubkey,
+    pub amount_staked: u64,
+    pub total_staked: u64,
+    pub seq: u64,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct VestedStakeEvent {
+    pub funder: Pubkey,
+    pub beneficiary: Pubkey,
+    pub stake_pool: Pubkey,
+    pub amount_staked: u64,
+    pub vesting: VestingSchedule,
+    pub seq: u64,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PositionTransferredEvent {
+    pub from: Pubkey,
+    pub to: Pubkey,
+    pub stake_pool: Pubkey,
+    pub amount: u64,
+    pub seq: u64,
+    pub slot: u64,
+    pub timestamp: i64,
 }
 
 #[event]
 pub struct WithdrawEvent {
     pub user: Pubkey,
+    pub stake_pool: Pubkey,
     pub amount_withdrawn: u64,
-    pub timestamp: u64,
+    pub seq: u64,
+    pub slot: u64,
+    pub timestamp: i64,
 }
 
 #[event]
 pub struct WithdrawalRequestEvent {
     pub user: Pubkey,
+    pub stake_pool: Pubkey,
     pub unlock_slot: u64,
-    pub timestamp: u64,
+    pub unlock_timestamp: i64,
+    pub seq: u64,
+    pub slot: u64,
+    pub timestamp: i64,
     pub emergency_mode: bool,
+    pub queued: bool,
+}
+
+#[event]
+pub struct LockExtendedEvent {
+    pub user: Pubkey,
+    pub stake_pool: Pubkey,
+    pub old_lock_end: u64,
+    pub new_lock_end: u64,
+    pub voting_power: u64,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AccountMigratedEvent {
+    pub account: Pubkey,
+    pub stake_pool: Pubkey,
+    pub from_version: u8,
+    pub to_version: u8,
+    pub old_len: u64,
+    pub seq: u64,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct TokensRescuedEvent {
+    pub stake_pool: Pubkey,
+    pub mint: Pubkey,
+    pub source: Pubkey,
+    pub recipient: Pubkey,
+    pub amount: u64,
+    pub seq: u64,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct VaultSyncedEvent {
+    pub stake_pool: Pubkey,
+    pub vault_balance: u64,
+    pub total_staked: u64,
+    pub surplus: u64,
+    pub previous_surplus: u64,
+    pub deficit: u64,
+    pub seq: u64,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct CircuitBreakerTrippedEvent {
+    pub stake_pool: Pubkey,
+    pub window_outflow: u64,
+    pub total_staked: u64,
+    pub threshold_bps: u16,
+    pub seq: u64,
+    pub slot: u64,
+    pub timestamp: i64,
 }
 
 #[event]
 pub struct PauseEvent {
+    pub stake_pool: Pubkey,
     pub paused: bool,
+    pub was_paused: bool,
     pub authority: Pubkey,
-    pub timestamp: u64,
+    pub seq: u64,
+    pub slot: u64,
+    pub timestamp: i64,
 }
 
 #[event]
 pub struct AdminWithdrawEvent {
+    pub stake_pool: Pubkey,
     pub recipient: Pubkey,
     pub amount_withdrawn: u64,
-    pub timestamp: u64,
-}
\ No newline at end of file
+    pub seq: u64,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ProposalClosedEvent {
+    pub stake_pool: Pubkey,
+    pub proposal_id: u64,
+    pub multisig_config: Pubkey,
+    pub status: ProposalStatus,
+    pub closer: Pubkey,
+    pub rent_recipient: Pubkey,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct UserStakeClosedEvent {
+    pub user: Pubkey,
+    pub stake_pool: Pubkey,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PoolInitializedEvent {
+    pub stake_pool: Pubkey,
+    pub authority: Pubkey,
+    pub token_mint: Pubkey,
+    pub token_vault: Pubkey,
+    pub token_program: Pubkey,
+    pub pool_id: u64,
+    pub decimals: u8,
+    pub lock_clock: LockClock,
+    pub lock_duration_slots: u64,
+    pub lock_duration_seconds: u64,
+    pub limits: PoolLimits,
+    pub seq: u64,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MultisigInitializedEvent {
+    pub stake_pool: Pubkey,
+    pub multisig_config: Pubkey,
+    pub signers: [Pubkey; 3],
+    pub threshold: u8,
+    pub authority: Pubkey,
+    pub seq: u64,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct UserStakeCreatedEvent {
+    pub user: Pubkey,
+    pub stake_pool: Pubkey,
+    pub user_stake: Pubkey,
+    pub payer: Pubkey,
+    pub seq: u64,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ProposalCreatedEvent {
+    pub stake_pool: Pubkey,
+    pub multisig_config: Pubkey,
+    pub proposal_id: u64,
+    pub proposal_type: ProposalType,
+    pub proposer: Pubkey,
+    pub data: Vec<u8>,
+    pub seq: u64,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ProposalSignedEvent {
+    pub stake_pool: Pubkey,
+    pub multisig_config: Pubkey,
+    pub proposal_id: u64,
+    pub signer: Pubkey,
+    pub signature_count: u8,
+    pub threshold: u8,
+    pub status: ProposalStatus,
+    pub seq: u64,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ProposalExecutedEvent {
+    pub stake_pool: Pubkey,
+    pub multisig_config: Pubkey,
+    pub proposal_id: u64,
+    pub proposal_type: ProposalType,
+    pub executor: Pubkey,
+    pub seq: u64,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AuthorityUpdatedEvent {
+    pub stake_pool: Pubkey,
+    pub old_authority: Pubkey,
+    pub new_authority: Pubkey,
+    pub seq: u64,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct TransferConsentUpdatedEvent {
+    pub stake_pool: Pubkey,
+    pub old_required: bool,
+    pub new_required: bool,
+    pub seq: u64,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct OutflowCapUpdatedEvent {
+    pub stake_pool: Pubkey,
+    pub old_cap: u64,
+    pub new_cap: u64,
+    pub seq: u64,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct CircuitBreakerUpdatedEvent {
+    pub stake_pool: Pubkey,
+    pub old_threshold_bps: u16,
+    pub new_threshold_bps: u16,
+    pub old_window_slots: u64,
+    pub new_window_slots: u64,
+    pub seq: u64,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct CircuitBreakerResetEvent {
+    pub stake_pool: Pubkey,
+    pub was_tripped: bool,
+    pub window_outflow: u64,
+    pub seq: u64,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AutoWithdrawUpdatedEvent {
+    pub user: Pubkey,
+    pub stake_pool: Pubkey,
+    pub old_enabled: bool,
+    pub new_enabled: bool,
+    pub old_keeper_tip_bps: u16,
+    pub new_keeper_tip_bps: u16,
+    pub old_payout_token_account: Pubkey,
+    pub new_payout_token_account: Pubkey,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct CrankWithdrawEvent {
+    pub user: Pubkey,
+    pub stake_pool: Pubkey,
+    pub keeper: Pubkey,
+    pub owner_amount: u64,
+    pub keeper_tip: u64,
+    pub seq: u64,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct TierTableInitializedEvent {
+    pub stake_pool: Pubkey,
+    pub tier_table: Pubkey,
+    pub payer: Pubkey,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct TierTableUpdatedEvent {
+    pub stake_pool: Pubkey,
+    pub tier_table: Pubkey,
+    pub old_tiers: Vec<StakeTier>,
+    pub new_tiers: Vec<StakeTier>,
+    pub seq: u64,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct TierVerifiedEvent {
+    pub user: Pubkey,
+    pub stake_pool: Pubkey,
+    pub old_tier: u8,
+    pub tier: u8,
+    pub staked_amount: u64,
+    pub lock_end: u64,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct CheckpointsInitializedEvent {
+    pub stake_pool: Pubkey,
+    pub checkpoints: Pubkey,
+    pub user: Option<Pubkey>,           // None for the pool-wide history
+    pub amount: u64,                    // Balance the history starts from
+    pub slot: u64,
+    pub timestamp: i64,
+}
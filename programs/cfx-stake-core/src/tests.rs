@@ -0,0 +1,904 @@
+use anchor_lang::__private::bytemuck::{self, Zeroable};
+
+use super::*;
+
+// Slot-clock pool with whole-token base units and the default limits
+fn pool() -> StakePool {
+    let mut pool = StakePool::zeroed();
+    pool.limits = PoolLimits::default();
+    pool.lock_duration_slots = DEFAULT_LOCK_DURATION_SLOTS;
+    pool.version = StakePool::VERSION;
+    pool
+}
+
+fn position(staked_amount: u64) -> UserStake {
+    let mut user_stake = UserStake::try_from_slice(&[0u8; UserStake::LEN]).unwrap();
+    user_stake.staked_amount = staked_amount;
+    user_stake.version = UserStake::VERSION;
+    user_stake
+}
+
+fn clock(slot: u64) -> Clock {
+    Clock { slot, unix_timestamp: slot as i64, ..Clock::default() }
+}
+
+fn proposal(version: u8, status: ProposalStatus, created_at: u64) -> MultisigProposal {
+    MultisigProposal {
+        id: 0,
+        proposal_type: ProposalType::TogglePause,
+        proposer: Pubkey::default(),
+        multisig_config: Pubkey::default(),
+        status,
+        signatures: [true, false, false],
+        signature_count: 1,
+        created_at,
+        executed_at: None,
+        data: Vec::new(),
+        bump: 0,
+        version,
+        reserved: [0; 32],
+    }
+}
+
+#[test]
+fn proposals_expire_after_the_window() {
+    let pending = proposal(MultisigProposal::VERSION, ProposalStatus::Pending, 100);
+    assert!(!pending.is_expired(100 + PROPOSAL_EXPIRY_SLOTS - 1));
+    assert!(pending.is_expired(100 + PROPOSAL_EXPIRY_SLOTS));
+
+    let executed = proposal(MultisigProposal::VERSION, ProposalStatus::Executed, 100);
+    assert!(!executed.is_expired(100 + PROPOSAL_EXPIRY_SLOTS));
+}
+
+#[test]
+fn proposals_from_before_expiry_expire_from_their_creation() {
+    let legacy = proposal(0, ProposalStatus::Approved, 100);
+    assert!(!legacy.is_expired(100 + PROPOSAL_EXPIRY_SLOTS - 1));
+    assert!(legacy.is_expired(100 + PROPOSAL_EXPIRY_SLOTS));
+}
+
+// Bytes of a proposal account written before `version`/`reserved` existed: the
+// old account was 33 bytes smaller and zero-filled past the Borsh data
+fn legacy_proposal_account(data_len: usize) -> Vec<u8> {
+    let mut proposal = proposal(0, ProposalStatus::Executed, 100);
+    proposal.executed_at = Some(200);
+    proposal.data = vec![7; data_len];
+
+    let mut bytes = Vec::new();
+    proposal.try_serialize(&mut bytes).unwrap();
+    bytes.truncate(bytes.len() - 33);
+    assert!(bytes.len() <= 8 + MultisigProposal::LEN - 33);
+    bytes.resize(8 + MultisigProposal::LEN - 33, 0);
+    bytes
+}
+
+#[test]
+fn legacy_proposals_with_room_for_new_fields_still_decode() {
+    let bytes = legacy_proposal_account(223);
+    let decoded = MultisigProposal::try_deserialize(&mut bytes.as_slice()).unwrap();
+    assert_eq!(decoded.data, vec![7; 223]);
+    assert_eq!(decoded.executed_at, Some(200));
+    assert_eq!(decoded.version, 0);
+    assert_eq!(decoded.reserved, [0; 32]);
+
+    let bytes = legacy_proposal_account(224);
+    assert!(MultisigProposal::try_deserialize(&mut bytes.as_slice()).is_err());
+}
+
+#[test]
+fn legacy_proposals_with_long_data_decode_once_grown() {
+    // `migrate_proposal` zero-fills the 33 bytes the old account was missing
+    let mut bytes = legacy_proposal_account(MAX_PROPOSAL_DATA_LEN);
+    bytes.resize(8 + MultisigProposal::LEN, 0);
+
+    let decoded = MultisigProposal::try_deserialize(&mut bytes.as_slice()).unwrap();
+    assert_eq!(decoded.data, vec![7; MAX_PROPOSAL_DATA_LEN]);
+    assert_eq!(decoded.bump, 0);
+    assert_eq!(decoded.version, 0);
+}
+
+// Borsh pool data in the original layout: discriminator, authority, mint, vault,
+// lock duration, total staked and the three flags
+fn legacy_pool_account(total_staked: u64) -> Vec<u8> {
+    let mut bytes = vec![0; 8];
+    bytes.extend_from_slice(Pubkey::new_from_array([1; 32]).as_ref());
+    bytes.extend_from_slice(Pubkey::new_from_array([2; 32]).as_ref());
+    bytes.extend_from_slice(Pubkey::new_from_array([3; 32]).as_ref());
+    bytes.extend_from_slice(&DEFAULT_LOCK_DURATION_SLOTS.to_le_bytes());
+    bytes.extend_from_slice(&total_staked.to_le_bytes());
+    bytes.extend_from_slice(&[1, 0, 254]);
+    assert_eq!(bytes.len(), 8 + StakePool::LEGACY_LEN);
+    bytes
+}
+
+#[test]
+fn legacy_pools_keep_their_state_in_the_zero_copy_layout() {
+    let old = StakePoolV1::decode(&legacy_pool_account(5_000)).unwrap();
+    let stake_pool = old.upgrade();
+
+    assert_eq!(old.version, 0);
+    assert_eq!(stake_pool.authority, Pubkey::new_from_array([1; 32]));
+    assert_eq!(stake_pool.token_mint, Pubkey::new_from_array([2; 32]));
+    assert_eq!(stake_pool.token_vault, Pubkey::new_from_array([3; 32]));
+    assert_eq!(stake_pool.lock_duration_slots, DEFAULT_LOCK_DURATION_SLOTS);
+    assert_eq!(stake_pool.total_staked, 5_000);
+    assert_eq!((stake_pool.emergency_mode, stake_pool.reentrancy_guard, stake_pool.bump), (1, 0, 254));
+    assert_eq!(stake_pool.version, StakePool::VERSION);
+    assert_eq!(stake_pool.event_seq, 0);
+}
+
+#[test]
+fn version_1_pools_keep_every_field_in_the_zero_copy_layout() {
+    let limits = PoolLimits { min_stake: 7, ..PoolLimits::default() };
+    let mut bytes = legacy_pool_account(5_000);
+    bytes.extend_from_slice(Pubkey::new_from_array([4; 32]).as_ref());
+    bytes.push(6);
+    bytes.extend(LockClock::UnixTimestamp.try_to_vec().unwrap());
+    bytes.extend_from_slice(&3_600u64.to_le_bytes());
+    bytes.extend_from_slice(&2u64.to_le_bytes());
+    bytes.extend(limits.try_to_vec().unwrap());
+    bytes.push(1);
+    for field in [500u64, 9, 400] {
+        bytes.extend_from_slice(&field.to_le_bytes());
+    }
+    bytes.extend_from_slice(&1_000u16.to_le_bytes());
+    for field in [1_000u64, 10, 300] {
+        bytes.extend_from_slice(&field.to_le_bytes());
+    }
+    bytes.push(1);
+    bytes.extend_from_slice(&25u64.to_le_bytes());
+    bytes.push(1);
+    bytes.resize(8 + StakePoolV1::LEN, 0);
+
+    let stake_pool = StakePoolV1::decode(&bytes).unwrap().upgrade();
+    assert_eq!(stake_pool.token_program, Pubkey::new_from_array([4; 32]));
+    assert_eq!(stake_pool.decimals, 6);
+    assert_eq!(stake_pool.lock_clock, LockClock::UnixTimestamp as u8);
+    assert_eq!((stake_pool.lock_duration_seconds, stake_pool.pool_id), (3_600, 2));
+    assert!(stake_pool.limits == limits);
+    assert_eq!(stake_pool.require_transfer_consent, 1);
+    assert_eq!((stake_pool.outflow_cap, stake_pool.outflow_queue_epoch, stake_pool.outflow_queue_used), (500, 9, 400));
+    assert_eq!(stake_pool.breaker_threshold_bps, 1_000);
+    assert_eq!(
+        (stake_pool.breaker_window_slots, stake_pool.breaker_window_start, stake_pool.breaker_window_outflow),
+        (1_000, 10, 300)
+    );
+    assert_eq!((stake_pool.breaker_tripped, stake_pool.surplus), (1, 25));
+    assert_eq!(stake_pool.version, StakePool::VERSION);
+}
+
+#[test]
+fn version_2_pools_grow_without_moving_a_field() {
+    let mut stake_pool = breaker_pool();
+    stake_pool.total_staked = 5_000;
+    stake_pool.event_seq = 42;
+    stake_pool.breaker_buckets = [1, 2, 3, 4, 5];
+    stake_pool.version = 2;
+
+    let mut bytes = bytemuck::bytes_of(&stake_pool)[..StakePool::V2_LEN].to_vec();
+    bytes.resize(StakePool::LEN, 0);
+    let grown: StakePool = bytemuck::pod_read_unaligned(&bytes);
+    assert_eq!(bytemuck::bytes_of(&grown), bytemuck::bytes_of(&stake_pool));
+}
+
+// A version 1 position: the payout account, tier table revision and the wider
+// reserved tail did not exist, and the account was 60 bytes shorter
+fn version_1_position_account(auto_withdraw: bool) -> Vec<u8> {
+    let mut user_stake = position(5_000);
+    user_stake.owner = Pubkey::new_from_array([9; 32]);
+    user_stake.auto_withdraw = auto_withdraw;
+    user_stake.keeper_tip_bps = 50;
+    user_stake.version = 1;
+
+    let mut bytes = Vec::new();
+    user_stake.try_serialize(&mut bytes).unwrap();
+    bytes.truncate(8 + UserStake::LEN - 60);
+    bytes
+}
+
+#[test]
+fn version_1_positions_decode_once_grown() {
+    let mut bytes = version_1_position_account(false);
+    assert!(UserStake::try_deserialize(&mut bytes.as_slice()).is_err());
+    bytes.resize(8 + UserStake::LEN, 0);
+
+    let mut user_stake = UserStake::try_deserialize(&mut bytes.as_slice()).unwrap();
+    assert_eq!(user_stake.staked_amount, 5_000);
+    assert_eq!(user_stake.keeper_tip_bps, 50);
+    assert_eq!(user_stake.migrate(&pool()), 1);
+    assert_eq!(user_stake.version, UserStake::VERSION);
+    assert_eq!(user_stake.payout_token_account, Pubkey::default());
+}
+
+#[test]
+fn opted_in_positions_pay_the_owner_token_account_after_migration() {
+    let mut bytes = version_1_position_account(true);
+    bytes.resize(8 + UserStake::LEN, 0);
+    let mut stake_pool = pool();
+    stake_pool.token_mint = Pubkey::new_unique();
+    stake_pool.token_program = token_interface::TOKEN_PROGRAM_ID;
+
+    let mut user_stake = UserStake::try_deserialize(&mut bytes.as_slice()).unwrap();
+    user_stake.migrate(&stake_pool);
+    assert_eq!(
+        user_stake.payout_token_account,
+        token_interface::associated_token_address(
+            &user_stake.owner,
+            &stake_pool.token_mint,
+            &token_interface::TOKEN_PROGRAM_ID
+        )
+    );
+}
+
+#[test]
+fn limits_scale_with_the_mint_decimals() {
+    let mut pool = pool();
+    pool.decimals = 6;
+    pool.check_limits().unwrap();
+    let min_stake = MIN_STAKE_TOKENS * 1_000_000;
+    assert_eq!(pool.min_stake_amount().unwrap(), min_stake);
+    assert_eq!(pool.max_pool_size().unwrap(), MAX_POOL_SIZE_TOKENS * 1_000_000);
+
+    let user_stake = position(0);
+    let err = pool.check_stake(&user_stake, min_stake - 1).unwrap_err();
+    assert_eq!(err, StakeError::BelowMinimumStakeAmount.into());
+    pool.check_stake(&user_stake, min_stake).unwrap();
+}
+
+#[test]
+fn limits_that_overflow_base_units_are_refused() {
+    // 900,000,000 tokens fit in a u64 at 9 decimals but not at 11
+    let mut pool = pool();
+    pool.decimals = 9;
+    pool.check_limits().unwrap();
+    pool.decimals = 11;
+    assert_eq!(pool.check_limits().unwrap_err(), StakeError::UnsupportedMintDecimals.into());
+
+    pool.decimals = 0;
+    pool.limits.min_stake = 0;
+    assert_eq!(pool.check_limits().unwrap_err(), StakeError::InvalidPoolLimits.into());
+}
+
+fn pool_address(mint: &Pubkey, pool_id: u64) -> Pubkey {
+    let seeds: &[&[u8]] = &[b"stake_pool", mint.as_ref(), &StakePool::pool_id_seed(pool_id)];
+    Pubkey::find_program_address(seeds, &ID).0
+}
+
+#[test]
+fn pool_zero_keeps_the_original_address() {
+    let mint = Pubkey::new_unique();
+    let original = Pubkey::find_program_address(&[b"stake_pool", mint.as_ref()], &ID).0;
+    assert_eq!(pool_address(&mint, 0), original);
+
+    // Other ids get their own pool under the same mint
+    assert_eq!(StakePool::pool_id_seed(7), 7u64.to_le_bytes());
+    assert_ne!(pool_address(&mint, 1), original);
+    assert_ne!(pool_address(&mint, 1), pool_address(&mint, 2));
+}
+
+#[test]
+fn registries_list_each_pool_once_up_to_the_limit() {
+    let mut registry = PoolRegistry { token_mint: Pubkey::new_unique(), pools: Vec::new(), bump: 0 };
+    let first = Pubkey::new_unique();
+    registry.register(first).unwrap();
+    assert_eq!(registry.register(first).unwrap_err(), StakeError::PoolAlreadyRegistered.into());
+
+    for _ in 1..MAX_POOLS_PER_MINT {
+        registry.register(Pubkey::new_unique()).unwrap();
+    }
+    let err = registry.register(Pubkey::new_unique()).unwrap_err();
+    assert_eq!(err, StakeError::PoolRegistryFull.into());
+    assert_eq!(registry.pools.len(), MAX_POOLS_PER_MINT);
+}
+
+fn wall_clock(slot: u64, unix_timestamp: i64) -> Clock {
+    Clock { slot, unix_timestamp, ..Clock::default() }
+}
+
+#[test]
+fn unix_timestamp_pools_unlock_by_wall_clock_time() {
+    let mut pool = pool();
+    pool.lock_clock = LockClock::UnixTimestamp as u8;
+    pool.lock_duration_seconds = 1_000;
+    let mut user_stake = position(20_000);
+
+    pool.reserve_withdrawal(&mut user_stake, &wall_clock(100, 50_000)).unwrap();
+    assert_eq!(user_stake.unlock_timestamp, 51_000);
+
+    // Slots racing ahead do not unlock the position; only the timestamp counts
+    let err = pool.check_withdrawal(&user_stake, &wall_clock(u64::MAX, 50_999)).unwrap_err();
+    assert_eq!(err, StakeError::TokensStillLocked.into());
+    assert!(pool.is_unlocked(&user_stake, &wall_clock(101, 51_000)));
+    assert_eq!(pool.check_withdrawal(&user_stake, &wall_clock(101, 51_000)).unwrap(), 20_000);
+}
+
+#[test]
+fn slot_pools_ignore_the_wall_clock() {
+    let pool = pool();
+    let mut user_stake = position(20_000);
+    user_stake.unlock_slot = 1_000;
+    user_stake.unlock_timestamp = 1_000;
+
+    assert!(!pool.is_unlocked(&user_stake, &wall_clock(999, i64::MAX)));
+    assert!(pool.is_unlocked(&user_stake, &wall_clock(1_000, 0)));
+    assert_eq!(pool.clock_now(&wall_clock(1_000, 0)), 1_000);
+}
+
+#[test]
+fn only_empty_positions_can_be_closed() {
+    position(0).check_close().unwrap();
+
+    let err = position(1).check_close().unwrap_err();
+    assert_eq!(err, StakeError::StakeAccountNotEmpty.into());
+
+    // A pending request keeps an emptied position open until it is settled
+    let mut user_stake = position(20_000);
+    user_stake.withdrawal_requested = true;
+    user_stake.staked_amount = 0;
+    let err = user_stake.check_close().unwrap_err();
+    assert_eq!(err, StakeError::WithdrawalAlreadyRequested.into());
+    user_stake.clear();
+    user_stake.check_close().unwrap();
+}
+
+// Token-2022 mint data: base mint padded to a token account, account type, then TLV entries
+fn mint_data(extensions: &[(u16, usize)]) -> Vec<u8> {
+    let mut data = vec![0u8; 165];
+    data.push(1);
+    for &(extension_type, length) in extensions {
+        data.extend_from_slice(&extension_type.to_le_bytes());
+        data.extend_from_slice(&(length as u16).to_le_bytes());
+        data.resize(data.len() + length, 0);
+    }
+    data
+}
+
+#[test]
+fn transfer_hook_mints_are_detected() {
+    // Transfer fee config (1) alone is supported
+    assert!(!token_interface::has_transfer_hook(&mint_data(&[(1, 108)])));
+    // The hook may follow other extensions
+    assert!(token_interface::has_transfer_hook(&mint_data(&[(1, 108), (14, 64)])));
+    // A legacy-sized mint has no extensions
+    assert!(!token_interface::has_transfer_hook(&[0u8; 82]));
+}
+
+#[test]
+fn stake_is_refused_while_a_request_holds_queue_capacity() {
+    let mut pool = pool();
+    pool.outflow_cap = 25_000;
+    let mut user_stake = position(20_000);
+    let now = clock(1_000);
+
+    pool.reserve_withdrawal(&mut user_stake, &now).unwrap();
+    let (epoch, used) = (pool.outflow_queue_epoch, pool.outflow_queue_used);
+    assert_eq!(used, 20_000);
+
+    // Topping up would silently cancel the request and orphan its reservation
+    let err = pool.check_stake(&user_stake, MIN_STAKE_TOKENS).unwrap_err();
+    assert_eq!(err, StakeError::WithdrawalAlreadyRequested.into());
+
+    // So the request -> stake -> request loop cannot push the queue out
+    assert_eq!((pool.outflow_queue_epoch, pool.outflow_queue_used), (epoch, used));
+
+    // Once withdrawn, the position can stake and request again
+    user_stake.settle_withdrawal(20_000).unwrap();
+    pool.check_stake(&user_stake, MIN_STAKE_TOKENS).unwrap();
+}
+
+#[test]
+fn pools_without_a_cap_let_the_owner_stake_over_a_request() {
+    let mut pool = pool();
+    let mut user_stake = position(20_000);
+
+    pool.reserve_withdrawal(&mut user_stake, &clock(1_000)).unwrap();
+    pool.check_stake(&user_stake, MIN_STAKE_TOKENS).unwrap();
+
+    // `stake` drops the request before crediting the new tokens
+    user_stake.clear_request();
+    assert!(!user_stake.withdrawal_requested);
+    assert_eq!((user_stake.unlock_slot, user_stake.withdrawal_reserved), (0, 0));
+}
+
+#[test]
+fn funders_cannot_stake_over_a_beneficiary_request() {
+    let mut pool = pool();
+    let mut user_stake = position(20_000);
+    pool.check_stake_for(&user_stake, MIN_STAKE_TOKENS).unwrap();
+
+    // Even where the owner could, a funder must not cancel the beneficiary's request
+    pool.reserve_withdrawal(&mut user_stake, &clock(1_000)).unwrap();
+    pool.check_stake(&user_stake, MIN_STAKE_TOKENS).unwrap();
+    let err = pool.check_stake_for(&user_stake, MIN_STAKE_TOKENS).unwrap_err();
+    assert_eq!(err, StakeError::WithdrawalAlreadyRequested.into());
+}
+
+#[test]
+fn transfers_need_the_recipient_signature_when_the_pool_requires_consent() {
+    let mut pool = pool();
+    let (from, to) = (position(20_000), position(0));
+    pool.check_transfer(&from, &to, false).unwrap();
+
+    pool.require_transfer_consent = 1;
+    let err = pool.check_transfer(&from, &to, false).unwrap_err();
+    assert_eq!(err, StakeError::RecipientConsentRequired.into());
+    pool.check_transfer(&from, &to, true).unwrap();
+
+    // Positions are never merged
+    let err = pool.check_transfer(&from, &position(1), true).unwrap_err();
+    assert_eq!(err, StakeError::StakeAccountNotEmpty.into());
+}
+
+#[test]
+fn transfers_move_the_whole_position() {
+    let mut from = position(20_000);
+    from.lock_end = 5_000;
+    from.vesting = VestingSchedule { total: 10_000, start: 0, cliff: 0, end: 4_000 };
+    let mut to = position(0);
+
+    from.transfer_to(&mut to);
+    assert_eq!((to.staked_amount, to.lock_end, to.vesting.total), (20_000, 5_000, 10_000));
+    assert_eq!((from.staked_amount, from.lock_end, from.vesting.total), (0, 0, 0));
+    from.check_close().unwrap();
+}
+
+#[test]
+fn lock_ends_only_move_later() {
+    let pool = pool();
+    let mut user_stake = position(20_000);
+    let now = clock(1_000);
+
+    assert_eq!(pool.extend_lock(&mut user_stake, 5_000, &now).unwrap(), 0);
+    assert_eq!(pool.extend_lock(&mut user_stake, 6_000, &now).unwrap(), 5_000);
+    for earlier in [6_000, 5_999, 1_000] {
+        let err = pool.extend_lock(&mut user_stake, earlier, &now).unwrap_err();
+        assert_eq!(err, StakeError::LockEndNotExtended.into());
+    }
+    assert_eq!(user_stake.lock_end, 6_000);
+
+    let too_far = 1_000 + MAX_LOCK_DURATION_SLOTS + 1;
+    let err = pool.extend_lock(&mut user_stake, too_far, &now).unwrap_err();
+    assert_eq!(err, StakeError::ExcessiveLockDuration.into());
+}
+
+#[test]
+fn lock_ends_in_the_past_must_be_extended_past_now() {
+    let pool = pool();
+    let mut user_stake = position(20_000);
+    user_stake.lock_end = 500;
+
+    let err = pool.extend_lock(&mut user_stake, 900, &clock(1_000)).unwrap_err();
+    assert_eq!(err, StakeError::LockEndNotExtended.into());
+    pool.extend_lock(&mut user_stake, 1_001, &clock(1_000)).unwrap();
+}
+
+#[test]
+fn vesting_grants_need_the_beneficiary_signature() {
+    let pool = pool();
+    let schedule = VestingSchedule { total: 0, start: 1_000, cliff: 2_000, end: 5_000 };
+    let err = pool.check_vesting_grant(&position(0), &schedule, 1_000, false).unwrap_err();
+    assert_eq!(err, StakeError::BeneficiaryConsentRequired.into());
+    pool.check_vesting_grant(&position(0), &schedule, 1_000, true).unwrap();
+
+    // A schedule still vesting blocks the next grant
+    let mut user_stake = position(40_000);
+    user_stake.vesting = VestingSchedule { total: 40_000, ..schedule };
+    let err = pool.check_vesting_grant(&user_stake, &schedule, 4_999, true).unwrap_err();
+    assert_eq!(err, StakeError::VestingAlreadyActive.into());
+    pool.check_vesting_grant(&user_stake, &schedule, 5_000, true).unwrap();
+
+    let inverted = VestingSchedule { cliff: 6_000, ..schedule };
+    let err = pool.check_vesting_grant(&position(0), &inverted, 1_000, true).unwrap_err();
+    assert_eq!(err, StakeError::InvalidVestingSchedule.into());
+}
+
+#[test]
+fn vested_tokens_are_released_in_parts() {
+    let mut pool = pool();
+    let lock = pool.lock_duration_slots;
+    let mut user_stake = position(40_000);
+    user_stake.vesting = VestingSchedule { total: 40_000, start: 0, cliff: lock, end: 8 * lock };
+
+    // Nothing before the cliff, then linear
+    assert_eq!(user_stake.vesting.vested(lock - 1), 0);
+    assert_eq!(user_stake.vesting.vested(2 * lock), 10_000);
+
+    // The first request releases what has vested and leaves the rest staked
+    pool.reserve_withdrawal(&mut user_stake, &clock(lock)).unwrap();
+    let amount = pool.check_withdrawal(&user_stake, &clock(2 * lock)).unwrap();
+    assert_eq!(amount, 10_000);
+    user_stake.settle_withdrawal(amount).unwrap();
+    assert_eq!(user_stake.staked_amount, 30_000);
+    assert!(!user_stake.withdrawal_requested);
+    assert_eq!(user_stake.withdrawable_at(2 * lock), 0);
+
+    // What vests later needs a new request
+    pool.reserve_withdrawal(&mut user_stake, &clock(3 * lock)).unwrap();
+    assert_eq!(user_stake.withdrawal_reserved, 10_000);
+    assert_eq!(pool.check_withdrawal(&user_stake, &clock(8 * lock)).unwrap(), 10_000);
+}
+
+#[test]
+fn requests_reserve_what_vests_by_the_unlock_point() {
+    let mut pool = pool();
+    pool.outflow_cap = 1_000_000;
+    let lock = pool.lock_duration_slots;
+    let mut user_stake = position(40_000);
+    user_stake.vesting = VestingSchedule { total: 40_000, start: 0, cliff: 0, end: 4 * lock };
+
+    // A quarter has vested at the request, half at the unlock point
+    let queued = pool.reserve_withdrawal(&mut user_stake, &clock(lock)).unwrap();
+    assert!(!queued);
+    assert_eq!(user_stake.unlock_slot, 2 * lock);
+    assert_eq!(user_stake.withdrawal_reserved, 20_000);
+    assert_eq!(pool.outflow_queue_used, 20_000);
+
+    let err = pool.check_withdrawal(&user_stake, &clock(2 * lock - 1)).unwrap_err();
+    assert_eq!(err, StakeError::TokensStillLocked.into());
+
+    // Withdrawing late pays no more than the reservation
+    assert_eq!(user_stake.withdrawable_amount(&pool, &clock(3 * lock)), 30_000);
+    assert_eq!(pool.check_withdrawal(&user_stake, &clock(3 * lock)).unwrap(), 20_000);
+}
+
+#[test]
+fn requests_are_booked_at_the_position_lock_end() {
+    let mut pool = pool();
+    pool.outflow_cap = 1_000_000;
+    let lock = pool.lock_duration_slots;
+    let mut user_stake = position(20_000);
+    user_stake.lock_end = 1_000 + 3 * lock;
+
+    pool.reserve_withdrawal(&mut user_stake, &clock(1_000)).unwrap();
+    assert_eq!(user_stake.unlock_slot, user_stake.lock_end);
+    assert_eq!(pool.outflow_queue_epoch, user_stake.lock_end / pool.outflow_epoch_length());
+}
+
+#[test]
+fn requests_with_nothing_vested_by_the_unlock_point_are_refused() {
+    let mut pool = pool();
+    let lock = pool.lock_duration_slots;
+    let mut user_stake = position(40_000);
+    user_stake.vesting = VestingSchedule { total: 40_000, start: 0, cliff: 10 * lock, end: 20 * lock };
+
+    let err = pool.reserve_withdrawal(&mut user_stake, &clock(1_000)).unwrap_err();
+    assert_eq!(err, StakeError::NothingVested.into());
+    assert!(!user_stake.withdrawal_requested);
+}
+
+#[test]
+fn position_status_counts_down_to_the_unlock_point() {
+    let mut pool = pool();
+    pool.outflow_cap = 1_000_000;
+    let lock = pool.lock_duration_slots;
+    let mut user_stake = position(10_000);
+
+    let idle = pool.position_status(&user_stake, &clock(100));
+    assert!(!idle.withdrawal_requested && !idle.unlocked);
+    assert_eq!((idle.slots_until_unlock, idle.seconds_until_unlock, idle.withdrawable_amount), (0, 0, 0));
+
+    pool.reserve_withdrawal(&mut user_stake, &clock(100)).unwrap();
+    let pending = pool.position_status(&user_stake, &clock(100 + lock - 10));
+    assert!(pending.withdrawal_requested && !pending.unlocked);
+    assert_eq!(pending.slots_until_unlock, 10);
+    assert_eq!(pending.seconds_until_unlock, StakePool::slots_to_seconds(10));
+    assert_eq!(pending.withdrawable_amount, 0);
+
+    let matured = pool.position_status(&user_stake, &clock(100 + lock));
+    assert!(matured.unlocked);
+    assert_eq!((matured.slots_until_unlock, matured.seconds_until_unlock), (0, 0));
+    assert_eq!(matured.withdrawable_amount, pool.check_withdrawal(&user_stake, &clock(100 + lock)).unwrap());
+}
+
+#[test]
+fn position_status_counts_wall_clock_seconds_on_timestamp_pools() {
+    let mut pool = pool();
+    pool.lock_clock = LockClock::UnixTimestamp as u8;
+    pool.lock_duration_seconds = 1_000;
+    let mut user_stake = position(20_000);
+    pool.reserve_withdrawal(&mut user_stake, &wall_clock(100, 50_000)).unwrap();
+
+    let status = pool.position_status(&user_stake, &wall_clock(u64::MAX, 50_400));
+    assert!(!status.unlocked);
+    assert_eq!(status.seconds_until_unlock, 600);
+    assert_eq!(status.slots_until_unlock, StakePool::seconds_to_slots(600));
+}
+
+#[test]
+fn position_status_pays_nothing_while_the_breaker_is_tripped() {
+    let mut pool = pool();
+    let mut user_stake = position(10_000);
+    pool.reserve_withdrawal(&mut user_stake, &clock(0)).unwrap();
+    let matured = clock(pool.lock_duration_slots);
+    assert_eq!(pool.position_status(&user_stake, &matured).withdrawable_amount, 10_000);
+
+    pool.breaker_tripped = 1;
+    let status = pool.position_status(&user_stake, &matured);
+    assert!(status.unlocked);
+    assert_eq!(status.withdrawable_amount, 0);
+}
+
+#[test]
+fn pool_stats_report_limits_in_base_units() {
+    let mut pool = pool();
+    pool.decimals = 6;
+    pool.total_staked = 1_000_000;
+    pool.surplus = 25;
+    let address = Pubkey::new_unique();
+
+    let stats = pool.stats(address).unwrap();
+    assert_eq!(stats.stake_pool, address);
+    assert_eq!(stats.min_stake, pool.min_stake_amount().unwrap());
+    assert_eq!(stats.max_pool_size, MAX_POOL_SIZE_TOKENS * 1_000_000);
+    assert_eq!(stats.remaining_capacity, stats.max_pool_size - 1_000_000);
+    assert_eq!(stats.surplus, 25);
+    assert!(!stats.emergency_mode && !stats.breaker_tripped);
+}
+
+#[test]
+fn keeper_tips_are_capped_and_cleared_with_the_opt_in() {
+    let mut user_stake = position(10_000);
+    let payout = Pubkey::new_unique();
+
+    let err = user_stake.set_auto_withdraw(true, MAX_KEEPER_TIP_BPS + 1, payout).unwrap_err();
+    assert_eq!(err, StakeError::ExcessiveKeeperTip.into());
+    assert!(!user_stake.auto_withdraw);
+
+    user_stake.set_auto_withdraw(true, MAX_KEEPER_TIP_BPS, payout).unwrap();
+    assert_eq!(user_stake.payout_token_account, payout);
+
+    user_stake.set_auto_withdraw(false, 0, payout).unwrap();
+    assert!(!user_stake.auto_withdraw);
+    assert_eq!(user_stake.payout_token_account, Pubkey::default());
+}
+
+#[test]
+fn keeper_tips_round_down_and_the_owner_gets_the_rest() {
+    let mut user_stake = position(10_000);
+    user_stake.set_auto_withdraw(true, MAX_KEEPER_TIP_BPS, Pubkey::new_unique()).unwrap();
+    assert_eq!(user_stake.split_keeper_tip(10_000), (9_900, 100));
+    assert_eq!(user_stake.split_keeper_tip(199), (198, 1));
+    assert_eq!(user_stake.split_keeper_tip(99), (99, 0));
+    assert_eq!(user_stake.split_keeper_tip(u64::MAX), (u64::MAX - u64::MAX / 100, u64::MAX / 100));
+
+    user_stake.keeper_tip_bps = 0;
+    assert_eq!(user_stake.split_keeper_tip(10_000), (10_000, 0));
+}
+
+#[test]
+fn only_opted_in_positions_can_be_cranked() {
+    let mut pool = pool();
+    let mut user_stake = position(10_000);
+    pool.reserve_withdrawal(&mut user_stake, &clock(0)).unwrap();
+    let matured = clock(pool.lock_duration_slots);
+
+    let err = pool.check_crank(&user_stake, &matured).unwrap_err();
+    assert_eq!(err, StakeError::AutoWithdrawNotEnabled.into());
+
+    user_stake.set_auto_withdraw(true, 50, Pubkey::new_unique()).unwrap();
+    let err = pool.check_crank(&user_stake, &clock(pool.lock_duration_slots - 1)).unwrap_err();
+    assert_eq!(err, StakeError::TokensStillLocked.into());
+    assert_eq!(pool.check_crank(&user_stake, &matured).unwrap(), 10_000);
+}
+
+#[test]
+fn sync_vault_credits_only_the_balance_above_the_principal() {
+    let mut pool = pool();
+    pool.total_staked = 1_000;
+
+    assert_eq!(pool.reconcile_vault(1_500), 0);
+    assert_eq!(pool.surplus, 500);
+
+    // A shortfall is reported and leaves nothing to withdraw
+    assert_eq!(pool.reconcile_vault(800), 200);
+    assert_eq!(pool.surplus, 0);
+}
+
+#[test]
+fn admin_withdrawals_take_only_the_surplus() {
+    let mut pool = pool();
+    pool.total_staked = 1_000;
+    pool.reconcile_vault(1_500);
+
+    let err = pool.check_surplus_withdrawal(501, 1_500).unwrap_err();
+    assert_eq!(err, StakeError::ExceedsSurplus.into());
+    pool.check_surplus_withdrawal(500, 1_500).unwrap();
+
+    // Donations not yet reconciled stay out of reach
+    let err = pool.check_surplus_withdrawal(600, 2_000).unwrap_err();
+    assert_eq!(err, StakeError::ExceedsSurplus.into());
+
+    pool.reconcile_vault(1_000);
+    let err = pool.check_surplus_withdrawal(1, 1_000).unwrap_err();
+    assert_eq!(err, StakeError::ExceedsSurplus.into());
+}
+
+#[test]
+fn rescues_never_touch_the_pool_mint_or_the_vault() {
+    let mut pool = pool();
+    pool.token_mint = Pubkey::new_unique();
+    pool.token_vault = Pubkey::new_unique();
+    let authority = Pubkey::new_unique();
+    let (source, foreign_mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+    pool.check_rescue_source(&source, &foreign_mint, &authority, &authority).unwrap();
+
+    // Any account of the staked mint is refused, not just the vault
+    let err = pool.check_rescue_source(&source, &pool.token_mint, &authority, &authority).unwrap_err();
+    assert_eq!(err, StakeError::InvalidRescueAccount.into());
+    let err = pool.check_rescue_source(&pool.token_vault, &foreign_mint, &authority, &authority).unwrap_err();
+    assert_eq!(err, StakeError::InvalidRescueAccount.into());
+
+    // Only accounts the pool PDA holds can be rescued
+    let err = pool.check_rescue_source(&source, &foreign_mint, &Pubkey::new_unique(), &authority).unwrap_err();
+    assert_eq!(err, StakeError::InvalidRescueAccount.into());
+}
+
+// Breaker at 10% of a 1,000,000 pool over a 1,000-slot window
+fn breaker_pool() -> StakePool {
+    let mut pool = pool();
+    pool.total_staked = 1_000_000;
+    pool.breaker_threshold_bps = 1_000;
+    pool.breaker_window_slots = 1_000;
+    pool
+}
+
+#[test]
+fn breaker_window_rolls_instead_of_resetting() {
+    let mut pool = breaker_pool();
+    let key = Pubkey::new_unique();
+
+    // Just under the threshold at the end of one window and again right after
+    // its boundary: a tumbling window would let ~2x through
+    pool.record_outflow(key, 99_999, &clock(10_990)).unwrap();
+    assert_eq!(pool.breaker_tripped, 0);
+    pool.record_outflow(key, 99_999, &clock(11_010)).unwrap();
+    assert_eq!(pool.breaker_tripped, 1);
+}
+
+#[test]
+fn breaker_forgets_outflows_older_than_the_window() {
+    let mut pool = breaker_pool();
+    let key = Pubkey::new_unique();
+
+    pool.record_outflow(key, 99_999, &clock(10_000)).unwrap();
+    pool.record_outflow(key, 99_999, &clock(11_250)).unwrap();
+    assert_eq!(pool.breaker_tripped, 0);
+    assert_eq!(pool.breaker_window_outflow, 99_999);
+}
+
+#[test]
+fn breaker_never_trips_on_an_empty_pool() {
+    let mut pool = breaker_pool();
+    pool.total_staked = 0;
+
+    pool.record_outflow(Pubkey::new_unique(), 1, &clock(10_000)).unwrap();
+    assert_eq!(pool.breaker_tripped, 0);
+}
+
+fn tier_table() -> TierTable {
+    TierTable {
+        stake_pool: Pubkey::default(),
+        tiers: vec![StakeTier { min_stake: 100, min_lock: 0 }, StakeTier { min_stake: 1_000, min_lock: 500 }],
+        bump: 0,
+        version: TierTable::VERSION,
+        revision: 0,
+        reserved: [0; 24],
+    }
+}
+
+#[test]
+fn partial_withdrawals_drop_the_cached_tier() {
+    let (table, pool) = (tier_table(), pool());
+    let mut user_stake = position(1_500);
+    user_stake.lock_end = 2_000;
+    user_stake.verify_tier(&table, &pool, &clock(1_000)).unwrap();
+    assert_eq!(user_stake.tier, 2);
+
+    user_stake.settle_withdrawal(600).unwrap();
+    assert_eq!(user_stake.staked_amount, 900);
+    assert_eq!(user_stake.tier, 0);
+    assert_eq!(user_stake.cached_tier(&table, &pool, &clock(1_000)), 0);
+}
+
+#[test]
+fn cached_tier_lapses_with_the_remaining_lock() {
+    let (table, pool) = (tier_table(), pool());
+    let mut user_stake = position(1_500);
+    user_stake.lock_end = 2_000;
+    user_stake.verify_tier(&table, &pool, &clock(1_000)).unwrap();
+    assert_eq!(user_stake.tier_valid_until, 1_500);
+
+    assert_eq!(user_stake.cached_tier(&table, &pool, &clock(1_500)), 2);
+    assert_eq!(table.tier_of(&pool, &user_stake, &clock(1_500)).unwrap(), 2);
+    assert_eq!(user_stake.cached_tier(&table, &pool, &clock(1_501)), 0);
+    assert_eq!(table.tier_of(&pool, &user_stake, &clock(1_501)).unwrap(), 1);
+
+    // A tier without a lock minimum never lapses on time alone
+    user_stake.staked_amount = 500;
+    user_stake.verify_tier(&table, &pool, &clock(1_000)).unwrap();
+    assert_eq!(user_stake.cached_tier(&table, &pool, &clock(u64::MAX)), 1);
+}
+
+#[test]
+fn cached_tier_lapses_when_the_tier_table_changes() {
+    let (mut table, pool) = (tier_table(), pool());
+    let mut user_stake = position(500);
+    user_stake.verify_tier(&table, &pool, &clock(1_000)).unwrap();
+    assert_eq!(user_stake.cached_tier(&table, &pool, &clock(u64::MAX)), 1);
+
+    // A SetTierTable raising the bar bumps the revision; the cached tier no longer counts
+    table.tiers[0].min_stake = 600;
+    table.revision += 1;
+    assert_eq!(user_stake.cached_tier(&table, &pool, &clock(1_000)), 0);
+
+    user_stake.verify_tier(&table, &pool, &clock(1_000)).unwrap();
+    assert_eq!((user_stake.tier, user_stake.tier_table_revision), (0, 1));
+}
+
+fn tier_data(tiers: &[(u64, u64)]) -> Vec<u8> {
+    let mut data = vec![tiers.len() as u8];
+    for (min_stake, min_lock) in tiers {
+        data.extend_from_slice(&min_stake.to_le_bytes());
+        data.extend_from_slice(&min_lock.to_le_bytes());
+    }
+    data
+}
+
+#[test]
+fn tier_minimums_must_fit_in_base_units() {
+    let mut pool = pool();
+    pool.decimals = 9;
+    let max_tokens = u64::MAX / 1_000_000_000;
+
+    let tiers = TierTable::parse_tiers(&tier_data(&[(100, 0), (max_tokens, 0)]), &pool).unwrap();
+    assert_eq!(tiers[1].min_stake, max_tokens);
+    assert!(TierTable::parse_tiers(&tier_data(&[(100, 0), (max_tokens + 1, 0)]), &pool).is_err());
+}
+
+fn checkpoints(slot: u64, amount: u64, complete: bool) -> StakeCheckpoints {
+    let mut checkpoints = StakeCheckpoints::zeroed();
+    checkpoints.init(Pubkey::new_unique(), 255, slot, amount, complete);
+    checkpoints
+}
+
+#[test]
+fn changes_in_one_slot_share_a_checkpoint() {
+    let mut history = checkpoints(10, 0, true);
+    history.record(20, 5);
+    history.record(20, 7);
+
+    assert_eq!(history.ring.len, 2);
+    assert_eq!(history.amount_at(5), Some(0));
+    assert_eq!(history.amount_at(19), Some(0));
+    assert_eq!(history.amount_at(20), Some(7));
+    assert_eq!(history.amount_at(u64::MAX), Some(7));
+}
+
+#[test]
+fn full_rings_overwrite_their_oldest_checkpoint() {
+    let mut history = checkpoints(0, 0, true);
+    for index in 1..=USER_CHECKPOINTS as u64 + 8 {
+        history.record(index * 10, index * 100);
+    }
+
+    // Slots 0..=80 were overwritten; the history now starts at slot 90
+    assert_eq!(history.ring.len as usize, USER_CHECKPOINTS);
+    assert_eq!(history.ring.complete, 0);
+    assert_eq!(history.amount_at(0), None);
+    assert_eq!(history.amount_at(89), None);
+    assert_eq!(history.amount_at(90), Some(900));
+    assert_eq!(history.amount_at(255), Some(2_500));
+    assert_eq!(history.amount_at(400), Some(4_000));
+    assert_eq!(history.amount_at(u64::MAX), Some(4_000));
+
+    let slots: Vec<u64> = history.ring.ordered(&history.entries).map(|checkpoint| checkpoint.slot).collect();
+    assert_eq!(slots, (9..=40).map(|index| index * 10).collect::<Vec<_>>());
+}
+
+#[test]
+fn histories_started_late_know_nothing_before_their_start() {
+    let history = checkpoints(100, 50, false);
+
+    assert_eq!(history.amount_at(99), None);
+    assert_eq!(history.amount_at(100), Some(50));
+}
@@ -0,0 +1,160 @@
+// Token program interface shared by the legacy SPL Token program and Token-2022.
+//
+// Anchor 0.26 only ships typed accounts for the legacy token program, so token
+// accounts and mints are passed as raw accounts and unpacked here. Both programs
+// share the same base account layout, Token-2022 only appends extensions.
+use anchor_lang::prelude::*;
+use solana_program::program::invoke_signed;
+use solana_program::program_pack::Pack;
+use spl_token_2022::extension::{BaseStateWithExtensions, ExtensionType, StateWithExtensions};
+use spl_token_2022::state::{Account as TokenAccountState, Mint as MintState};
+
+use crate::StakeError;
+
+pub use anchor_spl::token::ID as TOKEN_PROGRAM_ID;
+pub use spl_token_2022::ID as TOKEN_2022_PROGRAM_ID;
+
+/// Whether the key is either the legacy token program or Token-2022
+pub fn is_token_program(key: &Pubkey) -> bool {
+    *key == TOKEN_PROGRAM_ID || *key == TOKEN_2022_PROGRAM_ID
+}
+
+/// Associated token account of `owner` for `mint` under either token program
+pub fn associated_token_address(owner: &Pubkey, mint: &Pubkey, token_program: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[owner.as_ref(), token_program.as_ref(), mint.as_ref()],
+        &anchor_spl::associated_token::ID,
+    )
+    .0
+}
+
+/// Unpack the base state of a token account owned by either token program
+pub fn unpack_token_account(info: &AccountInfo) -> Result<TokenAccountState> {
+    require!(is_token_program(info.owner), StakeError::InvalidTokenProgram);
+    let data = info.try_borrow_data()?;
+    let state = StateWithExtensions::<TokenAccountState>::unpack(&data)?;
+    Ok(state.base)
+}
+
+/// Unpack the base state of a mint owned by either token program
+pub fn unpack_mint(info: &AccountInfo) -> Result<MintState> {
+    require!(is_token_program(info.owner), StakeError::InvalidTokenProgram);
+    let data = info.try_borrow_data()?;
+    let state = StateWithExtensions::<MintState>::unpack(&data)?;
+    Ok(state.base)
+}
+
+// Token-2022 extension type ids this program cannot serve. The transfer hook
+// needs extra accounts on every transfer, which `transfer_checked` never passes.
+const TRANSFER_HOOK_EXTENSION: u16 = 14;
+
+// Start of the extension TLV entries in a Token-2022 mint: the base mint padded
+// to the size of a token account, then the account type byte
+const MINT_TLV_START: usize = TokenAccountState::LEN + 1;
+
+/// Whether Token-2022 mint data carries the transfer-hook extension. The TLV
+/// entries are read directly, since this version of spl-token-2022 does not
+/// know the extension and fails to parse it.
+pub fn has_transfer_hook(data: &[u8]) -> bool {
+    let mut offset = MINT_TLV_START;
+    while let Some(header) = data.get(offset..offset + 4) {
+        let extension_type = u16::from_le_bytes([header[0], header[1]]);
+        let length = u16::from_le_bytes([header[2], header[3]]) as usize;
+        match extension_type {
+            0 => return false,
+            TRANSFER_HOOK_EXTENSION => return true,
+            _ => offset += 4 + length,
+        }
+    }
+    false
+}
+
+/// Reject mints whose extensions the pool cannot transfer
+pub fn check_mint_extensions(mint: &AccountInfo) -> Result<()> {
+    if *mint.owner == TOKEN_2022_PROGRAM_ID {
+        require!(!has_transfer_hook(&mint.try_borrow_data()?), StakeError::UnsupportedMintExtension);
+    }
+    Ok(())
+}
+
+/// Size of a token account for the given mint, including the account
+/// extensions required by the mint's extensions (e.g. transfer fees)
+pub fn token_account_len(mint: &AccountInfo) -> Result<usize> {
+    if *mint.owner == TOKEN_PROGRAM_ID {
+        return Ok(TokenAccountState::LEN);
+    }
+    require!(*mint.owner == TOKEN_2022_PROGRAM_ID, StakeError::InvalidTokenProgram);
+    let data = mint.try_borrow_data()?;
+    let state = StateWithExtensions::<MintState>::unpack(&data)?;
+    let mint_extensions = state.get_extension_types()?;
+    let account_extensions = ExtensionType::get_required_init_account_extensions(&mint_extensions);
+    Ok(ExtensionType::get_account_len::<TokenAccountState>(&account_extensions))
+}
+
+#[derive(Accounts)]
+pub struct TransferChecked<'info> {
+    /// CHECK: Validated by the token program
+    pub from: AccountInfo<'info>,
+    /// CHECK: Validated by the token program
+    pub mint: AccountInfo<'info>,
+    /// CHECK: Validated by the token program
+    pub to: AccountInfo<'info>,
+    /// CHECK: Validated by the token program
+    pub authority: AccountInfo<'info>,
+}
+
+/// `TransferChecked` CPI that works with both token programs
+pub fn transfer_checked<'info>(
+    ctx: CpiContext<'_, '_, '_, 'info, TransferChecked<'info>>,
+    amount: u64,
+    decimals: u8,
+) -> Result<()> {
+    let ix = spl_token_2022::instruction::transfer_checked(
+        ctx.program.key,
+        ctx.accounts.from.key,
+        ctx.accounts.mint.key,
+        ctx.accounts.to.key,
+        ctx.accounts.authority.key,
+        &[],
+        amount,
+        decimals,
+    )?;
+    invoke_signed(
+        &ix,
+        &[
+            ctx.accounts.from,
+            ctx.accounts.mint,
+            ctx.accounts.to,
+            ctx.accounts.authority,
+        ],
+        ctx.signer_seeds,
+    )
+    .map_err(Into::into)
+}
+
+#[derive(Accounts)]
+pub struct InitializeAccount3<'info> {
+    /// CHECK: Validated by the token program
+    pub account: AccountInfo<'info>,
+    /// CHECK: Validated by the token program
+    pub mint: AccountInfo<'info>,
+}
+
+/// `InitializeAccount3` CPI that works with both token programs
+pub fn initialize_account3<'info>(
+    ctx: CpiContext<'_, '_, '_, 'info, InitializeAccount3<'info>>,
+    owner: &Pubkey,
+) -> Result<()> {
+    let ix = spl_token_2022::instruction::initialize_account3(
+        ctx.program.key,
+        ctx.accounts.account.key,
+        ctx.accounts.mint.key,
+        owner,
+    )?;
+    invoke_signed(
+        &ix,
+        &[ctx.accounts.account, ctx.accounts.mint],
+        ctx.signer_seeds,
+    )
+    .map_err(Into::into)
+}
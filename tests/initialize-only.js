@@ -16,14 +16,48 @@ describe("chain-fox-dao-initialize", () => {
       program.programId
     );
     
+    // 计算代币的质押池注册表 PDA
+    const [poolRegistryPDA] = await PublicKey.findProgramAddress(
+      [Buffer.from("pool_registry"), new PublicKey("6trEEfEZ7LPuhFDjXt7jr4FubXXhovViKnfFrGUFNhG4").toBuffer()],
+      program.programId
+    );
+
+    try {
+      await program.methods
+        .initializePoolRegistry()
+        .accounts({
+          poolRegistry: poolRegistryPDA,
+          tokenMint: new PublicKey("6trEEfEZ7LPuhFDjXt7jr4FubXXhovViKnfFrGUFNhG4"),
+          payer: provider.wallet.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+
+      console.log("✅ 质押池注册表初始化成功");
+    } catch (error) {
+      if (error.message.includes("already in use")) {
+        console.log("✅ 质押池注册表已经初始化");
+      } else {
+        throw error;
+      }
+    }
+
+    // 计算质押池的余额检查点 PDA
+    const [poolCheckpointsPDA] = await PublicKey.findProgramAddress(
+      [Buffer.from("pool_checkpoints"), stakePoolPDA.toBuffer()],
+      program.programId
+    );
+
     // 创建代币金库
     const tokenVault = anchor.web3.Keypair.generate();
     
     try {
       await program.methods
-        .initialize(bump, null, null)
+        .initialize(bump, null, null, null, new anchor.BN(0), null)
         .accounts({
           stakePool: stakePoolPDA,
+          poolCheckpoints: poolCheckpointsPDA,
+          poolRegistry: poolRegistryPDA,
           tokenMint: new PublicKey("6trEEfEZ7LPuhFDjXt7jr4FubXXhovViKnfFrGUFNhG4"),
           tokenVault: tokenVault.publicKey,
           authority: provider.wallet.publicKey,